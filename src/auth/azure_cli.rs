@@ -0,0 +1,101 @@
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use tokio::process::Command;
+
+use super::{Token, TokenCache, TokenProvider};
+use crate::cloud::Cloud;
+
+#[cfg(windows)]
+const AZ_PROGRAM: &str = "az.cmd";
+#[cfg(not(windows))]
+const AZ_PROGRAM: &str = "az";
+
+/// Assumed lifetime when an older Azure CLI does not report `expires_on`.
+const DEFAULT_LIFETIME: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AzAccessToken {
+    access_token: String,
+    /// Expiry as a POSIX timestamp, reported by Azure CLI 2.54 and later.
+    #[serde(rename = "expires_on")]
+    expires_on: Option<u64>,
+}
+
+/// Token provider that borrows the Azure CLI's signed-in session via
+/// `az account get-access-token`.
+pub struct AzureCliToken {
+    cloud: Cloud,
+    tenant: Option<String>,
+    token: TokenCache,
+}
+
+impl AzureCliToken {
+    /// Uses the CLI's current subscription tenant unless `tenant` is given.
+    /// The CLI must be signed in to `cloud` (`az cloud set`).
+    pub fn new(cloud: Cloud, tenant: Option<String>) -> Self {
+        Self {
+            cloud,
+            tenant,
+            token: TokenCache::default(),
+        }
+    }
+
+    async fn acquire_token(&self) -> anyhow::Result<Token> {
+        let mut command = Command::new(AZ_PROGRAM);
+        command.args([
+            "account",
+            "get-access-token",
+            "--resource",
+            self.cloud.graph_host(),
+            "--output",
+            "json",
+        ]);
+        if let Some(tenant) = &self.tenant {
+            command.args(["--tenant", tenant]);
+        }
+
+        let output = command
+            .output()
+            .await
+            .map_err(|e| anyhow::anyhow!("running `{}`; {}", AZ_PROGRAM, e))?;
+
+        if !output.status.success() {
+            anyhow::bail!(
+                "az account get-access-token; {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+        }
+
+        let az_token: AzAccessToken = serde_json::from_slice(&output.stdout)?;
+        let lifetime = match az_token.expires_on {
+            Some(expires_on) => {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+                Duration::from_secs(expires_on.saturating_sub(now))
+            }
+            None => DEFAULT_LIFETIME,
+        };
+
+        Ok(Token {
+            access_token: az_token.access_token,
+            refresh_token: None,
+            expires_at: Instant::now() + lifetime,
+        })
+    }
+}
+
+#[async_trait]
+impl TokenProvider for AzureCliToken {
+    async fn access_token(&self) -> anyhow::Result<String> {
+        self.token.get_or_acquire(|| self.acquire_token()).await
+    }
+
+    async fn refresh(&self, rejected: &str) -> anyhow::Result<String> {
+        self.token
+            .reacquire(rejected, || self.acquire_token())
+            .await
+    }
+}
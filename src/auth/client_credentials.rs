@@ -0,0 +1,167 @@
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, Private};
+use openssl::sign::Signer;
+use openssl::x509::X509;
+use reqwest::Client;
+use serde_json::json;
+
+use super::{token_endpoint, Token, TokenCache, TokenErrorResponse, TokenProvider, TokenResponse};
+use crate::cloud::Cloud;
+
+const CLIENT_ASSERTION_TYPE: &str = "urn:ietf:params:oauth:client-assertion-type:jwt-bearer";
+
+/// Lifetime of the signed client assertion.
+const ASSERTION_LIFETIME_SECS: u64 = 10 * 60;
+
+/// How the application proves its identity.
+pub enum ClientCredential {
+    Secret(String),
+    Certificate {
+        certificate: X509,
+        private_key: PKey<Private>,
+    },
+}
+
+impl ClientCredential {
+    /// Loads a certificate and its private key from a single PEM file.
+    pub fn from_pem_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let pem = std::fs::read(path)
+            .map_err(|e| anyhow::anyhow!("reading {}; {}", path.display(), e))?;
+        Ok(Self::Certificate {
+            certificate: X509::from_pem(&pem)?,
+            private_key: PKey::private_key_from_pem(&pem)?,
+        })
+    }
+}
+
+/// App-only token provider using the OAuth2 client credentials grant.
+///
+/// The token is cached and re-acquired once it is about to expire.
+pub struct ClientCredentials {
+    client: Client,
+    cloud: Cloud,
+    tenant: String,
+    client_id: String,
+    credential: ClientCredential,
+    token: TokenCache,
+}
+
+impl ClientCredentials {
+    pub fn new(
+        client: Client,
+        cloud: Cloud,
+        tenant: impl Into<String>,
+        client_id: impl Into<String>,
+        credential: ClientCredential,
+    ) -> Self {
+        Self {
+            client,
+            cloud,
+            tenant: tenant.into(),
+            client_id: client_id.into(),
+            credential,
+            token: TokenCache::default(),
+        }
+    }
+
+    async fn acquire_token(&self) -> anyhow::Result<Token> {
+        let mut form = vec![
+            ("grant_type", "client_credentials".to_string()),
+            ("client_id", self.client_id.clone()),
+            ("scope", self.cloud.app_only_scope()),
+        ];
+        match &self.credential {
+            ClientCredential::Secret(secret) => form.push(("client_secret", secret.clone())),
+            ClientCredential::Certificate {
+                certificate,
+                private_key,
+            } => {
+                form.push(("client_assertion_type", CLIENT_ASSERTION_TYPE.to_string()));
+                form.push((
+                    "client_assertion",
+                    self.client_assertion(certificate, private_key)?,
+                ));
+            }
+        }
+
+        let response = self
+            .client
+            .post(token_endpoint(self.cloud, &self.tenant))
+            .form(&form)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let error: TokenErrorResponse = response.json().await?;
+            anyhow::bail!(
+                "acquiring app-only token; {}: {}",
+                error.error,
+                error.error_description.unwrap_or_default()
+            )
+        }
+
+        let token: TokenResponse = response.json().await?;
+        Ok(token.into())
+    }
+
+    /// Builds the RS256-signed JWT used in place of a client secret.
+    fn client_assertion(
+        &self,
+        certificate: &X509,
+        private_key: &PKey<Private>,
+    ) -> anyhow::Result<String> {
+        let thumbprint = certificate.digest(MessageDigest::sha1())?;
+        let header = json!({
+            "alg": "RS256",
+            "typ": "JWT",
+            "x5t": URL_SAFE_NO_PAD.encode(thumbprint),
+        });
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let mut jti = [0u8; 16];
+        openssl::rand::rand_bytes(&mut jti)?;
+        let claims = json!({
+            "aud": token_endpoint(self.cloud, &self.tenant),
+            "iss": self.client_id,
+            "sub": self.client_id,
+            "jti": URL_SAFE_NO_PAD.encode(jti),
+            "nbf": now,
+            "exp": now + ASSERTION_LIFETIME_SECS,
+        });
+
+        let signing_input = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(header.to_string()),
+            URL_SAFE_NO_PAD.encode(claims.to_string())
+        );
+        let mut signer = Signer::new(MessageDigest::sha256(), private_key)?;
+        signer.update(signing_input.as_bytes())?;
+        let signature = signer.sign_to_vec()?;
+
+        Ok(format!(
+            "{}.{}",
+            signing_input,
+            URL_SAFE_NO_PAD.encode(signature)
+        ))
+    }
+}
+
+#[async_trait]
+impl TokenProvider for ClientCredentials {
+    async fn access_token(&self) -> anyhow::Result<String> {
+        self.token.get_or_acquire(|| self.acquire_token()).await
+    }
+
+    async fn refresh(&self, rejected: &str) -> anyhow::Result<String> {
+        self.token
+            .reacquire(rejected, || self.acquire_token())
+            .await
+    }
+}
@@ -0,0 +1,84 @@
+use std::time::Duration;
+
+use reqwest::Client;
+use serde::Deserialize;
+
+use super::{token_endpoint, Token, TokenErrorResponse, TokenResponse};
+use crate::cloud::Cloud;
+
+const DEVICE_CODE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+#[derive(Deserialize)]
+struct DeviceCodeResponse {
+    device_code: String,
+    message: String,
+    expires_in: u64,
+    interval: u64,
+}
+
+/// Acquires a delegated token with the OAuth2 device code flow.
+///
+/// The verification URL and user code are printed to stderr and the token
+/// endpoint is polled until the user completes sign-in. Wrap the result in a
+/// [`RefreshTokenProvider`](super::refresh_token::RefreshTokenProvider) to
+/// keep it fresh for long runs.
+pub async fn acquire_token(
+    client: &Client,
+    cloud: Cloud,
+    tenant: &str,
+    client_id: &str,
+    scopes: &str,
+) -> anyhow::Result<Token> {
+    let url = format!("{}/{}/oauth2/v2.0/devicecode", cloud.authority(), tenant);
+    let response = client
+        .post(url)
+        .form(&[("client_id", client_id), ("scope", scopes)])
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let response_txt = response.text().await?;
+        anyhow::bail!("requesting device code; {}: {}", status, response_txt)
+    }
+
+    let device_code: DeviceCodeResponse = response.json().await?;
+    eprintln!("{}", device_code.message);
+
+    let mut interval = Duration::from_secs(device_code.interval);
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(device_code.expires_in);
+
+    loop {
+        tokio::time::sleep(interval).await;
+
+        if tokio::time::Instant::now() >= deadline {
+            anyhow::bail!("device code expired before sign-in completed")
+        }
+
+        let response = client
+            .post(token_endpoint(cloud, tenant))
+            .form(&[
+                ("grant_type", DEVICE_CODE_GRANT_TYPE),
+                ("client_id", client_id),
+                ("device_code", &device_code.device_code),
+            ])
+            .send()
+            .await?;
+
+        if response.status().is_success() {
+            let token: TokenResponse = response.json().await?;
+            return Ok(token.into());
+        }
+
+        let error: TokenErrorResponse = response.json().await?;
+        match error.error.as_str() {
+            "authorization_pending" => {}
+            "slow_down" => interval += Duration::from_secs(5),
+            _ => anyhow::bail!(
+                "acquiring token; {}: {}",
+                error.error,
+                error.error_description.unwrap_or_default()
+            ),
+        }
+    }
+}
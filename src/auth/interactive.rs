@@ -0,0 +1,166 @@
+use std::process::Command;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use openssl::sha::sha256;
+use reqwest::{Client, Url};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+use super::{token_endpoint, Token, TokenErrorResponse, TokenResponse};
+use crate::cloud::Cloud;
+
+const SIGNED_IN_PAGE: &str = "<html><body>Signed in. You may close this window.</body></html>";
+const FAILED_PAGE: &str =
+    "<html><body>Sign-in failed. Check the terminal for details.</body></html>";
+
+/// Acquires a delegated token with the OAuth2 authorization code flow (PKCE).
+///
+/// Opens the system browser at the sign-in page and waits for the redirect
+/// on a random localhost port. Wrap the result in a
+/// [`RefreshTokenProvider`](super::refresh_token::RefreshTokenProvider) to
+/// keep it fresh for long runs.
+pub async fn acquire_token(
+    client: &Client,
+    cloud: Cloud,
+    tenant: &str,
+    client_id: &str,
+    scopes: &str,
+) -> anyhow::Result<Token> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let redirect_uri = format!("http://localhost:{}", listener.local_addr()?.port());
+
+    let code_verifier = random_string()?;
+    let code_challenge = URL_SAFE_NO_PAD.encode(sha256(code_verifier.as_bytes()));
+    let state = random_string()?;
+
+    let authorize_url = Url::parse_with_params(
+        &format!("{}/{}/oauth2/v2.0/authorize", cloud.authority(), tenant),
+        &[
+            ("client_id", client_id),
+            ("response_type", "code"),
+            ("redirect_uri", &redirect_uri),
+            ("response_mode", "query"),
+            ("scope", scopes),
+            ("state", &state),
+            ("code_challenge", &code_challenge),
+            ("code_challenge_method", "S256"),
+        ],
+    )?;
+
+    eprintln!("Opening the browser to sign in. If it does not open, visit:");
+    eprintln!("{}", authorize_url);
+    if let Err(e) = open_browser(authorize_url.as_str()) {
+        tracing::warn!("Failed to open the browser; {}", e);
+    }
+
+    let code = receive_code(&listener, &state).await?;
+
+    let response = client
+        .post(token_endpoint(cloud, tenant))
+        .form(&[
+            ("grant_type", "authorization_code"),
+            ("client_id", client_id),
+            ("scope", scopes),
+            ("code", &code),
+            ("redirect_uri", &redirect_uri),
+            ("code_verifier", &code_verifier),
+        ])
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        let error: TokenErrorResponse = response.json().await?;
+        anyhow::bail!(
+            "redeeming authorization code; {}: {}",
+            error.error,
+            error.error_description.unwrap_or_default()
+        )
+    }
+
+    let token: TokenResponse = response.json().await?;
+    Ok(token.into())
+}
+
+/// Waits for the browser redirect and returns the authorization code.
+async fn receive_code(listener: &TcpListener, state: &str) -> anyhow::Result<String> {
+    loop {
+        let (mut stream, _) = listener.accept().await?;
+
+        let mut buf = vec![0u8; 8192];
+        let n = stream.read(&mut buf).await?;
+        let request = String::from_utf8_lossy(&buf[..n]);
+
+        // e.g. "GET /?code=...&state=... HTTP/1.1"
+        let path = match request.lines().next().and_then(|l| l.split(' ').nth(1)) {
+            Some(path) => path,
+            None => continue,
+        };
+        let url = Url::parse(&format!("http://localhost{}", path))?;
+        let param = |name: &str| {
+            url.query_pairs()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.into_owned())
+        };
+
+        // browsers also ask for /favicon.ico and the like
+        if param("code").is_none() && param("error").is_none() {
+            respond(&mut stream, "404 Not Found", "").await?;
+            continue;
+        }
+
+        if let Some(error) = param("error") {
+            respond(&mut stream, "200 OK", FAILED_PAGE).await?;
+            anyhow::bail!(
+                "signing in; {}: {}",
+                error,
+                param("error_description").unwrap_or_default()
+            )
+        }
+
+        if param("state").as_deref() != Some(state) {
+            respond(&mut stream, "400 Bad Request", FAILED_PAGE).await?;
+            anyhow::bail!("signing in; state mismatch in redirect")
+        }
+
+        respond(&mut stream, "200 OK", SIGNED_IN_PAGE).await?;
+        return Ok(param("code").unwrap_or_default());
+    }
+}
+
+async fn respond(
+    stream: &mut tokio::net::TcpStream,
+    status: &str,
+    body: &str,
+) -> anyhow::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    Ok(())
+}
+
+fn random_string() -> anyhow::Result<String> {
+    let mut bytes = [0u8; 32];
+    openssl::rand::rand_bytes(&mut bytes)?;
+    Ok(URL_SAFE_NO_PAD.encode(bytes))
+}
+
+fn open_browser(url: &str) -> std::io::Result<()> {
+    #[cfg(target_os = "windows")]
+    let mut command = {
+        let mut command = Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    };
+    #[cfg(target_os = "macos")]
+    let mut command = Command::new("open");
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    let mut command = Command::new("xdg-open");
+
+    command.arg(url).spawn()?;
+    Ok(())
+}
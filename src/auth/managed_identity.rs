@@ -0,0 +1,128 @@
+use std::env;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::{Deserialize, Deserializer};
+
+use super::{Token, TokenCache, TokenProvider};
+use crate::cloud::Cloud;
+
+/// Azure Instance Metadata Service token endpoint, available on VMs.
+const IMDS_ENDPOINT: &str = "http://169.254.169.254/metadata/identity/oauth2/token";
+const IMDS_API_VERSION: &str = "2018-02-01";
+
+/// App Service / Container Apps expose their own endpoint through these.
+const IDENTITY_ENDPOINT_ENV: &str = "IDENTITY_ENDPOINT";
+const IDENTITY_HEADER_ENV: &str = "IDENTITY_HEADER";
+const APP_SERVICE_API_VERSION: &str = "2019-08-01";
+
+#[derive(Deserialize)]
+struct ManagedIdentityTokenResponse {
+    access_token: String,
+    /// Seconds until expiry; IMDS returns this as a string.
+    #[serde(deserialize_with = "string_or_number")]
+    expires_in: u64,
+}
+
+fn string_or_number<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrNumber {
+        String(String),
+        Number(u64),
+    }
+
+    match StringOrNumber::deserialize(deserializer)? {
+        StringOrNumber::String(s) => s.parse().map_err(serde::de::Error::custom),
+        StringOrNumber::Number(n) => Ok(n),
+    }
+}
+
+/// Token provider for Azure-hosted runs using the managed identity of the
+/// VM, App Service or Container App.
+pub struct ManagedIdentity {
+    client: Client,
+    /// The API the tokens are for, Graph unless set otherwise.
+    resource: String,
+    client_id: Option<String>,
+    token: TokenCache,
+}
+
+impl ManagedIdentity {
+    /// Uses the system-assigned identity unless the `client_id` of a
+    /// user-assigned identity is given.
+    pub fn new(client: Client, cloud: Cloud, client_id: Option<String>) -> Self {
+        Self {
+            client,
+            resource: format!("{}/", cloud.graph_host()),
+            client_id,
+            token: TokenCache::default(),
+        }
+    }
+
+    /// Acquires tokens for another API, e.g. `https://storage.azure.com/`.
+    pub fn with_resource(mut self, resource: &str) -> Self {
+        self.resource = resource.to_string();
+        self
+    }
+
+    async fn acquire_token(&self) -> anyhow::Result<Token> {
+        let mut query = vec![("resource", self.resource.as_str())];
+        if let Some(client_id) = &self.client_id {
+            query.push(("client_id", client_id));
+        }
+
+        let request = match (
+            env::var(IDENTITY_ENDPOINT_ENV),
+            env::var(IDENTITY_HEADER_ENV),
+        ) {
+            (Ok(endpoint), Ok(header)) => {
+                query.push(("api-version", APP_SERVICE_API_VERSION));
+                self.client
+                    .get(endpoint)
+                    .header("X-IDENTITY-HEADER", header)
+                    .query(&query)
+            }
+            _ => {
+                query.push(("api-version", IMDS_API_VERSION));
+                self.client
+                    .get(IMDS_ENDPOINT)
+                    .header("Metadata", "true")
+                    .query(&query)
+            }
+        };
+
+        let response = request.send().await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let response_txt = response.text().await?;
+            anyhow::bail!(
+                "acquiring managed identity token; {}: {}",
+                status,
+                response_txt
+            )
+        }
+
+        let token: ManagedIdentityTokenResponse = response.json().await?;
+        Ok(Token {
+            access_token: token.access_token,
+            refresh_token: None,
+            expires_at: Instant::now() + Duration::from_secs(token.expires_in),
+        })
+    }
+}
+
+#[async_trait]
+impl TokenProvider for ManagedIdentity {
+    async fn access_token(&self) -> anyhow::Result<String> {
+        self.token.get_or_acquire(|| self.acquire_token()).await
+    }
+
+    async fn refresh(&self, rejected: &str) -> anyhow::Result<String> {
+        self.token
+            .reacquire(rejected, || self.acquire_token())
+            .await
+    }
+}
@@ -0,0 +1,218 @@
+use std::env;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::sync::Mutex;
+
+use crate::cloud::Cloud;
+
+pub mod azure_cli;
+pub mod client_credentials;
+pub mod device_code;
+pub mod interactive;
+pub mod managed_identity;
+pub mod refresh_token;
+
+/// Environment variable holding a pre-minted Graph access token.
+pub const ACCESS_TOKEN_ENV: &str = "ACCESS_TOKEN";
+
+/// Public client id of the "Microsoft Graph Command Line Tools" app, used
+/// when no client id of our own is configured.
+pub const DEFAULT_CLIENT_ID: &str = "14d82eec-204b-4c2f-b7e8-296a70dab67d";
+
+/// Tenant used for delegated sign-in when none is configured.
+pub const DEFAULT_TENANT: &str = "organizations";
+
+/// Tokens are treated as expired this long before they actually expire.
+const EXPIRY_SKEW: Duration = Duration::from_secs(5 * 60);
+
+/// Reads the Graph access token from the `ACCESS_TOKEN` environment variable.
+pub fn access_token_from_env() -> anyhow::Result<String> {
+    env::var(ACCESS_TOKEN_ENV)
+        .map_err(|_| anyhow::anyhow!("{} environment variable is not set", ACCESS_TOKEN_ENV))
+}
+
+/// The tenant, object and app ids a JWT access token was issued for, e.g.
+/// `tid oid appid`; `None` for tokens that aren't JWTs.
+pub fn token_identity(token: &str) -> Option<String> {
+    let payload = URL_SAFE_NO_PAD
+        .decode(token.split('.').nth(1)?.trim_end_matches('='))
+        .ok()?;
+    let claims: Value = serde_json::from_slice(&payload).ok()?;
+    let claim = |name: &str| claims.get(name).and_then(Value::as_str);
+    Some(format!(
+        "{} {} {}",
+        claim("tid")?,
+        claim("oid").unwrap_or_default(),
+        claim("appid").or(claim("azp")).unwrap_or_default()
+    ))
+}
+
+/// An access token acquired from the identity platform.
+#[derive(Clone)]
+pub struct Token {
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    pub expires_at: Instant,
+}
+
+impl Token {
+    pub fn is_expired(&self) -> bool {
+        Instant::now() + EXPIRY_SKEW >= self.expires_at
+    }
+}
+
+/// Source of Graph access tokens for [`GraphClient`](crate::GraphClient).
+#[async_trait]
+pub trait TokenProvider: Send + Sync {
+    /// Returns a valid access token, acquiring a new one if needed.
+    async fn access_token(&self) -> anyhow::Result<String>;
+
+    /// Discards the `rejected` token and acquires a new one; called when
+    /// Graph rejects a token with `401 Unauthorized`. Returns the current
+    /// token without acquiring one if it already replaced `rejected`, e.g.
+    /// when concurrent requests were rejected together.
+    async fn refresh(&self, rejected: &str) -> anyhow::Result<String>;
+}
+
+/// A fixed, pre-minted access token.
+pub struct StaticToken(pub String);
+
+#[async_trait]
+impl TokenProvider for StaticToken {
+    async fn access_token(&self) -> anyhow::Result<String> {
+        Ok(self.0.clone())
+    }
+
+    async fn refresh(&self, _rejected: &str) -> anyhow::Result<String> {
+        anyhow::bail!("access token was rejected and cannot be refreshed")
+    }
+}
+
+/// Cached token for providers that can re-acquire a token on demand.
+#[derive(Default)]
+pub(crate) struct TokenCache(Mutex<Option<Token>>);
+
+impl TokenCache {
+    /// Returns the cached token, calling `acquire` if it is missing or expired.
+    pub(crate) async fn get_or_acquire<F, Fut>(&self, acquire: F) -> anyhow::Result<String>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = anyhow::Result<Token>>,
+    {
+        let mut token = self.0.lock().await;
+        match token.as_ref() {
+            Some(token) if !token.is_expired() => Ok(token.access_token.clone()),
+            _ => {
+                let new_token = acquire().await?;
+                let access_token = new_token.access_token.clone();
+                *token = Some(new_token);
+                Ok(access_token)
+            }
+        }
+    }
+
+    /// Replaces the `rejected` token with a freshly acquired one, unless
+    /// another caller already did.
+    pub(crate) async fn reacquire<F, Fut>(
+        &self,
+        rejected: &str,
+        acquire: F,
+    ) -> anyhow::Result<String>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = anyhow::Result<Token>>,
+    {
+        let mut token = self.0.lock().await;
+        if let Some(token) = token
+            .as_ref()
+            .filter(|token| token.access_token != rejected)
+        {
+            return Ok(token.access_token.clone());
+        }
+        let new_token = acquire().await?;
+        let access_token = new_token.access_token.clone();
+        *token = Some(new_token);
+        Ok(access_token)
+    }
+}
+
+/// Successful response of the `/oauth2/v2.0/token` endpoint.
+#[derive(Deserialize)]
+pub(crate) struct TokenResponse {
+    access_token: String,
+    refresh_token: Option<String>,
+    expires_in: u64,
+}
+
+impl From<TokenResponse> for Token {
+    fn from(response: TokenResponse) -> Self {
+        Self {
+            access_token: response.access_token,
+            refresh_token: response.refresh_token,
+            expires_at: Instant::now() + Duration::from_secs(response.expires_in),
+        }
+    }
+}
+
+/// Error response of the `/oauth2/v2.0/token` endpoint.
+#[derive(Deserialize)]
+pub(crate) struct TokenErrorResponse {
+    error: String,
+    error_description: Option<String>,
+}
+
+pub(crate) fn token_endpoint(cloud: Cloud, tenant: &str) -> String {
+    format!("{}/{}/oauth2/v2.0/token", cloud.authority(), tenant)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jwt(claims: &str) -> String {
+        format!(
+            "{}.{}.signature",
+            URL_SAFE_NO_PAD.encode(r#"{"alg":"RS256"}"#),
+            URL_SAFE_NO_PAD.encode(claims)
+        )
+    }
+
+    #[test]
+    fn identity_of_tokens() {
+        let user = jwt(r#"{"tid":"t1","oid":"o1","appid":"a1"}"#);
+        assert_eq!(token_identity(&user).as_deref(), Some("t1 o1 a1"));
+        // v2 tokens name the app azp
+        let app = jwt(r#"{"tid":"t1","oid":"o2","azp":"a2"}"#);
+        assert_eq!(token_identity(&app).as_deref(), Some("t1 o2 a2"));
+        assert_eq!(token_identity("opaque"), None);
+        assert_eq!(token_identity(&jwt(r#"{"oid":"o1"}"#)), None);
+    }
+
+    #[tokio::test]
+    async fn tokens_rejected_together_are_refreshed_once() {
+        let cache = TokenCache::default();
+        let acquired = std::sync::atomic::AtomicUsize::new(0);
+        let acquire = || async {
+            let n = acquired.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(Token {
+                access_token: format!("token-{}", n),
+                refresh_token: None,
+                expires_at: Instant::now() + Duration::from_secs(3600),
+            })
+        };
+        let first = cache.get_or_acquire(acquire).await.unwrap();
+        let (a, b) = tokio::join!(
+            cache.reacquire(&first, acquire),
+            cache.reacquire(&first, acquire)
+        );
+        assert_eq!(a.unwrap(), "token-1");
+        assert_eq!(b.unwrap(), "token-1");
+        assert_eq!(acquired.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,97 @@
+use async_trait::async_trait;
+use reqwest::Client;
+use tokio::sync::Mutex;
+
+use super::{token_endpoint, Token, TokenErrorResponse, TokenProvider, TokenResponse};
+use crate::cloud::Cloud;
+
+/// Delegated token provider that renews its token with the OAuth2 refresh
+/// token grant once it expires.
+pub struct RefreshTokenProvider {
+    client: Client,
+    cloud: Cloud,
+    tenant: String,
+    client_id: String,
+    scopes: String,
+    token: Mutex<Token>,
+}
+
+impl RefreshTokenProvider {
+    pub fn new(
+        client: Client,
+        cloud: Cloud,
+        tenant: impl Into<String>,
+        client_id: impl Into<String>,
+        scopes: impl Into<String>,
+        token: Token,
+    ) -> Self {
+        Self {
+            client,
+            cloud,
+            tenant: tenant.into(),
+            client_id: client_id.into(),
+            scopes: scopes.into(),
+            token: Mutex::new(token),
+        }
+    }
+
+    async fn redeem(&self, refresh_token: &str) -> anyhow::Result<Token> {
+        let response = self
+            .client
+            .post(token_endpoint(self.cloud, &self.tenant))
+            .form(&[
+                ("grant_type", "refresh_token"),
+                ("client_id", &self.client_id),
+                ("scope", &self.scopes),
+                ("refresh_token", refresh_token),
+            ])
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let error: TokenErrorResponse = response.json().await?;
+            anyhow::bail!(
+                "refreshing token; {}: {}",
+                error.error,
+                error.error_description.unwrap_or_default()
+            )
+        }
+
+        let new_token: TokenResponse = response.json().await?;
+        let mut new_token = Token::from(new_token);
+        // the identity platform may omit the refresh token when it is unchanged
+        if new_token.refresh_token.is_none() {
+            new_token.refresh_token = Some(refresh_token.to_string());
+        }
+        Ok(new_token)
+    }
+
+    async fn refresh_locked(&self, token: &mut Token) -> anyhow::Result<String> {
+        let refresh_token = token
+            .refresh_token
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("token expired and no refresh token was issued"))?;
+        *token = self.redeem(&refresh_token).await?;
+        Ok(token.access_token.clone())
+    }
+}
+
+#[async_trait]
+impl TokenProvider for RefreshTokenProvider {
+    async fn access_token(&self) -> anyhow::Result<String> {
+        let mut token = self.token.lock().await;
+        if token.is_expired() {
+            self.refresh_locked(&mut token).await
+        } else {
+            Ok(token.access_token.clone())
+        }
+    }
+
+    async fn refresh(&self, rejected: &str) -> anyhow::Result<String> {
+        let mut token = self.token.lock().await;
+        if token.access_token != rejected {
+            return Ok(token.access_token.clone());
+        }
+        self.refresh_locked(&mut token).await
+    }
+}
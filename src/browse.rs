@@ -0,0 +1,563 @@
+use std::collections::HashMap;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::Path;
+
+use crate::diff::{Dump, DumpedUser};
+
+/// A user in the tree being browsed.
+struct Node {
+    user: DumpedUser,
+    parent: Option<usize>,
+    /// Indexes of the direct reports, by display name.
+    children: Vec<usize>,
+    /// Direct and indirect reports.
+    headcount: usize,
+}
+
+/// The reporting tree of a dump, rebuilt from the manager ids.
+pub struct OrgTree {
+    nodes: Vec<Node>,
+    roots: Vec<usize>,
+}
+
+impl OrgTree {
+    /// Users whose manager is not in the dump become roots, as do users only
+    /// reachable through a management cycle.
+    pub fn new(dump: Dump) -> Self {
+        let mut users: Vec<DumpedUser> = dump.users.into_values().collect();
+        users.sort_by(|a, b| a.display_name.cmp(&b.display_name).then(a.id.cmp(&b.id)));
+        let index: HashMap<String, usize> = users
+            .iter()
+            .enumerate()
+            .map(|(i, user)| (user.id.clone(), i))
+            .collect();
+        let mut nodes: Vec<Node> = users
+            .into_iter()
+            .map(|user| Node {
+                user,
+                parent: None,
+                children: Vec::new(),
+                headcount: 0,
+            })
+            .collect();
+        for i in 0..nodes.len() {
+            let manager = nodes[i]
+                .user
+                .manager_id
+                .as_ref()
+                .and_then(|id| index.get(id));
+            if let Some(&manager) = manager.filter(|&&manager| manager != i) {
+                nodes[i].parent = Some(manager);
+                nodes[manager].children.push(i);
+            }
+        }
+
+        let mut tree = Self {
+            roots: (0..nodes.len())
+                .filter(|&i| nodes[i].parent.is_none())
+                .collect(),
+            nodes,
+        };
+        // whatever is left is only reachable through a cycle; cut it where
+        // it is first found
+        let mut reached = vec![false; tree.nodes.len()];
+        for root in tree.roots.clone() {
+            tree.mark(root, &mut reached);
+        }
+        for i in 0..tree.nodes.len() {
+            if !reached[i] {
+                if let Some(parent) = tree.nodes[i].parent.take() {
+                    tree.nodes[parent].children.retain(|&child| child != i);
+                }
+                tree.roots.push(i);
+                tree.mark(i, &mut reached);
+            }
+        }
+        for root in tree.roots.clone() {
+            tree.count(root);
+        }
+        tree
+    }
+
+    fn mark(&self, node: usize, reached: &mut [bool]) {
+        let mut stack = vec![node];
+        while let Some(node) = stack.pop() {
+            reached[node] = true;
+            stack.extend(&self.nodes[node].children);
+        }
+    }
+
+    fn count(&mut self, node: usize) -> usize {
+        let mut headcount = 0;
+        for child in self.nodes[node].children.clone() {
+            headcount += 1 + self.count(child);
+        }
+        self.nodes[node].headcount = headcount;
+        headcount
+    }
+
+    /// All users, each before their reports.
+    fn preorder(&self) -> Vec<usize> {
+        let mut order = Vec::with_capacity(self.nodes.len());
+        let mut stack: Vec<usize> = self.roots.iter().rev().copied().collect();
+        while let Some(node) = stack.pop() {
+            order.push(node);
+            stack.extend(self.nodes[node].children.iter().rev());
+        }
+        order
+    }
+
+    /// Writes `node` and everyone below it as a CSV dump, which `diff` and
+    /// `browse` can read again. Returns the number of users written.
+    pub fn export(&self, node: usize, path: &Path) -> anyhow::Result<usize> {
+        let mut writer = ::csv::Writer::from_path(path)
+            .map_err(|e| anyhow::anyhow!("creating {}; {}", path.display(), e))?;
+        writer.write_record([
+            "id",
+            "display_name",
+            "job_title",
+            "department",
+            "manager_id",
+            "manager_display_name",
+        ])?;
+        let mut stack = vec![node];
+        let mut written = 0;
+        while let Some(node) = stack.pop() {
+            let user = &self.nodes[node].user;
+            let text = |value: &Option<String>| value.clone().unwrap_or_default();
+            writer.write_record([
+                user.id.clone(),
+                user.display_name.clone(),
+                text(&user.job_title),
+                text(&user.department),
+                text(&user.manager_id),
+                text(&user.manager_display_name),
+            ])?;
+            written += 1;
+            stack.extend(self.nodes[node].children.iter().rev());
+        }
+        writer.flush()?;
+        Ok(written)
+    }
+}
+
+/// What typed keys go to.
+enum Mode {
+    Browse,
+    Search(String),
+    Export(String),
+}
+
+/// A key press, decoded from the terminal's input.
+enum Key {
+    Up,
+    Down,
+    Left,
+    Right,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Enter,
+    Escape,
+    Backspace,
+    Char(char),
+}
+
+/// State of the browser: which managers are expanded and who is selected.
+struct Browser {
+    tree: OrgTree,
+    expanded: Vec<bool>,
+    selected: usize,
+    /// First visible row.
+    offset: usize,
+    mode: Mode,
+    /// Last search, for jumping to the next match.
+    query: Option<String>,
+    message: Option<String>,
+}
+
+const HELP: &str = "↑↓ move  → expand  ← collapse  / search  n next  e export  q quit";
+
+impl Browser {
+    fn new(tree: OrgTree) -> Self {
+        let mut expanded = vec![false; tree.nodes.len()];
+        // a single root is expanded right away
+        if let [root] = tree.roots[..] {
+            expanded[root] = true;
+        }
+        Self {
+            expanded,
+            tree,
+            selected: 0,
+            offset: 0,
+            mode: Mode::Browse,
+            query: None,
+            message: None,
+        }
+    }
+
+    /// The rows shown: the roots and the reports of expanded managers, with
+    /// their depth.
+    fn rows(&self) -> Vec<(usize, usize)> {
+        let mut rows = Vec::new();
+        let mut stack: Vec<(usize, usize)> =
+            self.tree.roots.iter().rev().map(|&i| (i, 0)).collect();
+        while let Some((node, depth)) = stack.pop() {
+            rows.push((node, depth));
+            if self.expanded[node] {
+                stack.extend(
+                    self.tree.nodes[node]
+                        .children
+                        .iter()
+                        .rev()
+                        .map(|&i| (i, depth + 1)),
+                );
+            }
+        }
+        rows
+    }
+
+    fn selected_node(&self, rows: &[(usize, usize)]) -> Option<usize> {
+        rows.get(self.selected).map(|&(node, _)| node)
+    }
+
+    /// Handles a key; returns false once the browser should close.
+    fn handle(&mut self, key: Key) -> bool {
+        let rows = self.rows();
+        let node = self.selected_node(&rows);
+        match std::mem::replace(&mut self.mode, Mode::Browse) {
+            Mode::Search(mut query) => match key {
+                Key::Enter => {
+                    self.query = Some(query.to_lowercase()).filter(|query| !query.is_empty());
+                    self.find_next(node);
+                }
+                Key::Escape => {}
+                Key::Backspace => {
+                    query.pop();
+                    self.mode = Mode::Search(query);
+                }
+                Key::Char(c) => {
+                    query.push(c);
+                    self.mode = Mode::Search(query);
+                }
+                _ => self.mode = Mode::Search(query),
+            },
+            Mode::Export(mut path) => match key {
+                Key::Enter => {
+                    if let Some(node) = node {
+                        self.message = Some(match self.tree.export(node, Path::new(&path)) {
+                            Ok(written) => format!("Exported {} users to {}", written, path),
+                            Err(e) => format!("Export failed; {}", e),
+                        });
+                    }
+                }
+                Key::Escape => {}
+                Key::Backspace => {
+                    path.pop();
+                    self.mode = Mode::Export(path);
+                }
+                Key::Char(c) => {
+                    path.push(c);
+                    self.mode = Mode::Export(path);
+                }
+                _ => self.mode = Mode::Export(path),
+            },
+            Mode::Browse => {
+                self.message = None;
+                let last = rows.len().saturating_sub(1);
+                match key {
+                    Key::Char('q') | Key::Escape => return false,
+                    Key::Up | Key::Char('k') => self.selected = self.selected.saturating_sub(1),
+                    Key::Down | Key::Char('j') => self.selected = (self.selected + 1).min(last),
+                    Key::PageUp => self.selected = self.selected.saturating_sub(page_size()),
+                    Key::PageDown => self.selected = (self.selected + page_size()).min(last),
+                    Key::Home => self.selected = 0,
+                    Key::End => self.selected = last,
+                    Key::Right | Key::Char('l') => {
+                        if let Some(node) = node {
+                            self.expanded[node] = true;
+                        }
+                    }
+                    Key::Left | Key::Char('h') => {
+                        if let Some(node) = node {
+                            if self.expanded[node] {
+                                self.expanded[node] = false;
+                            } else if let Some(parent) = self.tree.nodes[node].parent {
+                                self.select(parent);
+                            }
+                        }
+                    }
+                    Key::Enter | Key::Char(' ') => {
+                        if let Some(node) = node {
+                            self.expanded[node] = !self.expanded[node];
+                        }
+                    }
+                    Key::Char('/') => self.mode = Mode::Search(String::new()),
+                    Key::Char('n') => self.find_next(node),
+                    Key::Char('e') => {
+                        if let Some(node) = node {
+                            let name = &self.tree.nodes[node].user.display_name;
+                            self.mode = Mode::Export(format!("{}.csv", file_name(name)));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        true
+    }
+
+    /// Selects the next user after `from`, in tree order, whose name, job
+    /// title, department or id contains the last search.
+    fn find_next(&mut self, from: Option<usize>) {
+        let Some(query) = &self.query else {
+            self.message = Some("Search with / first".to_string());
+            return;
+        };
+        let order = self.tree.preorder();
+        let start = from
+            .and_then(|from| order.iter().position(|&node| node == from))
+            .map_or(0, |i| i + 1);
+        let matches = |&&node: &&usize| {
+            let user = &self.tree.nodes[node].user;
+            [
+                Some(&user.display_name),
+                user.job_title.as_ref(),
+                user.department.as_ref(),
+                Some(&user.id),
+            ]
+            .into_iter()
+            .flatten()
+            .any(|text| text.to_lowercase().contains(query.as_str()))
+        };
+        let found = order[start..]
+            .iter()
+            .chain(&order[..start])
+            .find(matches)
+            .copied();
+        match found {
+            Some(node) => self.select(node),
+            None => self.message = Some(format!("No users match {:?}", query)),
+        }
+    }
+
+    /// Expands the managers above `node` and selects it.
+    fn select(&mut self, node: usize) {
+        let mut parent = self.tree.nodes[node].parent;
+        while let Some(manager) = parent {
+            self.expanded[manager] = true;
+            parent = self.tree.nodes[manager].parent;
+        }
+        if let Some(row) = self.rows().iter().position(|&(row, _)| row == node) {
+            self.selected = row;
+        }
+    }
+
+    fn render(&mut self, out: &mut impl Write) -> io::Result<()> {
+        let (width, height) = terminal_size();
+        let rows = self.rows();
+        // a header and a status line around the tree
+        let visible = height.saturating_sub(2).max(1);
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        } else if self.selected >= self.offset + visible {
+            self.offset = self.selected + 1 - visible;
+        }
+
+        let mut screen = String::from("\x1b[H\x1b[2J");
+        let header = format!(
+            "{} users, {} roots",
+            self.tree.nodes.len(),
+            self.tree.roots.len()
+        );
+        screen.push_str(&format!("\x1b[1m{}\x1b[0m\r\n", truncate(&header, width)));
+        for (row, &(node, depth)) in rows.iter().enumerate().skip(self.offset).take(visible) {
+            let entry = &self.tree.nodes[node];
+            let marker = match (entry.children.is_empty(), self.expanded[node]) {
+                (true, _) => ' ',
+                (false, true) => '▾',
+                (false, false) => '▸',
+            };
+            let mut line = format!(
+                "{}{} {}",
+                "  ".repeat(depth),
+                marker,
+                entry.user.display_name
+            );
+            if let Some(title) = &entry.user.job_title {
+                line.push_str(&format!(" — {}", title));
+            }
+            if let Some(department) = &entry.user.department {
+                line.push_str(&format!(" ({})", department));
+            }
+            if entry.headcount > 0 {
+                line.push_str(&format!(" [{}]", entry.headcount));
+            }
+            let line = truncate(&line, width);
+            if row == self.selected {
+                screen.push_str(&format!("\x1b[7m{}\x1b[0m\r\n", line));
+            } else {
+                screen.push_str(&format!("{}\r\n", line));
+            }
+        }
+        let status = match (&self.mode, &self.message) {
+            (Mode::Search(query), _) => format!("Search: {}", query),
+            (Mode::Export(path), _) => format!("Export subtree to: {}", path),
+            (Mode::Browse, Some(message)) => message.clone(),
+            (Mode::Browse, None) => HELP.to_string(),
+        };
+        screen.push_str(&format!("\x1b[{};1H{}", height, truncate(&status, width)));
+        out.write_all(screen.as_bytes())?;
+        out.flush()
+    }
+}
+
+/// Shortens `line` to `width` characters.
+fn truncate(line: &str, width: usize) -> String {
+    line.chars().take(width).collect()
+}
+
+/// A file name for a user's subtree, e.g. `Jane_Doe`.
+fn file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn page_size() -> usize {
+    terminal_size().1.saturating_sub(2).max(1)
+}
+
+/// Browses the reporting tree of `dump` in the terminal until `q` is
+/// pressed.
+pub fn run(dump: Dump) -> anyhow::Result<()> {
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        anyhow::bail!("browsing needs an interactive terminal")
+    }
+    let mut browser = Browser::new(OrgTree::new(dump));
+    let _terminal = RawTerminal::enter()?;
+    let mut stdout = io::stdout().lock();
+    let mut stdin = io::stdin().lock();
+    let mut buf = [0u8; 64];
+    loop {
+        browser.render(&mut stdout)?;
+        let n = stdin.read(&mut buf)?;
+        if n == 0 {
+            return Ok(());
+        }
+        for key in decode(&buf[..n]) {
+            if !browser.handle(key) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Decodes the keys in one read from the terminal; escape sequences arrive
+/// whole.
+fn decode(input: &[u8]) -> Vec<Key> {
+    let text = String::from_utf8_lossy(input);
+    let mut keys = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        keys.push(match c {
+            '\x1b' if chars.peek() == Some(&'[') || chars.peek() == Some(&'O') => {
+                chars.next();
+                let mut sequence = String::new();
+                for c in chars.by_ref() {
+                    sequence.push(c);
+                    if c.is_ascii_alphabetic() || c == '~' {
+                        break;
+                    }
+                }
+                match sequence.as_str() {
+                    "A" => Key::Up,
+                    "B" => Key::Down,
+                    "C" => Key::Right,
+                    "D" => Key::Left,
+                    "H" | "1~" => Key::Home,
+                    "F" | "4~" => Key::End,
+                    "5~" => Key::PageUp,
+                    "6~" => Key::PageDown,
+                    _ => continue,
+                }
+            }
+            '\x1b' => Key::Escape,
+            '\r' | '\n' => Key::Enter,
+            '\x7f' | '\x08' => Key::Backspace,
+            // Ctrl-C, as the terminal doesn't signal in raw mode
+            '\x03' => Key::Char('q'),
+            c if c.is_control() => continue,
+            c => Key::Char(c),
+        });
+    }
+    keys
+}
+
+/// The terminal in raw mode on the alternate screen, restored when dropped.
+struct RawTerminal {
+    #[cfg(unix)]
+    original: libc::termios,
+}
+
+#[cfg(unix)]
+impl RawTerminal {
+    fn enter() -> anyhow::Result<Self> {
+        // SAFETY: termios is plain data, filled in by tcgetattr
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+            anyhow::bail!("reading the terminal mode; {}", io::Error::last_os_error())
+        }
+        let mut raw = original;
+        unsafe { libc::cfmakeraw(&mut raw) };
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+            anyhow::bail!(
+                "switching the terminal to raw mode; {}",
+                io::Error::last_os_error()
+            )
+        }
+        print!("\x1b[?1049h\x1b[?25l");
+        Ok(Self { original })
+    }
+}
+
+#[cfg(not(unix))]
+impl RawTerminal {
+    fn enter() -> anyhow::Result<Self> {
+        anyhow::bail!("browsing is only supported in Unix terminals")
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = io::stdout().flush();
+        #[cfg(unix)]
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original);
+        }
+    }
+}
+
+/// Columns and rows of the terminal, 80x24 if unknown.
+fn terminal_size() -> (usize, usize) {
+    #[cfg(unix)]
+    {
+        // SAFETY: winsize is plain data, filled in by the ioctl
+        let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+        if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0
+            && size.ws_col > 0
+            && size.ws_row > 0
+        {
+            return (size.ws_col as usize, size.ws_row as usize);
+        }
+    }
+    (80, 24)
+}
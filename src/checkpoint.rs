@@ -0,0 +1,211 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::model::User;
+
+/// Minimum time between two checkpoint writes.
+const SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Progress of a reportee tree walk, persisted so an interrupted run can be
+/// resumed.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Ids of the users already written to the output.
+    pub emitted: HashSet<String>,
+    /// Managers whose direct reports have not been fetched yet, by id.
+    pub pending: HashMap<String, User>,
+    /// Users the whole walk is expected to write, roots included, from
+    /// `$count`; missing if unknown.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected: Option<u64>,
+}
+
+impl Checkpoint {
+    /// Starts a walk below `roots`, which have already been emitted.
+    pub fn new(roots: &[User]) -> Self {
+        Self {
+            emitted: roots.iter().map(|root| root.id.clone()).collect(),
+            pending: roots
+                .iter()
+                .map(|root| (root.id.clone(), root.clone()))
+                .collect(),
+            expected: None,
+        }
+    }
+
+    /// Records that the walk is expected to write `users` users, and makes
+    /// room for them.
+    pub fn set_expected(&mut self, users: u64) {
+        self.expected = Some(users);
+        let additional = (users as usize).saturating_sub(self.emitted.len());
+        self.emitted.reserve(additional);
+    }
+
+    /// Users expected to be written by the rest of the walk, if known.
+    pub fn remaining(&self) -> Option<u64> {
+        self.expected
+            .map(|expected| expected.saturating_sub(self.emitted.len() as u64))
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let file = std::fs::File::open(path)
+            .map_err(|e| anyhow::anyhow!("opening checkpoint {}; {}", path.display(), e))?;
+        let checkpoint = serde_json::from_reader(std::io::BufReader::new(file))
+            .map_err(|e| anyhow::anyhow!("reading checkpoint {}; {}", path.display(), e))?;
+        Ok(checkpoint)
+    }
+
+    /// Managers still to be expanded.
+    pub fn pending_managers(&self) -> Vec<User> {
+        self.pending.values().cloned().collect()
+    }
+
+    /// Records that `manager` was expanded into `reportees`, returning the
+    /// reportees that were not emitted before.
+    pub fn expanded<'a>(&mut self, manager: &User, reportees: &'a [User]) -> Vec<&'a User> {
+        self.pending.remove(&manager.id);
+
+        let mut fresh = Vec::new();
+        for reportee in reportees {
+            if self.emitted.insert(reportee.id.clone()) {
+                fresh.push(reportee);
+            }
+            self.pending.insert(reportee.id.clone(), reportee.clone());
+        }
+        fresh
+    }
+}
+
+/// Writes a [`Checkpoint`] to disk, at most every few seconds.
+pub struct CheckpointFile {
+    path: PathBuf,
+    last_saved: Option<Instant>,
+}
+
+impl CheckpointFile {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            last_saved: None,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Saves `checkpoint` unless it was saved very recently; `before_save`
+    /// runs first so the output can be flushed up to the checkpoint.
+    pub fn maybe_save(
+        &mut self,
+        checkpoint: &Checkpoint,
+        before_save: impl FnOnce() -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        if self
+            .last_saved
+            .is_some_and(|last_saved| last_saved.elapsed() < SAVE_INTERVAL)
+        {
+            return Ok(());
+        }
+        before_save()?;
+        self.save(checkpoint)
+    }
+
+    /// Atomically replaces the checkpoint file with `checkpoint`.
+    pub fn save(&mut self, checkpoint: &Checkpoint) -> anyhow::Result<()> {
+        let tmp_path = self.path.with_extension("tmp");
+        let write = || -> std::io::Result<()> {
+            let mut writer = std::io::BufWriter::new(std::fs::File::create(&tmp_path)?);
+            serde_json::to_writer(&mut writer, checkpoint)?;
+            // a failed write, e.g. on a full disk, must not replace the
+            // previous checkpoint
+            let file = writer.into_inner().map_err(|e| e.into_error())?;
+            file.sync_all()
+        };
+        write().map_err(|e| anyhow::anyhow!("writing checkpoint {}; {}", tmp_path.display(), e))?;
+        std::fs::rename(&tmp_path, &self.path)
+            .map_err(|e| anyhow::anyhow!("writing checkpoint {}; {}", self.path.display(), e))?;
+        self.last_saved = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Removes the checkpoint once the walk has completed.
+    pub fn remove(&self) -> anyhow::Result<()> {
+        match std::fs::remove_file(&self.path) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(anyhow::anyhow!(
+                "removing checkpoint {}; {}",
+                self.path.display(),
+                e
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Map;
+
+    use super::*;
+
+    fn user(id: &str) -> User {
+        User::record(id, id, Map::new())
+    }
+
+    #[test]
+    fn reportees_are_emitted_once() {
+        let mut checkpoint = Checkpoint::new(&[user("root")]);
+        checkpoint.set_expected(4);
+        let reportees = [user("a"), user("b")];
+        let fresh = checkpoint.expanded(&user("root"), &reportees);
+        assert_eq!(fresh.len(), 2);
+        assert!(!checkpoint.pending.contains_key("root"));
+        assert_eq!(checkpoint.remaining(), Some(1));
+
+        // b reached again, e.g. through a second manager
+        let reportees = [user("b"), user("c")];
+        let fresh: Vec<&str> = checkpoint
+            .expanded(&user("a"), &reportees)
+            .into_iter()
+            .map(|user| user.id.as_str())
+            .collect();
+        assert_eq!(fresh, ["c"]);
+        assert_eq!(checkpoint.remaining(), Some(0));
+        let mut pending: Vec<String> = checkpoint.pending.into_keys().collect();
+        pending.sort();
+        assert_eq!(pending, ["b", "c"]);
+    }
+
+    #[test]
+    fn saved_checkpoints_resume_where_they_stopped() {
+        let path =
+            std::env::temp_dir().join(format!("checkpoint-test-{}.json", std::process::id()));
+        let mut checkpoint = Checkpoint::new(&[user("root")]);
+        checkpoint.expanded(&user("root"), &[user("a")]);
+        let mut file = CheckpointFile::new(&path);
+        file.save(&checkpoint).unwrap();
+
+        // saved moments ago, so the output is not flushed again either
+        let mut flushed = false;
+        file.maybe_save(&checkpoint, || {
+            flushed = true;
+            Ok(())
+        })
+        .unwrap();
+        assert!(!flushed);
+
+        let loaded = Checkpoint::load(&path).unwrap();
+        assert_eq!(loaded.emitted, checkpoint.emitted);
+        let pending: Vec<String> = loaded.pending.into_keys().collect();
+        assert_eq!(pending, ["a"]);
+        assert_eq!(loaded.expected, None);
+        file.remove().unwrap();
+        assert!(!path.exists());
+        // removing it twice is fine
+        file.remove().unwrap();
+    }
+}
@@ -0,0 +1,146 @@
+use std::path::Path;
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::model::User;
+
+/// The rules in use, set once at startup; the built-in rules otherwise.
+static CLASSIFICATION: OnceLock<Classification> = OnceLock::new();
+
+/// How users are labelled in the `employment_type` and `location` columns.
+///
+/// Read from a JSON file such as
+///
+/// ```json
+/// {
+///   "employment_type": {
+///     "property": "jobTitle",
+///     "default": "Employee",
+///     "rules": [{ "label": "Vendor", "keywords": ["CONSULT", "OUTSOURCE"], "ignore_case": true }]
+///   },
+///   "location": {
+///     "property": "officeLocation",
+///     "default": "On-Site",
+///     "rules": [{ "label": "Off-Shore", "keywords": ["Off-Shore", "Off-Site"] }]
+///   }
+/// }
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Classification {
+    #[serde(default = "Taxonomy::employment_type")]
+    pub employment_type: Taxonomy,
+    #[serde(default = "Taxonomy::location")]
+    pub location: Taxonomy,
+}
+
+impl Default for Classification {
+    /// Vendors by their job title and off-shore users by their office
+    /// location.
+    fn default() -> Self {
+        Self {
+            employment_type: Taxonomy::employment_type(),
+            location: Taxonomy::location(),
+        }
+    }
+}
+
+impl Classification {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let file = std::fs::File::open(path)
+            .map_err(|e| anyhow::anyhow!("opening classification {}; {}", path.display(), e))?;
+        let classification: Self = serde_json::from_reader(std::io::BufReader::new(file))
+            .map_err(|e| anyhow::anyhow!("reading classification {}; {}", path.display(), e))?;
+        Ok(classification)
+    }
+
+    /// Uses these rules for the rest of the run; fails if the rules were
+    /// already in use.
+    pub fn install(self) -> anyhow::Result<()> {
+        CLASSIFICATION
+            .set(self)
+            .map_err(|_| anyhow::anyhow!("the classification rules are already set"))
+    }
+
+    /// The installed rules, or the built-in ones.
+    pub fn current() -> &'static Self {
+        CLASSIFICATION.get_or_init(Self::default)
+    }
+}
+
+/// Labels derived from one user property.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Taxonomy {
+    /// Graph property the keywords are looked for in, e.g. `jobTitle`.
+    pub property: String,
+    /// Label of the users matching no rule.
+    pub default: String,
+    /// Tried in order; the first rule matching wins.
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+}
+
+impl Taxonomy {
+    fn employment_type() -> Self {
+        Self {
+            property: "jobTitle".to_string(),
+            default: "Employee".to_string(),
+            rules: vec![Rule::new("Vendor", &["CONSULT", "OUTSOURCE", "Outsource"])],
+        }
+    }
+
+    fn location() -> Self {
+        Self {
+            property: "officeLocation".to_string(),
+            default: "On-Site".to_string(),
+            rules: vec![Rule::new("Off-Shore", &["Off-Shore", "Off-Site"])],
+        }
+    }
+
+    /// The label of `user`. A missing property reads as `unknown`.
+    pub fn classify(&self, user: &User) -> &str {
+        let value = match user.property(&self.property) {
+            Value::Null => "unknown".to_string(),
+            Value::String(s) => s,
+            value => value.to_string(),
+        };
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(&value))
+            .map_or(&self.default, |rule| &rule.label)
+    }
+}
+
+/// A label given to the users whose property contains any of the keywords.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    pub label: String,
+    pub keywords: Vec<String>,
+    #[serde(default)]
+    pub ignore_case: bool,
+}
+
+impl Rule {
+    fn new(label: &str, keywords: &[&str]) -> Self {
+        Self {
+            label: label.to_string(),
+            keywords: keywords.iter().map(|kw| kw.to_string()).collect(),
+            ignore_case: false,
+        }
+    }
+
+    fn matches(&self, value: &str) -> bool {
+        if self.ignore_case {
+            let value = value.to_lowercase();
+            self.keywords
+                .iter()
+                .any(|kw| value.contains(&kw.to_lowercase()))
+        } else {
+            self.keywords.iter().any(|kw| value.contains(kw.as_str()))
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use clap::ValueEnum;
+
+/// A Microsoft cloud, with its own Graph service and identity platform.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Cloud {
+    /// The global (commercial) cloud, also used by GCC tenants.
+    #[default]
+    Global,
+    /// US Government L4 (GCC High).
+    UsGov,
+    /// US Government L5 (DoD).
+    UsGovDod,
+    /// China, operated by 21Vianet.
+    China,
+    /// Microsoft Cloud Germany.
+    Germany,
+}
+
+impl Cloud {
+    /// Microsoft identity platform authority tokens are requested from.
+    pub fn authority(&self) -> &'static str {
+        match self {
+            Cloud::Global => "https://login.microsoftonline.com",
+            Cloud::UsGov | Cloud::UsGovDod => "https://login.microsoftonline.us",
+            Cloud::China => "https://login.chinacloudapi.cn",
+            Cloud::Germany => "https://login.microsoftonline.de",
+        }
+    }
+
+    /// Host of the Graph service, which is also the resource tokens are
+    /// issued for.
+    pub fn graph_host(&self) -> &'static str {
+        match self {
+            Cloud::Global => "https://graph.microsoft.com",
+            Cloud::UsGov => "https://graph.microsoft.us",
+            Cloud::UsGovDod => "https://dod-graph.microsoft.us",
+            Cloud::China => "https://microsoftgraph.chinacloudapi.cn",
+            Cloud::Germany => "https://graph.microsoft.de",
+        }
+    }
+
+    /// Scope requesting all application permissions granted to the app.
+    pub fn app_only_scope(&self) -> String {
+        format!("{}/.default", self.graph_host())
+    }
+
+    /// Delegated scopes needed to read users and their reporting lines,
+    /// plus the `permissions` of the enabled features, e.g. `Mail.Send`.
+    pub fn delegated_scopes(&self, permissions: &[&str]) -> String {
+        let mut scopes: Vec<String> = ["User.Read.All"]
+            .iter()
+            .chain(permissions)
+            .map(|permission| format!("{}/{}", self.graph_host(), permission))
+            .collect();
+        scopes.push("offline_access".to_string());
+        scopes.join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delegated_scopes_of_features() {
+        assert_eq!(
+            Cloud::Global.delegated_scopes(&[]),
+            "https://graph.microsoft.com/User.Read.All offline_access"
+        );
+        assert_eq!(
+            Cloud::UsGov.delegated_scopes(&["Mail.Send"]),
+            "https://graph.microsoft.us/User.Read.All https://graph.microsoft.us/Mail.Send \
+             offline_access"
+        );
+    }
+}
@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::model::{Change, User};
+
+/// Snapshot key holding the id of a user's manager.
+const MANAGER_ID_KEY: &str = "managerId";
+
+/// State carried between `/users/delta` runs: the link for the next round
+/// and the last known properties of every user, since delta rounds only
+/// return what changed.
+#[derive(Default, Serialize, Deserialize)]
+pub struct DeltaState {
+    pub delta_link: Option<String>,
+    pub users: HashMap<String, Map<String, Value>>,
+}
+
+impl DeltaState {
+    /// Loads the state at `path`, or starts fresh if there is none yet.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        match std::fs::File::open(path) {
+            Ok(file) => serde_json::from_reader(std::io::BufReader::new(file))
+                .map_err(|e| anyhow::anyhow!("reading delta state {}; {}", path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(anyhow::anyhow!(
+                "opening delta state {}; {}",
+                path.display(),
+                e
+            )),
+        }
+    }
+
+    /// Atomically replaces the state file.
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let tmp_path = path.with_extension("tmp");
+        let write = || -> std::io::Result<()> {
+            let mut writer = std::io::BufWriter::new(std::fs::File::create(&tmp_path)?);
+            serde_json::to_writer(&mut writer, self)?;
+            // a failed write must not replace the previous state
+            let file = writer.into_inner().map_err(|e| e.into_error())?;
+            file.sync_all()
+        };
+        write()
+            .map_err(|e| anyhow::anyhow!("writing delta state {}; {}", tmp_path.display(), e))?;
+        std::fs::rename(&tmp_path, path)
+            .map_err(|e| anyhow::anyhow!("writing delta state {}; {}", path.display(), e))?;
+        Ok(())
+    }
+
+    /// Merges one round of delta items into the snapshot and returns the
+    /// changed users, marked as added, updated or deleted, with their
+    /// managers.
+    pub fn apply(
+        &mut self,
+        items: Vec<Map<String, Value>>,
+    ) -> anyhow::Result<Vec<(User, Option<User>)>> {
+        let mut changed = Vec::new();
+
+        for mut item in items {
+            let id = match item.get("id").and_then(Value::as_str) {
+                Some(id) => id.to_string(),
+                None => continue,
+            };
+
+            if item.contains_key("@removed") {
+                if let Some(properties) = self.users.remove(&id) {
+                    changed.push((id, Change::Delete, properties));
+                }
+                continue;
+            }
+
+            if let Some(manager) = item.remove("manager@delta") {
+                let manager_id = manager
+                    .as_array()
+                    .and_then(|managers| managers.first())
+                    .filter(|manager| manager.get("@removed").is_none())
+                    .and_then(|manager| manager.get("id"))
+                    .cloned()
+                    .unwrap_or(Value::Null);
+                item.insert(MANAGER_ID_KEY.to_string(), manager_id);
+            }
+            item.retain(|key, _| !key.starts_with('@'));
+
+            let change = if self.users.contains_key(&id) {
+                Change::Update
+            } else {
+                Change::Add
+            };
+            let properties = self.users.entry(id.clone()).or_default();
+            properties.extend(item);
+            changed.push((id, change, properties.clone()));
+        }
+
+        changed
+            .into_iter()
+            .map(|(_, change, properties)| {
+                let mut user = to_user(&properties)?;
+                user.change = Some(change);
+                let manager = properties
+                    .get(MANAGER_ID_KEY)
+                    .and_then(Value::as_str)
+                    .and_then(|manager_id| self.users.get(manager_id))
+                    .map(to_user)
+                    .transpose()?;
+                Ok((user, manager))
+            })
+            .collect()
+    }
+}
+
+fn to_user(properties: &Map<String, Value>) -> anyhow::Result<User> {
+    let mut properties = properties.clone();
+    properties.remove(MANAGER_ID_KEY);
+    // users without a display name are rare but legal
+    properties
+        .entry("displayName")
+        .or_insert_with(|| Value::String(String::new()));
+    Ok(serde_json::from_value(Value::Object(properties))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn items(items: Value) -> Vec<Map<String, Value>> {
+        serde_json::from_value(items).unwrap()
+    }
+
+    fn changes(changed: &[(User, Option<User>)]) -> Vec<(&str, &str, Option<&str>)> {
+        changed
+            .iter()
+            .map(|(user, manager)| {
+                (
+                    user.id.as_str(),
+                    user.change.unwrap().as_str(),
+                    manager.as_ref().map(|manager| manager.id.as_str()),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rounds_add_update_and_delete_users() {
+        let mut state = DeltaState::default();
+        let changed = state
+            .apply(items(json!([
+                { "id": "m", "displayName": "Mia" },
+                { "id": "u", "displayName": "Ann", "jobTitle": "Dev",
+                  "manager@delta": [{ "id": "m" }] },
+            ])))
+            .unwrap();
+        assert_eq!(
+            changes(&changed),
+            [("m", "add", None), ("u", "add", Some("m"))]
+        );
+
+        // only the changed properties come back; the rest is remembered
+        let changed = state
+            .apply(items(json!([{ "id": "u", "jobTitle": "Lead" }])))
+            .unwrap();
+        assert_eq!(changes(&changed), [("u", "update", Some("m"))]);
+        assert_eq!(changed[0].0.display_name, "Ann");
+        assert_eq!(changed[0].0.job_title.as_deref(), Some("Lead"));
+
+        // a removed manager link leaves the user without one
+        let changed = state
+            .apply(items(json!([
+                { "id": "u", "manager@delta": [{ "id": "m", "@removed": {} }] },
+                { "id": "m", "@removed": { "reason": "changed" } },
+                { "id": "gone", "@removed": { "reason": "deleted" } },
+            ])))
+            .unwrap();
+        assert_eq!(
+            changes(&changed),
+            [("u", "update", None), ("m", "delete", None)]
+        );
+        assert!(!state.users.contains_key("m"));
+    }
+
+    #[test]
+    fn state_survives_a_save() {
+        let path = std::env::temp_dir().join(format!("delta-test-{}.json", std::process::id()));
+        let mut state = DeltaState {
+            delta_link: Some("https://graph.microsoft.com/v1.0/users/delta?$deltatoken=t".into()),
+            ..DeltaState::default()
+        };
+        state
+            .apply(items(json!([{ "id": "u", "displayName": "Ann" }])))
+            .unwrap();
+        state.save(&path).unwrap();
+        let loaded = DeltaState::load(&path).unwrap();
+        assert_eq!(loaded.delta_link, state.delta_link);
+        assert_eq!(loaded.users, state.users);
+        std::fs::remove_file(&path).unwrap();
+        // no state yet is a first round
+        assert!(DeltaState::load(&path).unwrap().users.is_empty());
+    }
+}
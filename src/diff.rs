@@ -0,0 +1,398 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display};
+use std::path::Path;
+
+use serde_json::{Map, Value};
+
+use crate::output::{snake_case, OutputFormat, PLACEHOLDERS};
+
+/// A user as recorded in a previous dump.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DumpedUser {
+    pub id: String,
+    pub display_name: String,
+    pub job_title: Option<String>,
+    pub department: Option<String>,
+    pub manager_id: Option<String>,
+    pub manager_display_name: Option<String>,
+}
+
+impl DumpedUser {
+    /// Builds the user from a record keyed by snake_case column names.
+    fn from_record(record: &HashMap<String, Option<String>>) -> Option<Self> {
+        let field = |name: &str| record.get(name).cloned().flatten();
+        let id = field("id")?;
+        Some(Self {
+            display_name: field("display_name").unwrap_or_else(|| id.clone()),
+            id,
+            job_title: field("job_title"),
+            department: field("department"),
+            manager_id: field("manager_id"),
+            manager_display_name: field("manager_display_name"),
+        })
+    }
+}
+
+/// The users of a previous dump, by id, and the columns it has.
+#[derive(Default)]
+pub struct Dump {
+    pub users: HashMap<String, DumpedUser>,
+    pub columns: HashSet<String>,
+}
+
+impl Dump {
+    /// Reads a dump written in the `csv`, `json`, `jsonl` or `sqlite` format,
+    /// as told by the extension of `path`. Users deleted in a `--delta` dump
+    /// are left out.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let records = read_records(path)?;
+        let mut dump = Self::default();
+        for record in records {
+            dump.columns.extend(record.keys().cloned());
+            if record.get("change").cloned().flatten().as_deref() == Some("delete") {
+                continue;
+            }
+            let user = DumpedUser::from_record(&record).ok_or_else(|| {
+                anyhow::anyhow!("reading dump {}; a user has no id", path.display())
+            })?;
+            dump.users.insert(user.id.clone(), user);
+        }
+        Ok(dump)
+    }
+
+    /// Display name of the manager of `user`, looked up by id if the dump
+    /// doesn't carry it.
+    fn manager_name(&self, user: &DumpedUser) -> Option<String> {
+        let id = user.manager_id.as_ref()?;
+        user.manager_display_name
+            .clone()
+            .or_else(|| self.users.get(id).map(|m| m.display_name.clone()))
+            .or_else(|| Some(id.clone()))
+    }
+}
+
+/// The rows of a dump in the `csv`, `json`, `jsonl` or `sqlite` format, as
+/// told by the extension of `path`, keyed by snake_case column name.
+pub fn read_records(path: &Path) -> anyhow::Result<Vec<HashMap<String, Option<String>>>> {
+    let context = |e: &dyn Display| anyhow::anyhow!("reading dump {}; {}", path.display(), e);
+    Ok(match OutputFormat::from_path(path) {
+        Some(OutputFormat::Csv) => read_csv(path).map_err(|e| context(&e))?,
+        Some(OutputFormat::Json) => {
+            let file = std::fs::File::open(path).map_err(|e| context(&e))?;
+            let objects: Vec<Map<String, Value>> =
+                serde_json::from_reader(std::io::BufReader::new(file)).map_err(|e| context(&e))?;
+            objects.into_iter().map(json_record).collect()
+        }
+        Some(OutputFormat::Jsonl) => {
+            let text = std::fs::read_to_string(path).map_err(|e| context(&e))?;
+            text.lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(|line| serde_json::from_str(line).map(json_record))
+                .collect::<Result<_, _>>()
+                .map_err(|e| context(&e))?
+        }
+        Some(OutputFormat::Sqlite) => read_sqlite(path).map_err(|e| context(&e))?,
+        _ => anyhow::bail!(
+            "cannot read {}; use csv, json, jsonl or sqlite dumps",
+            path.display()
+        ),
+    })
+}
+
+fn read_csv(path: &Path) -> anyhow::Result<Vec<HashMap<String, Option<String>>>> {
+    let text = std::fs::read_to_string(path)?;
+    parse_csv(&text)
+}
+
+/// The rows of a CSV dump in any `--csv-delimiter` and quoting; the header
+/// row is required.
+fn parse_csv(text: &str) -> anyhow::Result<Vec<HashMap<String, Option<String>>>> {
+    let mut reader = ::csv::ReaderBuilder::new()
+        .delimiter(sniff_delimiter(text))
+        .from_reader(text.as_bytes());
+    let headers = reader.headers()?.clone();
+    if !headers.iter().any(|name| name == "id") {
+        anyhow::bail!(
+            "the header row has no id column; dumps written with --csv-no-header cannot be read"
+        )
+    }
+    let mut records = Vec::new();
+    for row in reader.records() {
+        let row = row?;
+        records.push(
+            headers
+                .iter()
+                .zip(row.iter())
+                // the placeholders written for missing values, or `--null-as ""`
+                .map(|(name, value)| {
+                    let missing = value.is_empty() || PLACEHOLDERS.contains(&value);
+                    let value = (!missing).then(|| value.to_string());
+                    (name.to_string(), value)
+                })
+                .collect(),
+        );
+    }
+    Ok(records)
+}
+
+/// The delimiter of a CSV dump: the character separating the most column
+/// names in the header row, preferring the usual ones on a tie.
+fn sniff_delimiter(text: &str) -> u8 {
+    const USUAL: [u8; 4] = [b',', b';', b'\t', b'|'];
+    let header = text.lines().next().unwrap_or_default();
+    let mut counts: HashMap<u8, usize> = HashMap::new();
+    let mut quoted = false;
+    for byte in header.bytes() {
+        match byte {
+            b'"' => quoted = !quoted,
+            // column names are snake_case
+            _ if quoted || byte.is_ascii_alphanumeric() || byte == b'_' || !byte.is_ascii() => {}
+            _ => *counts.entry(byte).or_default() += 1,
+        }
+    }
+    let rank = |byte: &u8| {
+        USUAL
+            .iter()
+            .position(|usual| usual == byte)
+            .unwrap_or(USUAL.len())
+    };
+    counts
+        .into_iter()
+        .max_by(|(a, a_count), (b, b_count)| a_count.cmp(b_count).then(rank(b).cmp(&rank(a))))
+        .map_or(b',', |(byte, _)| byte)
+}
+
+fn json_record(object: Map<String, Value>) -> HashMap<String, Option<String>> {
+    object
+        .into_iter()
+        .map(|(name, value)| {
+            let value = match value {
+                Value::Null => None,
+                Value::String(s) => Some(s),
+                value => Some(value.to_string()),
+            };
+            (snake_case(&name), value)
+        })
+        .collect()
+}
+
+fn read_sqlite(path: &Path) -> anyhow::Result<Vec<HashMap<String, Option<String>>>> {
+    let connection =
+        rusqlite::Connection::open_with_flags(path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut statement = connection.prepare("SELECT * FROM users")?;
+    let names: Vec<String> = statement
+        .column_names()
+        .into_iter()
+        .map(str::to_string)
+        .collect();
+    let rows = statement.query_map([], |row| {
+        names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| Ok((name.clone(), row.get::<_, Option<String>>(i)?)))
+            .collect()
+    })?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+/// A user whose attribute changed between two dumps.
+#[derive(Clone, Debug)]
+pub struct AttributeChange {
+    pub user: DumpedUser,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+/// Joiners, leavers and moves between two dumps, each sorted by display
+/// name.
+#[derive(Default)]
+pub struct OrgDiff {
+    pub joiners: Vec<DumpedUser>,
+    pub leavers: Vec<DumpedUser>,
+    pub manager_changes: Vec<AttributeChange>,
+    pub title_changes: Vec<AttributeChange>,
+    pub department_moves: Vec<AttributeChange>,
+}
+
+impl OrgDiff {
+    /// Compares the `old` dump with the `new` one. Attributes are only
+    /// compared when both dumps have their column.
+    pub fn between(old: &Dump, new: &Dump) -> Self {
+        let both = |column: &str| old.columns.contains(column) && new.columns.contains(column);
+        let mut diff = Self::default();
+
+        for user in new.users.values() {
+            let Some(previous) = old.users.get(&user.id) else {
+                diff.joiners.push(user.clone());
+                continue;
+            };
+            let change = |old, new| AttributeChange {
+                user: user.clone(),
+                old,
+                new,
+            };
+            if both("manager_id") && previous.manager_id != user.manager_id {
+                diff.manager_changes
+                    .push(change(old.manager_name(previous), new.manager_name(user)));
+            }
+            if both("job_title") && previous.job_title != user.job_title {
+                diff.title_changes
+                    .push(change(previous.job_title.clone(), user.job_title.clone()));
+            }
+            if both("department") && previous.department != user.department {
+                diff.department_moves
+                    .push(change(previous.department.clone(), user.department.clone()));
+            }
+        }
+        diff.leavers = old
+            .users
+            .values()
+            .filter(|user| !new.users.contains_key(&user.id))
+            .cloned()
+            .collect();
+
+        let key = |user: &DumpedUser| (user.display_name.clone(), user.id.clone());
+        diff.joiners.sort_by_key(key);
+        diff.leavers.sort_by_key(key);
+        for changes in [
+            &mut diff.manager_changes,
+            &mut diff.title_changes,
+            &mut diff.department_moves,
+        ] {
+            changes.sort_by_key(|change| key(&change.user));
+        }
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.joiners.is_empty()
+            && self.leavers.is_empty()
+            && self.manager_changes.is_empty()
+            && self.title_changes.is_empty()
+            && self.department_moves.is_empty()
+    }
+}
+
+impl Display for OrgDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let or_unknown = |value: &Option<String>| value.clone().unwrap_or("unknown".to_string());
+        for (title, sign, users) in [
+            ("Joiners", '+', &self.joiners),
+            ("Leavers", '-', &self.leavers),
+        ] {
+            writeln!(f, "{} ({})", title, users.len())?;
+            for user in users {
+                writeln!(
+                    f,
+                    "  {} {} ({}, {})",
+                    sign,
+                    user.display_name,
+                    or_unknown(&user.job_title),
+                    or_unknown(&user.department)
+                )?;
+            }
+        }
+        for (title, changes, missing) in [
+            ("Manager changes", &self.manager_changes, "none"),
+            ("Title changes", &self.title_changes, "unknown"),
+            ("Department moves", &self.department_moves, "unknown"),
+        ] {
+            writeln!(f, "{} ({})", title, changes.len())?;
+            for change in changes {
+                writeln!(
+                    f,
+                    "  {}: {} -> {}",
+                    change.user.display_name,
+                    change.old.as_deref().unwrap_or(missing),
+                    change.new.as_deref().unwrap_or(missing)
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::csv::{CsvOptions, CsvSink, Quoting};
+    use crate::output::{Column, OutputSink};
+    use crate::User;
+
+    /// Writes a root without a department and their report as a CSV dump
+    /// and reads it back.
+    fn round_trip(
+        options: CsvOptions,
+        null_as: Option<&str>,
+    ) -> anyhow::Result<Vec<HashMap<String, Option<String>>>> {
+        let property = |name: &str| Column::Property(name.to_string());
+        let columns = vec![
+            property("id"),
+            property("displayName"),
+            property("department"),
+            Column::ManagerId,
+            Column::ManagerDisplayName,
+            Column::LastSignIn,
+            Column::LastActivity,
+        ];
+        let root = User::record("u1", "Ann", Map::new());
+        let mut report = User::record("u2", "Bo", Map::new());
+        report.department = Some("Sales".to_string());
+
+        let mut text = Vec::new();
+        let mut sink = CsvSink::with_options(&mut text, columns, options)
+            .with_null_as(null_as.map(str::to_string));
+        sink.write_user(&root, None).unwrap();
+        sink.write_user(&report, Some(&root)).unwrap();
+        sink.finish().unwrap();
+        drop(sink);
+        parse_csv(&String::from_utf8(text).unwrap())
+    }
+
+    #[test]
+    fn placeholders_read_as_missing() {
+        for null_as in [None, Some("")] {
+            let records = round_trip(CsvOptions::default(), null_as).unwrap();
+            let root = &records[0];
+            assert_eq!(root["id"].as_deref(), Some("u1"));
+            for name in [
+                "department",
+                "manager_id",
+                "manager_display_name",
+                "last_sign_in",
+                "last_activity",
+            ] {
+                assert_eq!(root[name], None, "{} with {:?}", name, null_as);
+            }
+            let report = &records[1];
+            assert_eq!(report["department"].as_deref(), Some("Sales"));
+            assert_eq!(report["manager_id"].as_deref(), Some("u1"));
+        }
+    }
+
+    #[test]
+    fn delimiters_are_detected() {
+        for delimiter in [b',', b';', b'\t', b'|', b' '] {
+            for quoting in [Quoting::Necessary, Quoting::Always] {
+                let options = CsvOptions {
+                    delimiter,
+                    quoting,
+                    ..CsvOptions::default()
+                };
+                let records = round_trip(options, None).unwrap();
+                assert_eq!(records[1]["display_name"].as_deref(), Some("Bo"));
+                assert_eq!(records[1]["manager_display_name"].as_deref(), Some("Ann"));
+            }
+        }
+    }
+
+    #[test]
+    fn dumps_without_header_are_refused() {
+        let options = CsvOptions {
+            header: false,
+            ..CsvOptions::default()
+        };
+        let error = round_trip(options, None).unwrap_err();
+        assert!(error.to_string().contains("--csv-no-header"), "{}", error);
+    }
+}
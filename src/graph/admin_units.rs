@@ -0,0 +1,106 @@
+use futures::future::try_join_all;
+use serde::Deserialize;
+
+use super::{DirectoryObject, GraphClient};
+
+/// An administrative unit with its members and scoped role assignments.
+#[derive(Clone, Debug)]
+pub struct AdministrativeUnit {
+    pub id: String,
+    pub display_name: String,
+    pub description: Option<String>,
+    /// `Assigned` or `Dynamic`.
+    pub membership_type: Option<String>,
+    /// `HiddenMembership` when only members see who else is a member.
+    pub visibility: Option<String>,
+    pub members: Vec<DirectoryObject>,
+    pub scoped_roles: Vec<ScopedRole>,
+}
+
+/// A directory role held over the objects of one administrative unit.
+#[derive(Clone, Debug)]
+pub struct ScopedRole {
+    /// Id of the role assignment.
+    pub id: String,
+    /// e.g. `User Administrator`; the role's id if it cannot be looked up.
+    pub role: String,
+    pub member: DirectoryObject,
+}
+
+impl GraphClient {
+    /// Fetches every administrative unit of the tenant with its members and
+    /// scoped role assignments, sorted by name.
+    pub async fn fetch_administrative_units(&self) -> anyhow::Result<Vec<AdministrativeUnit>> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Unit {
+            id: String,
+            display_name: Option<String>,
+            description: Option<String>,
+            membership_type: Option<String>,
+            visibility: Option<String>,
+        }
+
+        let units: Vec<Unit> = self
+            .get_all(format!(
+                "{}/directory/administrativeUnits?$select=id,displayName,description,membershipType,visibility",
+                self.base_url()
+            ))
+            .await
+            .map_err(|e| anyhow::anyhow!("fetching administrative units; {}", e))?;
+        let roles = self.fetch_directory_role_names().await?;
+
+        let mut units = try_join_all(units.into_iter().map(|unit| async {
+            let unit_url = format!(
+                "{}/directory/administrativeUnits/{}",
+                self.base_url(),
+                unit.id
+            );
+            let name = unit.display_name.unwrap_or_else(|| unit.id.clone());
+            let members: Vec<DirectoryObject> = self
+                .get_all(format!(
+                    "{}/members?$select=id,displayName,userPrincipalName",
+                    unit_url
+                ))
+                .await
+                .map_err(|e| anyhow::anyhow!("fetching members of {}; {}", name, e))?;
+
+            #[derive(Deserialize)]
+            #[serde(rename_all = "camelCase")]
+            struct ScopedRoleMembership {
+                id: String,
+                role_id: String,
+                /// An identity: the id and display name only.
+                role_member_info: DirectoryObject,
+            }
+            let memberships: Vec<ScopedRoleMembership> = self
+                .get_all(format!("{}/scopedRoleMembers", unit_url))
+                .await
+                .map_err(|e| anyhow::anyhow!("fetching scoped roles of {}; {}", name, e))?;
+            let scoped_roles = memberships
+                .into_iter()
+                .map(|membership| ScopedRole {
+                    id: membership.id,
+                    role: roles
+                        .get(&membership.role_id)
+                        .cloned()
+                        .unwrap_or(membership.role_id),
+                    member: membership.role_member_info,
+                })
+                .collect();
+
+            anyhow::Ok(AdministrativeUnit {
+                id: unit.id,
+                display_name: name,
+                description: unit.description,
+                membership_type: unit.membership_type,
+                visibility: unit.visibility,
+                members,
+                scoped_roles,
+            })
+        }))
+        .await?;
+        units.sort_by(|a, b| a.display_name.cmp(&b.display_name));
+        Ok(units)
+    }
+}
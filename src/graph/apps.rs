@@ -0,0 +1,142 @@
+use futures::future::try_join_all;
+use serde::Deserialize;
+
+use super::{DirectoryObject, GraphClient};
+
+/// An app registration or service principal with its owners and
+/// credentials.
+#[derive(Clone, Debug)]
+pub struct App {
+    pub id: String,
+    pub kind: AppKind,
+    /// Id of the application, shared by its registration and its service
+    /// principals.
+    pub app_id: Option<String>,
+    pub display_name: String,
+    /// e.g. `AzureADMyOrg`; registrations only.
+    pub sign_in_audience: Option<String>,
+    /// e.g. `Application` or `ManagedIdentity`; service principals only.
+    pub service_principal_type: Option<String>,
+    /// Service principals only.
+    pub account_enabled: Option<bool>,
+    pub created_date_time: Option<String>,
+    pub owners: Vec<DirectoryObject>,
+    pub credentials: Vec<Credential>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AppKind {
+    Application,
+    ServicePrincipal,
+}
+
+impl AppKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AppKind::Application => "application",
+            AppKind::ServicePrincipal => "servicePrincipal",
+        }
+    }
+
+    /// The collection of the objects, e.g. `applications`.
+    fn collection(&self) -> &'static str {
+        match self {
+            AppKind::Application => "applications",
+            AppKind::ServicePrincipal => "servicePrincipals",
+        }
+    }
+}
+
+/// A client secret or certificate an app signs in with.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Credential {
+    pub key_id: Option<String>,
+    pub display_name: Option<String>,
+    /// `Password` for client secrets, e.g. `AsymmetricX509Cert` for
+    /// certificates.
+    #[serde(rename = "type", default = "password")]
+    pub credential_type: String,
+    pub start_date_time: Option<String>,
+    pub end_date_time: Option<String>,
+}
+
+fn password() -> String {
+    "Password".to_string()
+}
+
+impl GraphClient {
+    /// Fetches every app registration, then every service principal, each
+    /// with its owners and credentials and sorted by name.
+    pub async fn fetch_apps(&self) -> anyhow::Result<Vec<App>> {
+        let mut apps = self.fetch_apps_of(AppKind::Application).await?;
+        apps.extend(self.fetch_apps_of(AppKind::ServicePrincipal).await?);
+        Ok(apps)
+    }
+
+    async fn fetch_apps_of(&self, kind: AppKind) -> anyhow::Result<Vec<App>> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Object {
+            id: String,
+            app_id: Option<String>,
+            display_name: Option<String>,
+            sign_in_audience: Option<String>,
+            service_principal_type: Option<String>,
+            account_enabled: Option<bool>,
+            created_date_time: Option<String>,
+            #[serde(default)]
+            password_credentials: Vec<Credential>,
+            #[serde(default)]
+            key_credentials: Vec<Credential>,
+        }
+
+        let select = match kind {
+            AppKind::Application => {
+                "id,appId,displayName,signInAudience,createdDateTime,passwordCredentials,keyCredentials"
+            }
+            AppKind::ServicePrincipal => {
+                "id,appId,displayName,servicePrincipalType,accountEnabled,createdDateTime,passwordCredentials,keyCredentials"
+            }
+        };
+        let objects: Vec<Object> = self
+            .get_all(format!(
+                "{}/{}?$select={}",
+                self.base_url(),
+                kind.collection(),
+                select
+            ))
+            .await
+            .map_err(|e| anyhow::anyhow!("fetching {}; {}", kind.collection(), e))?;
+
+        let mut apps = try_join_all(objects.into_iter().map(|object| async move {
+            let name = object.display_name.unwrap_or_else(|| object.id.clone());
+            let owners = self
+                .get_all(format!(
+                    "{}/{}/{}/owners?$select=id,displayName,userPrincipalName",
+                    self.base_url(),
+                    kind.collection(),
+                    object.id
+                ))
+                .await
+                .map_err(|e| anyhow::anyhow!("fetching owners of {}; {}", name, e))?;
+            let mut credentials = object.password_credentials;
+            credentials.extend(object.key_credentials);
+            anyhow::Ok(App {
+                id: object.id,
+                kind,
+                app_id: object.app_id,
+                display_name: name,
+                sign_in_audience: object.sign_in_audience,
+                service_principal_type: object.service_principal_type,
+                account_enabled: object.account_enabled,
+                created_date_time: object.created_date_time,
+                owners,
+                credentials,
+            })
+        }))
+        .await?;
+        apps.sort_by(|a, b| a.display_name.cmp(&b.display_name));
+        Ok(apps)
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::try_join_all;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::{retry, GraphClient, VisitSender, Visited};
+use crate::model::{User, UsersResponse};
+
+/// Most requests Graph accepts in one `$batch` call.
+pub const MAX_BATCH_SIZE: usize = 20;
+
+#[derive(Deserialize)]
+pub(super) struct BatchResponse {
+    pub(super) responses: Vec<SubResponse>,
+}
+
+#[derive(Deserialize)]
+pub(super) struct SubResponse {
+    pub(super) id: String,
+    pub(super) status: u16,
+    #[serde(default)]
+    headers: HashMap<String, String>,
+    #[serde(default)]
+    pub(super) body: Value,
+}
+
+impl GraphClient {
+    /// Fetches the direct reports of up to [`MAX_BATCH_SIZE`] managers per
+    /// `$batch` call, following `@odata.nextLink` for large teams.
+    ///
+    /// Throttled and transiently failing sub-requests are resubmitted in a
+    /// later batch; other failures fail the whole call.
+    pub async fn fetch_direct_reports_batch(
+        &self,
+        managers: &[Arc<User>],
+    ) -> anyhow::Result<Vec<Vec<User>>> {
+        let mut results = Vec::with_capacity(managers.len());
+        for page in self.direct_reports_first_pages(managers, false).await? {
+            let page: UsersResponse = serde_json::from_value(page)?;
+            if let Some(progress) = &self.progress {
+                progress.add_users(page.value.len());
+            }
+            let mut reportees = page.value;
+            if let Some(next_link) = page.next_link {
+                reportees.extend(self.fetch_all_pages(next_link).await?);
+            }
+            results.push(reportees);
+        }
+        Ok(results)
+    }
+
+    /// Fetches the first page of direct reports of each of `managers` with
+    /// `$batch`, or with `probe` only whether there is any report, like
+    /// [`has_direct_reports`](GraphClient::has_direct_reports).
+    async fn direct_reports_first_pages(
+        &self,
+        managers: &[Arc<User>],
+        probe: bool,
+    ) -> anyhow::Result<Vec<Value>> {
+        let mut results: Vec<Option<Value>> = vec![None; managers.len()];
+        let mut pending: Vec<usize> = (0..managers.len()).collect();
+        let mut attempt = 0;
+
+        while !pending.is_empty() {
+            let mut retry = Vec::new();
+            let mut retry_after = Duration::ZERO;
+
+            for chunk in pending.chunks(MAX_BATCH_SIZE) {
+                let requests: Vec<Value> = chunk
+                    .iter()
+                    .map(|&i| {
+                        let url = match probe {
+                            true => self.direct_reports_probe_url(&managers[i].id)?,
+                            false => self.direct_reports_url(&managers[i].id)?,
+                        };
+                        // sub-request URLs are relative to the endpoint
+                        let url = &url.as_str()[self.base_url().len()..];
+                        let mut request = json!({
+                            "id": i.to_string(),
+                            "method": "GET",
+                            "url": url,
+                        });
+                        if self.odata_filter.is_some() && !probe {
+                            request["headers"] = json!({ "ConsistencyLevel": "eventual" });
+                        }
+                        Ok(request)
+                    })
+                    .collect::<anyhow::Result<_>>()?;
+
+                let url = format!("{}/$batch", self.base_url());
+                let response: BatchResponse = self
+                    .post_query(&url, &json!({ "requests": requests }))
+                    .await
+                    .map_err(|e| anyhow::anyhow!("fetching direct reports batch; {}", e))?;
+
+                for sub in response.responses {
+                    let i: usize = sub.id.parse()?;
+                    if (200..300).contains(&sub.status) {
+                        results[i] = Some(sub.body);
+                    } else if StatusCode::from_u16(sub.status).is_ok_and(retry::is_retryable_status)
+                        && attempt < self.max_retries
+                    {
+                        let delay =
+                            retry::delay(sub_retry_after(&sub), attempt, self.max_retry_after);
+                        retry_after = retry_after.max(delay);
+                        retry.push(i);
+                    } else {
+                        anyhow::bail!(
+                            "fetching direct reports of {}; {}: {}",
+                            managers[i].id,
+                            sub.status,
+                            sub.body
+                        )
+                    }
+                }
+            }
+
+            if !retry.is_empty() {
+                tokio::time::sleep(retry_after).await;
+            }
+            pending = retry;
+            attempt += 1;
+        }
+
+        Ok(results.into_iter().map(Option::unwrap_or_default).collect())
+    }
+
+    /// Walks the reporting trees below `managers` level by level, fetching
+    /// each level's direct reports with `$batch`; batches of a level run
+    /// concurrently.
+    pub(super) async fn walk_tree_batched(
+        &self,
+        managers: Vec<Arc<User>>,
+        tx: VisitSender,
+        visited: &Visited,
+    ) -> anyhow::Result<()> {
+        let mut level = managers;
+
+        while !level.is_empty() {
+            let batches = level
+                .chunks(MAX_BATCH_SIZE)
+                .map(|managers| self.fetch_direct_reports_batch(managers));
+            let mut reports: Vec<Vec<User>> =
+                try_join_all(batches).await?.into_iter().flatten().collect();
+            for (manager, reportees) in level.iter().zip(&mut reports) {
+                for reportee in reportees.iter_mut() {
+                    reportee.set_walk_manager(manager);
+                }
+                visited.retain_new(reportees);
+            }
+            if self.mark_truncated {
+                self.mark_truncated_batch(&mut reports).await?;
+            }
+            for reportees in &mut reports {
+                self.add_details(reportees).await?;
+            }
+
+            let mut next_level = Vec::new();
+            for (manager, reportees) in level.iter().zip(reports) {
+                let reportees: Vec<Arc<User>> = reportees.into_iter().map(Arc::new).collect();
+                next_level.extend(
+                    reportees
+                        .iter()
+                        .filter(|reportee| !self.at_depth_limit(reportee))
+                        .cloned(),
+                );
+                tx.send((manager.clone(), reportees))
+                    .map_err(|_| anyhow::anyhow!("reportee tree walk was cancelled"))?;
+            }
+            level = next_level;
+        }
+
+        Ok(())
+    }
+
+    /// Flags the reportees at the depth limit that have reports of their
+    /// own, asking for one report of each with `$batch`.
+    async fn mark_truncated_batch(&self, reports: &mut [Vec<User>]) -> anyhow::Result<()> {
+        let frontier: Vec<Arc<User>> = reports
+            .iter()
+            .flatten()
+            .filter(|reportee| self.at_depth_limit(reportee))
+            .map(|reportee| Arc::new(reportee.clone()))
+            .collect();
+        let batches = frontier
+            .chunks(MAX_BATCH_SIZE)
+            .map(|managers| self.direct_reports_first_pages(managers, true));
+        let truncated: HashSet<&str> = frontier
+            .iter()
+            .zip(try_join_all(batches).await?.into_iter().flatten())
+            .filter(|(_, page)| page["value"].as_array().is_some_and(|r| !r.is_empty()))
+            .map(|(user, _)| user.id.as_str())
+            .collect();
+
+        for reportee in reports.iter_mut().flatten() {
+            if truncated.contains(reportee.id.as_str()) {
+                reportee.truncated = true;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Delay requested by a throttled sub-request's `Retry-After` header.
+pub(super) fn sub_retry_after(sub: &SubResponse) -> Option<Duration> {
+    sub.headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("retry-after"))
+        .and_then(|(_, value)| retry::retry_after_value(value))
+}
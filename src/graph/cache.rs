@@ -0,0 +1,172 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
+
+use openssl::sha::sha256;
+
+/// Tells apart the temporary files of concurrent writers in this process.
+static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// On-disk cache of Graph GET responses, one file per URL and identity.
+///
+/// Entries older than the TTL are ignored and overwritten. Only meant to
+/// speed up repeated runs during development. The files contain directory
+/// data, so the directory is only accessible to the current user.
+pub struct ResponseCache {
+    dir: PathBuf,
+    ttl: Duration,
+    /// The cloud, tenant and signed-in identity the responses were fetched
+    /// as; part of every key, so another identity never sees them.
+    identity: String,
+}
+
+impl ResponseCache {
+    pub fn new(dir: impl Into<PathBuf>, ttl: Duration) -> anyhow::Result<Self> {
+        let dir = dir.into();
+        create_private_dir(&dir)
+            .map_err(|e| anyhow::anyhow!("creating cache directory {}; {}", dir.display(), e))?;
+        Ok(Self {
+            dir,
+            ttl,
+            identity: String::new(),
+        })
+    }
+
+    /// Keys the entries by `identity`, e.g. the Graph host, tenant id and
+    /// object id of the signed-in user or app.
+    pub fn with_identity(mut self, identity: impl Into<String>) -> Self {
+        self.identity = identity.into();
+        self
+    }
+
+    /// Default cache location in the user's cache directory, e.g.
+    /// `~/.cache/microsoft-graph-reportee-dump`.
+    pub fn default_dir() -> PathBuf {
+        let var = |name: &str| std::env::var_os(name).filter(|value| !value.is_empty());
+        var("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| var("LOCALAPPDATA").map(PathBuf::from))
+            .or_else(|| var("HOME").map(|home| PathBuf::from(home).join(".cache")))
+            .unwrap_or_else(std::env::temp_dir)
+            .join("microsoft-graph-reportee-dump")
+    }
+
+    /// Returns the cached body for `url` if it is younger than the TTL.
+    pub async fn get(&self, url: &str) -> Option<Vec<u8>> {
+        let path = self.path(url);
+        let modified = tokio::fs::metadata(&path).await.ok()?.modified().ok()?;
+        let age = SystemTime::now()
+            .duration_since(modified)
+            .unwrap_or_default();
+        if age > self.ttl {
+            return None;
+        }
+        tokio::fs::read(&path).await.ok()
+    }
+
+    /// Stores the body for `url`; failures only cost a cache miss later.
+    pub async fn put(&self, url: &str, body: &[u8]) {
+        let path = self.path(url);
+        // a name of its own, as other runs may write the same URL
+        let tmp_path = path.with_extension(format!(
+            "{}.{}.tmp",
+            std::process::id(),
+            TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        match write_private(&tmp_path, body).await {
+            Ok(()) => {
+                if tokio::fs::rename(&tmp_path, &path).await.is_err() {
+                    let _ = tokio::fs::remove_file(&tmp_path).await;
+                }
+            }
+            Err(_) => {
+                let _ = tokio::fs::remove_file(&tmp_path).await;
+            }
+        }
+    }
+
+    fn path(&self, url: &str) -> PathBuf {
+        let hash: String = sha256(format!("{}\n{}", self.identity, url).as_bytes())
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        self.dir.join(format!("{}.json", hash))
+    }
+}
+
+/// Creates `dir` if needed and makes it accessible to the current user
+/// only.
+fn create_private_dir(dir: &std::path::Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700))?;
+    }
+    Ok(())
+}
+
+/// Writes a file readable by the current user only.
+async fn write_private(path: &std::path::Path, body: &[u8]) -> std::io::Result<()> {
+    use tokio::io::AsyncWriteExt;
+
+    let mut options = tokio::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(path).await?;
+    file.write_all(body).await?;
+    file.flush().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("graph-cache-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[tokio::test]
+    async fn identities_have_their_own_entries() {
+        let dir = dir("identities");
+        let ttl = Duration::from_secs(60);
+        let alice = ResponseCache::new(&dir, ttl)
+            .unwrap()
+            .with_identity("tenant-a alice");
+        let bob = ResponseCache::new(&dir, ttl)
+            .unwrap()
+            .with_identity("tenant-b bob");
+        let url = "https://graph.microsoft.com/v1.0/users";
+        alice.put(url, b"alice's").await;
+        assert_eq!(alice.get(url).await.as_deref(), Some(&b"alice's"[..]));
+        assert_eq!(bob.get(url).await, None);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn entries_are_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = dir("private");
+        let cache = ResponseCache::new(&dir, Duration::from_secs(60)).unwrap();
+        cache
+            .put("https://graph.microsoft.com/v1.0/me", b"{}")
+            .await;
+        let mode =
+            |path: &std::path::Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&dir), 0o700);
+        let files: Vec<PathBuf> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        // no temporary files are left behind
+        assert_eq!(files.len(), 1);
+        assert_eq!(mode(&files[0]), 0o600);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
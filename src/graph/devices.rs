@@ -0,0 +1,55 @@
+use clap::ValueEnum;
+use futures::future::try_join_all;
+
+use super::GraphClient;
+use crate::model::{Device, User};
+
+/// Which devices of a user are listed in the `devices` column.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Devices {
+    /// Devices the user owns (`ownedDevices`), usually the ones they joined.
+    Owned,
+    /// Devices registered to the user (`registeredDevices`), e.g. personal
+    /// phones.
+    Registered,
+}
+
+impl GraphClient {
+    /// Fetches the `devices` of `user`, sorted by name.
+    pub async fn fetch_devices(
+        &self,
+        user: &User,
+        devices: Devices,
+    ) -> anyhow::Result<Vec<Device>> {
+        let relationship = match devices {
+            Devices::Owned => "ownedDevices",
+            Devices::Registered => "registeredDevices",
+        };
+        // the cast leaves out other directory objects, e.g. app roles
+        let mut url = self.user_url(&user.id, &[relationship, "microsoft.graph.device"])?;
+        url.query_pairs_mut().append_pair(
+            "$select",
+            "displayName,operatingSystem,operatingSystemVersion,isCompliant",
+        );
+        let mut devices: Vec<Device> = self
+            .get_all(url.to_string())
+            .await
+            .map_err(|e| anyhow::anyhow!("fetching devices of {}; {}", user.id, e))?;
+        devices.sort_by(|a, b| a.display_name.cmp(&b.display_name));
+        Ok(devices)
+    }
+
+    /// Fills in the devices of `users` when configured with
+    /// [`with_devices`](Self::with_devices).
+    pub(super) async fn add_devices(&self, users: &mut [User]) -> anyhow::Result<()> {
+        let Some(devices) = self.devices else {
+            return Ok(());
+        };
+        let fetches = users.iter().map(|user| self.fetch_devices(user, devices));
+        let fetched = try_join_all(fetches).await?;
+        for (user, devices) in users.iter_mut().zip(fetched) {
+            user.devices = devices;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,104 @@
+use std::fmt::{self, Display};
+use std::time::Duration;
+
+use super::batch::MAX_BATCH_SIZE;
+use super::presence::MAX_PRESENCE_IDS;
+use super::GraphClient;
+
+/// How a dump fetches its users.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fetch {
+    /// Walking `directReports` down from the roots.
+    Walk,
+    /// Paging through a list, as `--all-users` and `--group` do.
+    List,
+}
+
+/// Expected size and cost of a dump, for `--dry-run`.
+#[derive(Clone, Debug)]
+pub struct Estimate {
+    pub users: u64,
+    pub requests: u64,
+    pub duration: Duration,
+    pub concurrency: usize,
+    /// Requests per second allowed by `--rps`, if limited.
+    pub rate_limit: Option<f64>,
+    /// Whether the numbers are upper bounds, e.g. with `--max-depth`.
+    pub at_most: bool,
+}
+
+impl GraphClient {
+    /// Estimates the requests and time needed to fetch `users` users with
+    /// the configured details, from the `latency` of a single request.
+    pub fn estimate(&self, users: u64, fetch: Fetch, latency: Duration) -> Estimate {
+        let mut requests = match (fetch, self.batch) {
+            // every user is asked for their reports, leaves included
+            (Fetch::Walk, false) => users,
+            (Fetch::Walk, true) => users.div_ceil(MAX_BATCH_SIZE as u64),
+            (Fetch::List, _) => users.div_ceil(self.list_page_size() as u64).max(1),
+        };
+        // one request per user each
+        let per_user = [
+            self.memberships.is_some(),
+            self.devices.is_some(),
+            self.joined_teams,
+            self.mailbox_settings,
+            self.photo_dir.is_some(),
+        ];
+        requests += per_user.iter().filter(|&&enabled| enabled).count() as u64 * users;
+        if self.presence {
+            requests += match fetch {
+                // one request per manager's reports, and any walked user
+                // may be a manager
+                Fetch::Walk => users,
+                Fetch::List => users.div_ceil(MAX_PRESENCE_IDS as u64),
+            };
+        }
+        if self.licenses {
+            requests += 1;
+        }
+        if self.usage_period.is_some() {
+            requests += 1;
+        }
+        if self.mfa {
+            requests += 1;
+        }
+
+        let concurrency = self.concurrency.limit();
+        let mut duration = latency.mul_f64(requests as f64 / concurrency as f64);
+        let rate_limit = self.rate_limit.as_ref().map(|limiter| limiter.per_second());
+        if let Some(per_second) = rate_limit {
+            duration = duration.max(Duration::from_secs_f64(requests as f64 / per_second));
+        }
+        Estimate {
+            users,
+            requests,
+            duration,
+            concurrency,
+            rate_limit,
+            at_most: self.max_depth.is_some(),
+        }
+    }
+}
+
+impl Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let about = if self.at_most { "at most" } else { "about" };
+        writeln!(f, "Users: {} {}", about, self.users)?;
+        writeln!(f, "Requests: {} {}", about, self.requests)?;
+        let secs = self.duration.as_secs();
+        write!(
+            f,
+            "Time: {} {}h {:02}m {:02}s at {} concurrent requests",
+            about,
+            secs / 3600,
+            secs / 60 % 60,
+            secs % 60,
+            self.concurrency
+        )?;
+        if let Some(per_second) = self.rate_limit {
+            write!(f, " and {} requests/s", per_second)?;
+        }
+        writeln!(f, ", unless throttled")
+    }
+}
@@ -0,0 +1,66 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::GraphClient;
+use crate::model::User;
+
+impl GraphClient {
+    /// Fetches the SKUs the tenant subscribes to, mapping each SKU id to its
+    /// part number, e.g. `SPE_E5`.
+    pub async fn fetch_sku_names(&self) -> anyhow::Result<HashMap<String, String>> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Sku {
+            sku_id: String,
+            sku_part_number: String,
+        }
+
+        #[derive(Deserialize)]
+        struct Skus {
+            value: Vec<Sku>,
+        }
+
+        let url = format!(
+            "{}/subscribedSkus?$select=skuId,skuPartNumber",
+            self.base_url()
+        );
+        let skus: Skus = self
+            .get(&url)
+            .await
+            .map_err(|e| anyhow::anyhow!("fetching subscribed SKUs; {}", e))?;
+        Ok(skus
+            .value
+            .into_iter()
+            .map(|sku| (sku.sku_id, sku.sku_part_number))
+            .collect())
+    }
+
+    /// Names the licenses in the `assignedLicenses` of `users` when
+    /// configured with [`with_licenses`](Self::with_licenses). SKUs the
+    /// tenant no longer subscribes to keep their id.
+    pub(super) async fn add_license_names(&self, users: &mut [User]) -> anyhow::Result<()> {
+        if !self.licenses {
+            return Ok(());
+        }
+        let names = self
+            .sku_names
+            .get_or_try_init(|| self.fetch_sku_names())
+            .await?;
+        for user in users {
+            let assigned = user.properties.get("assignedLicenses");
+            let sku_ids = assigned
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(|license| license["skuId"].as_str());
+            let mut licenses: Vec<String> = sku_ids
+                .map(|id| names.get(id).cloned().unwrap_or_else(|| id.to_string()))
+                .collect();
+            licenses.sort();
+            user.licenses = licenses;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,360 @@
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use reqwest::{Method, StatusCode, Url};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+pub use super::batch::MAX_BATCH_SIZE;
+use super::batch::{sub_retry_after, BatchResponse};
+use super::{retry, GraphClient};
+
+/// A SharePoint list, by the URL of its site and its name, e.g.
+/// `https://contoso.sharepoint.com/sites/HR/Lists/Org Chart`.
+#[derive(Clone, Debug)]
+pub struct ListUrl {
+    /// The host name of the site collection, e.g. `contoso.sharepoint.com`.
+    host: String,
+    /// The server-relative path of the site, e.g. `/sites/HR`; empty for
+    /// the root site.
+    site_path: String,
+    /// The display or URL name of the list.
+    name: String,
+}
+
+impl ListUrl {
+    /// Parses the URL of a list as shown in the browser; a view page such
+    /// as `.../Lists/Org/AllItems.aspx` is ignored.
+    pub fn parse(url: &str) -> anyhow::Result<Self> {
+        let url: Url = url
+            .parse()
+            .map_err(|e| anyhow::anyhow!("parsing the list URL {}; {}", url, e))?;
+        let host = url
+            .host_str()
+            .ok_or_else(|| anyhow::anyhow!("{} has no host", url))?
+            .to_string();
+        let segments: Vec<String> = url
+            .path_segments()
+            .into_iter()
+            .flatten()
+            .map(|segment| String::from_utf8_lossy(&percent_decode(segment)).into_owned())
+            .collect();
+        let lists = segments
+            .iter()
+            .position(|segment| segment.eq_ignore_ascii_case("Lists"))
+            .filter(|&i| i + 1 < segments.len())
+            .ok_or_else(|| {
+                anyhow::anyhow!("{} is not a list URL like https://<site>/Lists/<name>", url)
+            })?;
+        let site_path = segments[..lists]
+            .iter()
+            .map(|segment| format!("/{}", segment))
+            .collect();
+        Ok(Self {
+            host,
+            site_path,
+            name: segments[lists + 1].clone(),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl std::fmt::Display for ListUrl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "https://{}{}/Lists/{}",
+            self.host, self.site_path, self.name
+        )
+    }
+}
+
+pub(crate) fn percent_decode(s: &str) -> Vec<u8> {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    decoded
+}
+
+/// A list found or created by [`GraphClient::open_list`].
+#[derive(Clone, Debug)]
+pub struct SharePointList {
+    pub site_id: String,
+    pub list_id: String,
+    pub web_url: String,
+}
+
+/// A change to an item of a list.
+pub enum ListItemWrite {
+    Create(Map<String, Value>),
+    /// Sets the fields of the item with the id.
+    Update(String, Map<String, Value>),
+    Delete(String),
+}
+
+#[derive(Deserialize)]
+struct Page<T> {
+    value: Vec<T>,
+    #[serde(rename = "@odata.nextLink")]
+    next_link: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListInfo {
+    id: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    display_name: String,
+    #[serde(default)]
+    web_url: String,
+}
+
+#[derive(Deserialize)]
+struct ColumnInfo {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct Item {
+    id: String,
+    #[serde(default)]
+    fields: Map<String, Value>,
+}
+
+impl GraphClient {
+    /// Finds the list of `url` by display or URL name, creating it on the
+    /// site if there is none, and adds the single line of text `columns` it
+    /// lacks.
+    pub async fn open_list(
+        &self,
+        url: &ListUrl,
+        columns: &[String],
+    ) -> anyhow::Result<SharePointList> {
+        #[derive(Deserialize)]
+        struct Site {
+            id: String,
+        }
+
+        let site_url = match url.site_path.as_str() {
+            "" => format!("{}/sites/{}", self.base_url(), url.host),
+            path => format!("{}/sites/{}:{}", self.base_url(), url.host, path),
+        };
+        let site: Site = self
+            .request(Method::GET, &site_url, None, None)
+            .await
+            .map_err(|e| anyhow::anyhow!("fetching site {}{}; {}", url.host, url.site_path, e))?;
+
+        let lists_url = format!("{}/sites/{}/lists", self.base_url(), site.id);
+        let lists: Vec<ListInfo> = self
+            .fetch_pages(format!("{}?$select=id,name,displayName,webUrl", lists_url))
+            .await
+            .map_err(|e| anyhow::anyhow!("fetching the lists of {}; {}", url, e))?;
+        let existing = lists.into_iter().find(|list| {
+            list.display_name.eq_ignore_ascii_case(&url.name)
+                || list.name.eq_ignore_ascii_case(&url.name)
+        });
+        let list = match existing {
+            Some(list) => list,
+            None => {
+                let body = json!({
+                    "displayName": url.name,
+                    "columns": columns
+                        .iter()
+                        .map(|name| json!({ "name": name, "text": {} }))
+                        .collect::<Vec<_>>(),
+                    "list": { "template": "genericList" },
+                });
+                let list: ListInfo = self
+                    .request(Method::POST, &lists_url, Some(&body), None)
+                    .await
+                    .map_err(|e| anyhow::anyhow!("creating list {}; {}", url, e))?;
+                tracing::info!("Created list {}", list.web_url);
+                list
+            }
+        };
+
+        let columns_url = format!("{}/{}/columns", lists_url, list.id);
+        let present: HashSet<String> = self
+            .fetch_pages::<ColumnInfo>(format!("{}?$select=name", columns_url))
+            .await
+            .map_err(|e| anyhow::anyhow!("fetching the columns of {}; {}", url, e))?
+            .into_iter()
+            .map(|column| column.name.to_lowercase())
+            .collect();
+        for name in columns {
+            if present.contains(&name.to_lowercase()) {
+                continue;
+            }
+            self.request::<Value>(
+                Method::POST,
+                &columns_url,
+                Some(&json!({ "name": name, "text": {} })),
+                None,
+            )
+            .await
+            .map_err(|e| anyhow::anyhow!("adding column {} to {}; {}", name, url, e))?;
+        }
+
+        Ok(SharePointList {
+            site_id: site.id,
+            list_id: list.id,
+            web_url: list.web_url,
+        })
+    }
+
+    /// The ids of the items of `list` by the value of their `key` field.
+    pub async fn fetch_list_items(
+        &self,
+        list: &SharePointList,
+        key: &str,
+    ) -> anyhow::Result<HashMap<String, String>> {
+        let url = format!(
+            "{}?$expand=fields($select={})&$top=999",
+            self.list_items_url(list),
+            key
+        );
+        let items: Vec<Item> = self.fetch_pages(url).await?;
+        Ok(items
+            .into_iter()
+            .filter_map(|item| match item.fields.get(key) {
+                Some(Value::String(value)) => Some((value.clone(), item.id)),
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// Applies up to [`MAX_BATCH_SIZE`] writes per `$batch` call and returns
+    /// the ids of the created items, `None` for the other writes.
+    ///
+    /// Throttled and transiently failing writes are resubmitted in a later
+    /// batch; other failures fail the whole call.
+    pub async fn write_list_items(
+        &self,
+        list: &SharePointList,
+        writes: &[ListItemWrite],
+    ) -> anyhow::Result<Vec<Option<String>>> {
+        // sub-request URLs are relative to the endpoint
+        let items_url = &self.list_items_url(list)[self.base_url().len()..];
+        let mut created = vec![None; writes.len()];
+        let mut pending: Vec<usize> = (0..writes.len()).collect();
+        let mut attempt = 0;
+
+        while !pending.is_empty() {
+            let mut retry = Vec::new();
+            let mut retry_after = Duration::ZERO;
+
+            for chunk in pending.chunks(MAX_BATCH_SIZE) {
+                let requests: Vec<Value> = chunk
+                    .iter()
+                    .map(|&i| {
+                        let id = i.to_string();
+                        let headers = json!({ "Content-Type": "application/json" });
+                        match &writes[i] {
+                            ListItemWrite::Create(fields) => json!({
+                                "id": id,
+                                "method": "POST",
+                                "url": items_url,
+                                "headers": headers,
+                                "body": { "fields": fields },
+                            }),
+                            ListItemWrite::Update(item, fields) => json!({
+                                "id": id,
+                                "method": "PATCH",
+                                "url": format!("{}/{}/fields", items_url, item),
+                                "headers": headers,
+                                "body": fields,
+                            }),
+                            ListItemWrite::Delete(item) => json!({
+                                "id": id,
+                                "method": "DELETE",
+                                "url": format!("{}/{}", items_url, item),
+                            }),
+                        }
+                    })
+                    .collect();
+
+                let url = format!("{}/$batch", self.base_url());
+                let response: BatchResponse = self
+                    .post(&url, &json!({ "requests": requests }))
+                    .await
+                    .map_err(|e| anyhow::anyhow!("writing list items batch; {}", e))?;
+
+                for sub in response.responses {
+                    let i: usize = sub.id.parse()?;
+                    let status = StatusCode::from_u16(sub.status)?;
+                    if status.is_success() {
+                        if let ListItemWrite::Create(_) = writes[i] {
+                            created[i] = sub.body["id"].as_str().map(str::to_string);
+                        }
+                    } else if status == StatusCode::NOT_FOUND
+                        && matches!(writes[i], ListItemWrite::Delete(_))
+                    {
+                        // deleted by someone else meanwhile
+                    } else if retry::is_retryable_status(status) && attempt < self.max_retries {
+                        let delay =
+                            retry::delay(sub_retry_after(&sub), attempt, self.max_retry_after);
+                        retry_after = retry_after.max(delay);
+                        retry.push(i);
+                    } else {
+                        anyhow::bail!("writing list item; {}: {}", sub.status, sub.body)
+                    }
+                }
+            }
+
+            if !retry.is_empty() {
+                tokio::time::sleep(retry_after).await;
+            }
+            pending = retry;
+            attempt += 1;
+        }
+
+        Ok(created)
+    }
+
+    fn list_items_url(&self, list: &SharePointList) -> String {
+        format!(
+            "{}/sites/{}/lists/{}/items",
+            self.base_url(),
+            list.site_id,
+            list.list_id
+        )
+    }
+
+    /// Fetches every page of a collection, bypassing the response cache as
+    /// lists change under the tool.
+    async fn fetch_pages<T: for<'de> Deserialize<'de>>(
+        &self,
+        mut url: String,
+    ) -> anyhow::Result<Vec<T>> {
+        let mut values = Vec::new();
+        loop {
+            let page: Page<T> = self.request(Method::GET, &url, None, None).await?;
+            values.extend(page.value);
+            match page.next_link {
+                Some(next_link) => url = next_link,
+                None => return Ok(values),
+            }
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use base64::Engine;
+use reqwest::header::CONTENT_RANGE;
+use reqwest::Method;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::GraphClient;
+
+/// Largest attachment sent inline with `sendMail`; the request may not
+/// exceed 4 MB once base64 encoded.
+const MAX_INLINE_ATTACHMENT: usize = 3 * 1024 * 1024;
+
+/// Bytes per request of an attachment upload session; a multiple of
+/// 320 KiB as Graph requires.
+const UPLOAD_CHUNK: usize = 10 * 320 * 1024;
+
+/// A file attached to a message.
+pub struct Attachment {
+    pub name: String,
+    pub content: Vec<u8>,
+}
+
+/// A plain text message with one attachment.
+pub struct Mail<'a> {
+    pub to: &'a [String],
+    pub subject: &'a str,
+    pub body: &'a str,
+    pub attachment: Attachment,
+}
+
+impl GraphClient {
+    /// Sends `mail` from the mailbox of `from`, or of the signed-in user.
+    /// Attachments over 3 MB are added to a draft through an upload
+    /// session, which is then sent.
+    pub async fn send_mail(&self, from: Option<&str>, mail: Mail<'_>) -> anyhow::Result<()> {
+        let mailbox = match from {
+            Some(from) => self.user_url(from, &[])?.to_string(),
+            None => format!("{}/me", self.base_url()),
+        };
+        let recipients: Vec<Value> = mail
+            .to
+            .iter()
+            .map(|address| json!({ "emailAddress": { "address": address } }))
+            .collect();
+        let mut message = json!({
+            "subject": mail.subject,
+            "body": { "contentType": "Text", "content": mail.body },
+            "toRecipients": recipients,
+        });
+
+        if mail.attachment.content.len() <= MAX_INLINE_ATTACHMENT {
+            message["attachments"] = json!([{
+                "@odata.type": "#microsoft.graph.fileAttachment",
+                "name": mail.attachment.name,
+                "contentType": "application/octet-stream",
+                "contentBytes": base64::engine::general_purpose::STANDARD
+                    .encode(&mail.attachment.content),
+            }]);
+            return self
+                .request::<Value>(
+                    Method::POST,
+                    &format!("{}/sendMail", mailbox),
+                    Some(&json!({ "message": message })),
+                    None,
+                )
+                .await
+                .map(drop)
+                .map_err(|e| anyhow::anyhow!("sending mail; {}", e));
+        }
+
+        #[derive(Deserialize)]
+        struct Draft {
+            id: String,
+        }
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct UploadSession {
+            upload_url: String,
+        }
+
+        let draft: Draft = self
+            .request(
+                Method::POST,
+                &format!("{}/messages", mailbox),
+                Some(&message),
+                None,
+            )
+            .await
+            .map_err(|e| anyhow::anyhow!("creating draft; {}", e))?;
+        let message_url = format!("{}/messages/{}", mailbox, draft.id);
+        let session: UploadSession = self
+            .request(
+                Method::POST,
+                &format!("{}/attachments/createUploadSession", message_url),
+                Some(&json!({
+                    "AttachmentItem": {
+                        "attachmentType": "file",
+                        "name": mail.attachment.name,
+                        "size": mail.attachment.content.len(),
+                    }
+                })),
+                None,
+            )
+            .await
+            .map_err(|e| anyhow::anyhow!("creating attachment upload session; {}", e))?;
+
+        let size = mail.attachment.content.len();
+        for (i, chunk) in mail.attachment.content.chunks(UPLOAD_CHUNK).enumerate() {
+            let start = i * UPLOAD_CHUNK;
+            // the upload URL is pre-authorized and must not get the token
+            let response = self
+                .client
+                .put(&session.upload_url)
+                .header(
+                    CONTENT_RANGE,
+                    format!("bytes {}-{}/{}", start, start + chunk.len() - 1, size),
+                )
+                .body(chunk.to_vec())
+                .send()
+                .await?;
+            if !response.status().is_success() {
+                let status = response.status();
+                let response_txt = response.text().await?;
+                anyhow::bail!("uploading attachment; {}: {}", status, response_txt)
+            }
+        }
+
+        // an empty body still needs a length
+        self.request::<Value>(
+            Method::POST,
+            &format!("{}/send", message_url),
+            Some(&json!({})),
+            None,
+        )
+        .await
+        .map_err(|e| anyhow::anyhow!("sending mail; {}", e))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,164 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use futures::future::try_join_all;
+use reqwest::{Method, StatusCode};
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::GraphClient;
+use crate::model::{MailboxSettings, User};
+
+impl GraphClient {
+    /// Fetches the time zone, locale and automatic reply status and schedule
+    /// of the mailbox of `user`; `None` if the user has no mailbox Graph can
+    /// read.
+    pub async fn fetch_mailbox_settings(
+        &self,
+        user: &User,
+    ) -> anyhow::Result<Option<MailboxSettings>> {
+        #[derive(Deserialize)]
+        struct Locale {
+            locale: Option<String>,
+        }
+
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct AutomaticReplies {
+            status: Option<String>,
+            scheduled_start_date_time: Option<DateTimeTimeZone>,
+            scheduled_end_date_time: Option<DateTimeTimeZone>,
+        }
+
+        /// A time as Graph writes it, e.g. `2024-07-01T08:00:00.0000000`
+        /// in `UTC`, which is the zone of automatic reply schedules.
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct DateTimeTimeZone {
+            date_time: String,
+        }
+
+        impl DateTimeTimeZone {
+            fn utc(&self) -> Option<DateTime<Utc>> {
+                NaiveDateTime::parse_from_str(&self.date_time, "%Y-%m-%dT%H:%M:%S%.f")
+                    .ok()
+                    .map(|at| at.and_utc())
+            }
+        }
+
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Settings {
+            time_zone: Option<String>,
+            language: Option<Locale>,
+            automatic_replies_setting: Option<AutomaticReplies>,
+        }
+
+        let mut url = self.user_url(&user.id, &["mailboxSettings"])?;
+        url.query_pairs_mut()
+            .append_pair("$select", "timeZone,language,automaticRepliesSetting");
+        let response = self
+            .send_with_retries(&Method::GET, url.as_str(), None, true)
+            .await?;
+        // throttling was retried already; anything but a missing mailbox,
+        // e.g. a missing MailboxSettings.Read consent, fails the dump
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await?;
+            if has_no_mailbox(status, &body) {
+                return Ok(None);
+            }
+            anyhow::bail!(
+                "fetching mailbox settings of {}; {}: {}",
+                user.id,
+                status,
+                body
+            )
+        }
+        let settings: Settings = response.json().await?;
+        let replies = settings.automatic_replies_setting;
+        let scheduled = |at: fn(&AutomaticReplies) -> &Option<DateTimeTimeZone>| {
+            replies
+                .as_ref()
+                .filter(|replies| replies.status.as_deref() == Some("scheduled"))
+                .and_then(|replies| at(replies).as_ref())
+                .and_then(DateTimeTimeZone::utc)
+        };
+        let auto_reply_start = scheduled(|replies| &replies.scheduled_start_date_time);
+        let auto_reply_end = scheduled(|replies| &replies.scheduled_end_date_time);
+        let auto_reply_status = replies.and_then(|replies| replies.status);
+        Ok(Some(MailboxSettings {
+            time_zone: settings.time_zone,
+            locale: settings.language.and_then(|language| language.locale),
+            auto_reply: auto_reply_status
+                .as_deref()
+                .is_some_and(|status| status != "disabled"),
+            auto_reply_status,
+            auto_reply_start,
+            auto_reply_end,
+        }))
+    }
+
+    /// Fills in the mailbox settings of `users` when configured with
+    /// [`with_mailbox_settings`](Self::with_mailbox_settings).
+    pub(super) async fn add_mailbox_settings(&self, users: &mut [User]) -> anyhow::Result<()> {
+        if !self.mailbox_settings {
+            return Ok(());
+        }
+        let fetches = users.iter().map(|user| self.fetch_mailbox_settings(user));
+        let settings = try_join_all(fetches).await?;
+        for (user, settings) in users.iter_mut().zip(settings) {
+            user.mailbox_settings = settings;
+        }
+        Ok(())
+    }
+}
+
+/// Error codes of users without an Exchange Online mailbox.
+const NO_MAILBOX_CODES: [&str; 2] = ["MailboxNotEnabledForRESTAPI", "ErrorMailboxNotFound"];
+
+/// Whether an error response says the user has no mailbox Graph can read.
+fn has_no_mailbox(status: StatusCode, body: &str) -> bool {
+    let code = serde_json::from_str::<Value>(body)
+        .ok()
+        .and_then(|error| error["error"]["code"].as_str().map(str::to_string));
+    status == StatusCode::NOT_FOUND
+        || code.is_some_and(|code| NO_MAILBOX_CODES.contains(&code.as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(code: &str) -> String {
+        format!(r#"{{"error":{{"code":"{}","message":"..."}}}}"#, code)
+    }
+
+    #[test]
+    fn missing_mailboxes() {
+        assert!(has_no_mailbox(StatusCode::NOT_FOUND, ""));
+        assert!(has_no_mailbox(
+            StatusCode::BAD_REQUEST,
+            &error("MailboxNotEnabledForRESTAPI")
+        ));
+        assert!(has_no_mailbox(
+            StatusCode::BAD_REQUEST,
+            &error("ErrorMailboxNotFound")
+        ));
+    }
+
+    #[test]
+    fn other_errors_are_not_missing_mailboxes() {
+        assert!(!has_no_mailbox(
+            StatusCode::FORBIDDEN,
+            &error("ErrorAccessDenied")
+        ));
+        assert!(!has_no_mailbox(
+            StatusCode::UNAUTHORIZED,
+            &error("InvalidAuthenticationToken")
+        ));
+        assert!(!has_no_mailbox(
+            StatusCode::TOO_MANY_REQUESTS,
+            &error("ApplicationThrottled")
+        ));
+        assert!(!has_no_mailbox(StatusCode::BAD_REQUEST, "not json"));
+    }
+}
@@ -0,0 +1,83 @@
+use clap::ValueEnum;
+use futures::future::try_join_all;
+use serde::Deserialize;
+
+use super::GraphClient;
+use crate::model::User;
+
+/// Which groups of a user are listed in the `groups` column.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Memberships {
+    /// Groups the user is a direct member of (`memberOf`).
+    Direct,
+    /// Also the groups those groups are members of (`transitiveMemberOf`).
+    Transitive,
+}
+
+impl GraphClient {
+    /// Fetches the display names of the groups `user` is a member of,
+    /// sorted.
+    pub async fn fetch_group_names(
+        &self,
+        user: &User,
+        memberships: Memberships,
+    ) -> anyhow::Result<Vec<String>> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct GroupName {
+            display_name: Option<String>,
+        }
+
+        #[derive(Deserialize)]
+        struct Page {
+            value: Vec<GroupName>,
+            #[serde(rename = "@odata.nextLink")]
+            next_link: Option<String>,
+        }
+
+        let member_of = match memberships {
+            Memberships::Direct => "memberOf",
+            Memberships::Transitive => "transitiveMemberOf",
+        };
+        // the cast leaves out directory roles and administrative units
+        let mut url = self.user_url(&user.id, &[member_of, "microsoft.graph.group"])?;
+        url.query_pairs_mut()
+            .append_pair("$select", "displayName")
+            .append_pair("$top", "999");
+        let mut url = url.to_string();
+        let mut names = Vec::new();
+        loop {
+            let page: Page = self
+                .get(&url)
+                .await
+                .map_err(|e| anyhow::anyhow!("fetching groups of {}; {}", user.id, e))?;
+            names.extend(
+                page.value
+                    .into_iter()
+                    .filter_map(|group| group.display_name),
+            );
+            match page.next_link {
+                Some(next_link) => url = next_link,
+                None => break,
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// Fills in the group memberships of `users` when configured with
+    /// [`with_memberships`](Self::with_memberships).
+    pub(super) async fn add_memberships(&self, users: &mut [User]) -> anyhow::Result<()> {
+        let Some(memberships) = self.memberships else {
+            return Ok(());
+        };
+        let fetches = users
+            .iter()
+            .map(|user| self.fetch_group_names(user, memberships));
+        let groups = try_join_all(fetches).await?;
+        for (user, groups) in users.iter_mut().zip(groups) {
+            user.groups = groups;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,49 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use super::GraphClient;
+use crate::model::{MfaRegistration, User};
+
+impl GraphClient {
+    /// Fetches the authentication methods registered by every user of the
+    /// tenant, by user id.
+    pub async fn fetch_mfa_registrations(
+        &self,
+    ) -> anyhow::Result<HashMap<String, MfaRegistration>> {
+        #[derive(Deserialize)]
+        struct Registration {
+            id: String,
+            #[serde(flatten)]
+            registration: MfaRegistration,
+        }
+
+        let registrations: Vec<Registration> = self
+            .get_all(format!(
+                "{}/reports/authenticationMethods/userRegistrationDetails?$select=id,isMfaRegistered,isMfaCapable,methodsRegistered",
+                self.base_url()
+            ))
+            .await
+            .map_err(|e| anyhow::anyhow!("fetching MFA registrations; {}", e))?;
+        Ok(registrations
+            .into_iter()
+            .map(|r| (r.id, r.registration))
+            .collect())
+    }
+
+    /// Fills in the MFA registration of `users` when configured with
+    /// [`with_mfa`](Self::with_mfa).
+    pub(super) async fn add_mfa(&self, users: &mut [User]) -> anyhow::Result<()> {
+        if !self.mfa {
+            return Ok(());
+        }
+        let registrations = self
+            .mfa_registrations
+            .get_or_try_init(|| self.fetch_mfa_registrations())
+            .await?;
+        for user in users {
+            user.mfa = registrations.get(&user.id).cloned();
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,1277 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_recursion::async_recursion;
+use clap::ValueEnum;
+use futures::future::try_join_all;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{Client, Method, Response, StatusCode, Url};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use tokio::sync::{mpsc, OnceCell};
+use tokio::time::Instant;
+use tracing::{debug, info_span, warn, Instrument};
+
+pub mod admin_units;
+pub mod apps;
+mod batch;
+pub mod cache;
+pub mod devices;
+pub mod estimate;
+mod licenses;
+pub mod lists;
+pub mod mail;
+mod mailbox;
+pub mod memberships;
+mod mfa;
+mod photos;
+pub mod presence;
+pub mod retry;
+pub mod roles;
+pub mod subscription;
+mod teams;
+pub mod throttle;
+pub mod usage;
+
+use crate::auth::{StaticToken, TokenProvider};
+use crate::cloud::Cloud;
+use crate::model::{MfaRegistration, Usage, User, UsersResponse};
+use crate::progress::Progress;
+use cache::ResponseCache;
+use devices::Devices;
+use memberships::Memberships;
+use throttle::{ConcurrencyController, RateLimiter};
+use usage::UsagePeriod;
+
+/// Version of the Graph endpoint requests are sent to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ApiVersion {
+    /// The stable endpoint.
+    #[value(name = "v1.0")]
+    V1,
+    /// The preview endpoint, whose properties can change without notice.
+    #[default]
+    Beta,
+}
+
+impl ApiVersion {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiVersion::V1 => "v1.0",
+            ApiVersion::Beta => "beta",
+        }
+    }
+}
+
+/// Channel of managers and their direct reports found by the tree walk.
+type VisitSender = mpsc::UnboundedSender<(Arc<User>, Vec<Arc<User>>)>;
+
+/// Users reached by a tree walk, so management cycles (A reports to B, who
+/// reports to A) are broken instead of walked forever.
+struct Visited {
+    ids: Mutex<HashSet<String>>,
+    /// Where the walk started; roots below other roots are walked from
+    /// there.
+    roots: HashSet<String>,
+    /// The cycles broken, e.g. `A -> B -> A`.
+    cycles: Mutex<Vec<String>>,
+}
+
+impl Visited {
+    fn new(managers: &[Arc<User>]) -> Self {
+        let roots: HashSet<String> = managers.iter().map(|manager| manager.id.clone()).collect();
+        Self {
+            ids: Mutex::new(roots.clone()),
+            roots,
+            cycles: Mutex::default(),
+        }
+    }
+
+    /// Drops the reportees that were already reached: roots of overlapping
+    /// subtrees and, otherwise, users in a cycle. Expects their walk
+    /// manager to be set.
+    fn retain_new(&self, reportees: &mut Vec<User>) {
+        let mut ids = self.ids.lock().unwrap();
+        reportees.retain(|reportee| {
+            if ids.insert(reportee.id.clone()) {
+                return true;
+            }
+            let chain = &reportee.management_chain_ids;
+            // a cycle back to the root being walked is still a cycle
+            if self.roots.contains(&reportee.id) && !chain.contains(&reportee.id) {
+                debug!(
+                    id = reportee.id,
+                    "reached another root; its subtree is walked once"
+                );
+                return false;
+            }
+            // named for the warning, where ids would mean little
+            let names = &reportee.management_chain;
+            let start = chain
+                .iter()
+                .rposition(|id| *id == reportee.id)
+                .unwrap_or(chain.len().saturating_sub(1));
+            let mut cycle = names[start.min(names.len())..].to_vec();
+            cycle.push(reportee.display_name.clone());
+            self.cycles.lock().unwrap().push(cycle.join(" -> "));
+            false
+        });
+    }
+}
+
+/// Properties fetched for every user by default; the full user object is
+/// large.
+const USER_SELECT: &str = "id,displayName,jobTitle,department,mail,officeLocation,\
+    employeeId,employeeType,employeeHireDate,companyName";
+
+/// Expands each user's manager inline, so records carry their own manager
+/// linkage.
+const MANAGER_EXPAND: &str = "manager($select=id,displayName,mail)";
+
+/// Largest page size `/users` accepts, and the one lists of users are
+/// fetched with unless configured otherwise.
+pub const MAX_PAGE_SIZE: usize = 999;
+
+/// A group, as found by [`GraphClient::find_group`].
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Group {
+    pub id: String,
+    pub display_name: String,
+}
+
+/// A user, group, device or service principal.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DirectoryObject {
+    pub id: String,
+    pub display_name: Option<String>,
+    /// Only users have one.
+    pub user_principal_name: Option<String>,
+    /// e.g. `#microsoft.graph.user`.
+    #[serde(rename = "@odata.type")]
+    pub odata_type: Option<String>,
+}
+
+impl DirectoryObject {
+    /// The kind of object, e.g. `user` or `group`.
+    pub fn kind(&self) -> Option<&str> {
+        self.odata_type
+            .as_deref()
+            .map(|t| t.trim_start_matches("#microsoft.graph."))
+    }
+
+    /// The display name, or the id of objects without one.
+    pub fn name(&self) -> &str {
+        self.display_name.as_deref().unwrap_or(&self.id)
+    }
+}
+
+/// Whether `s` looks like a directory object id (a GUID).
+pub fn is_object_id(s: &str) -> bool {
+    s.len() == 36
+        && s.char_indices().all(|(i, c)| match i {
+            8 | 13 | 18 | 23 => c == '-',
+            _ => c.is_ascii_hexdigit(),
+        })
+}
+
+/// Quotes user input as an OData string literal, e.g. `'O''Brien'`, for use
+/// in a `$filter`.
+pub fn odata_string(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+/// Quotes user input as a `$search` clause on `property`, e.g.
+/// `"displayName:O'Brien"`. Quotes and backslashes in it are escaped so the
+/// input can't end the clause.
+pub fn search_clause(property: &str, s: &str) -> String {
+    let escaped = s.replace('\\', "\\\\").replace('"', "\\\"");
+    format!("\"{}:{}\"", property, escaped)
+}
+
+/// User properties a search can be narrowed to with `property:value`;
+/// all but `employeeId` support `$search`.
+const SEARCH_PROPERTIES: [&str; 9] = [
+    "displayName",
+    "givenName",
+    "surname",
+    "mail",
+    "mailNickname",
+    "userPrincipalName",
+    "otherMails",
+    "proxyAddresses",
+    "employeeId",
+];
+
+/// Splits a search like `displayName:smith` or `"surname:van der Berg"`
+/// into one of [`SEARCH_PROPERTIES`] and the value to look for; `None` for
+/// plain text.
+fn qualified_search(query: &str) -> Option<(&'static str, &str)> {
+    let query = query.trim();
+    let query = match query.strip_prefix('"').and_then(|q| q.strip_suffix('"')) {
+        Some(quoted) => quoted,
+        None => query,
+    };
+    let (property, value) = query.split_once(':')?;
+    let property = SEARCH_PROPERTIES
+        .into_iter()
+        .find(|name| name.eq_ignore_ascii_case(property.trim()))?;
+    let value = value.trim();
+    let value = value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value);
+    (!value.is_empty()).then_some((property, value))
+}
+
+/// Thin client over the Microsoft Graph users API.
+#[derive(Clone)]
+pub struct GraphClient {
+    client: Client,
+    token_provider: Arc<dyn TokenProvider>,
+    cloud: Cloud,
+    api_version: ApiVersion,
+    batch: bool,
+    max_retries: u32,
+    max_retry_after: Duration,
+    concurrency: Arc<ConcurrencyController>,
+    rate_limit: Option<Arc<RateLimiter>>,
+    cache: Option<Arc<ResponseCache>>,
+    select: String,
+    expand: String,
+    /// `$top` of user queries, from `--page-size`.
+    page_size: Option<usize>,
+    /// OData `$filter` on the users fetched, from `--odata-filter`.
+    odata_filter: Option<String>,
+    max_depth: Option<usize>,
+    mark_truncated: bool,
+    memberships: Option<Memberships>,
+    devices: Option<Devices>,
+    joined_teams: bool,
+    licenses: bool,
+    mailbox_settings: bool,
+    presence: bool,
+    usage_period: Option<UsagePeriod>,
+    mfa: bool,
+    photo_dir: Option<PathBuf>,
+    progress: Option<Arc<Progress>>,
+    /// SKU part numbers by SKU id, fetched once when needed.
+    sku_names: Arc<OnceCell<HashMap<String, String>>>,
+    /// Microsoft 365 usage by user principal name, fetched once when
+    /// needed; `None` if it cannot be joined.
+    usage: Arc<OnceCell<Option<HashMap<String, Usage>>>>,
+    /// MFA registrations by user id, fetched once when needed.
+    mfa_registrations: Arc<OnceCell<HashMap<String, MfaRegistration>>>,
+}
+
+impl GraphClient {
+    pub fn new(access_token: impl Into<String>) -> Self {
+        Self::with_client(Client::new(), access_token)
+    }
+
+    pub fn with_client(client: Client, access_token: impl Into<String>) -> Self {
+        Self::with_token_provider(client, Arc::new(StaticToken(access_token.into())))
+    }
+
+    pub fn with_token_provider(client: Client, token_provider: Arc<dyn TokenProvider>) -> Self {
+        Self {
+            client,
+            token_provider,
+            cloud: Cloud::default(),
+            api_version: ApiVersion::default(),
+            batch: false,
+            max_retries: retry::DEFAULT_MAX_RETRIES,
+            max_retry_after: retry::DEFAULT_MAX_RETRY_AFTER,
+            concurrency: Arc::new(ConcurrencyController::default()),
+            rate_limit: None,
+            cache: None,
+            select: USER_SELECT.to_string(),
+            expand: MANAGER_EXPAND.to_string(),
+            page_size: None,
+            odata_filter: None,
+            max_depth: None,
+            mark_truncated: false,
+            memberships: None,
+            devices: None,
+            joined_teams: false,
+            licenses: false,
+            mailbox_settings: false,
+            presence: false,
+            usage_period: None,
+            mfa: false,
+            photo_dir: None,
+            progress: None,
+            sku_names: Arc::default(),
+            usage: Arc::default(),
+            mfa_registrations: Arc::default(),
+        }
+    }
+
+    /// Sends requests to the Graph service of `cloud` instead of the global
+    /// one.
+    pub fn with_cloud(mut self, cloud: Cloud) -> Self {
+        self.cloud = cloud;
+        self
+    }
+
+    /// Sends requests to the `api_version` endpoint instead of beta.
+    pub fn with_api_version(mut self, api_version: ApiVersion) -> Self {
+        self.api_version = api_version;
+        self
+    }
+
+    /// URL of the Graph endpoint, e.g. `https://graph.microsoft.com/v1.0`.
+    pub fn base_url(&self) -> String {
+        format!("{}/{}", self.cloud.graph_host(), self.api_version.as_str())
+    }
+
+    /// Stops expanding users `max_depth` levels below the roots. With
+    /// `mark_truncated`, users at the limit are checked for reports and
+    /// flagged as truncated, at the cost of one more request each.
+    pub fn with_max_depth(mut self, max_depth: Option<usize>, mark_truncated: bool) -> Self {
+        self.max_depth = max_depth;
+        self.mark_truncated = mark_truncated;
+        self
+    }
+
+    /// Also fetches the groups each dumped user is a member of, one request
+    /// per user.
+    pub fn with_memberships(mut self, memberships: Option<Memberships>) -> Self {
+        self.memberships = memberships;
+        self
+    }
+
+    /// Also fetches the devices of each dumped user, one request per user.
+    pub fn with_devices(mut self, devices: Option<Devices>) -> Self {
+        self.devices = devices;
+        self
+    }
+
+    /// Also fetches the teams each dumped user is a member of, one request
+    /// per user.
+    pub fn with_joined_teams(mut self, joined_teams: bool) -> Self {
+        self.joined_teams = joined_teams;
+        self
+    }
+
+    /// Names the licenses assigned to each dumped user; `assignedLicenses`
+    /// must be selected.
+    pub fn with_licenses(mut self, licenses: bool) -> Self {
+        self.licenses = licenses;
+        self
+    }
+
+    /// Also fetches the mailbox settings of each dumped user, one request
+    /// per user.
+    pub fn with_mailbox_settings(mut self, mailbox_settings: bool) -> Self {
+        self.mailbox_settings = mailbox_settings;
+        self
+    }
+
+    /// Also fetches the current presence of the dumped users, batched per
+    /// page or level of users.
+    pub fn with_presence(mut self, presence: bool) -> Self {
+        self.presence = presence;
+        self
+    }
+
+    /// Joins the Microsoft 365 activity of the dumped users over `period`
+    /// onto them, from a single report; `userPrincipalName` must be
+    /// selected.
+    pub fn with_usage(mut self, period: Option<UsagePeriod>) -> Self {
+        self.usage_period = period;
+        self
+    }
+
+    /// Looks up the MFA registration of the dumped users, from a single
+    /// report of the whole tenant.
+    pub fn with_mfa(mut self, mfa: bool) -> Self {
+        self.mfa = mfa;
+        self
+    }
+
+    /// Saves the profile photo of each dumped user into `dir`;
+    /// `userPrincipalName` should be selected to name the files.
+    pub fn with_photos(mut self, dir: Option<PathBuf>) -> Self {
+        self.photo_dir = dir;
+        self
+    }
+
+    /// Counts the users fetched and requests sent in `progress`.
+    pub fn with_progress(mut self, progress: Option<Arc<Progress>>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Also expands the open extensions of each user, except for group
+    /// members.
+    pub fn with_open_extensions(mut self, open_extensions: bool) -> Self {
+        self.expand = MANAGER_EXPAND.to_string();
+        if open_extensions {
+            self.expand.push_str(",extensions");
+        }
+        self
+    }
+
+    /// Fetches `properties` of each user instead of the default set; `id`
+    /// and `displayName` are always included.
+    pub fn with_select(mut self, properties: &[String]) -> Self {
+        let mut select = vec!["id", "displayName"];
+        for property in properties {
+            if !select.contains(&property.as_str()) {
+                select.push(property);
+            }
+        }
+        self.select = select.join(",");
+        self
+    }
+
+    /// Only fetches the users matching the OData `$filter` in walks, group
+    /// listings and `fetch_all_users`. Tree walks don't descend below the
+    /// users it leaves out.
+    pub fn with_odata_filter(mut self, filter: Option<String>) -> Self {
+        self.odata_filter = filter;
+        self
+    }
+
+    /// Serves GET requests from `cache` when possible.
+    pub fn with_cache(mut self, cache: Option<ResponseCache>) -> Self {
+        self.cache = cache.map(Arc::new);
+        self
+    }
+
+    /// Replaces the adaptive limit on in-flight requests.
+    pub fn with_concurrency(mut self, concurrency: ConcurrencyController) -> Self {
+        self.concurrency = Arc::new(concurrency);
+        self
+    }
+
+    /// Starts at most `per_second` requests per second, retries included.
+    pub fn with_rate_limit(mut self, per_second: Option<f64>) -> Self {
+        self.rate_limit = per_second.map(|per_second| Arc::new(RateLimiter::new(per_second)));
+        self
+    }
+
+    /// Sets how often a throttled request is retried before giving up.
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Waits at most `max_retry_after` when Graph asks to retry later.
+    pub fn with_max_retry_after(mut self, max_retry_after: Duration) -> Self {
+        self.max_retry_after = max_retry_after;
+        self
+    }
+
+    /// Fetches direct reports with `$batch` requests during tree walks.
+    pub fn with_batching(mut self, batch: bool) -> Self {
+        self.batch = batch;
+        self
+    }
+
+    /// Asks for pages of `page_size` users (`$top`), up to
+    /// [`MAX_PAGE_SIZE`]. Without it, direct reports come in Graph's default
+    /// pages of 100 and lists of users in the largest pages.
+    pub fn with_page_size(mut self, page_size: Option<usize>) -> Self {
+        self.page_size = page_size.map(|size| size.clamp(1, MAX_PAGE_SIZE));
+        self
+    }
+
+    /// Page size of lists of users, e.g. `--all-users` and `--group`.
+    pub(crate) fn list_page_size(&self) -> usize {
+        self.page_size.unwrap_or(MAX_PAGE_SIZE)
+    }
+
+    /// Fetches a single page of users from `url`.
+    pub async fn fetch_users(&self, url: &str) -> anyhow::Result<UsersResponse> {
+        self.get(url)
+            .await
+            .map_err(|e| anyhow::anyhow!("fetching users; {}", e))
+    }
+
+    /// Number of objects in the collection at `path` below the endpoint
+    /// (e.g. `users/<id>/transitiveReports`), optionally restricted by an
+    /// OData `$filter`.
+    pub async fn count(&self, path: &str, filter: Option<&str>) -> anyhow::Result<u64> {
+        let mut url = Url::parse(&format!("{}/{}/$count", self.base_url(), path))?;
+        self.append_filter(&mut url, filter);
+        // the plain-text count parses as a JSON number
+        self.request(Method::GET, url.as_str(), None, None)
+            .await
+            .map_err(|e| anyhow::anyhow!("counting {}; {}", path, e))
+    }
+
+    /// Fetches a single user by object id or user principal name.
+    pub async fn fetch_user(&self, id: &str) -> anyhow::Result<User> {
+        let mut url = self.user_url(id, &[])?;
+        url.query_pairs_mut()
+            .append_pair("$select", &self.select)
+            .append_pair("$expand", &self.expand);
+        let mut user: User = self
+            .get(url.as_str())
+            .await
+            .map_err(|e| anyhow::anyhow!("fetching user {}; {}", id, e))?;
+        self.add_details(std::slice::from_mut(&mut user)).await?;
+        Ok(user)
+    }
+
+    /// Fetches the first page of users whose display name, mail or user
+    /// principal name has a word starting with `query`, and the users whose
+    /// employee id is `query`. A `query` like `displayName:smith` only
+    /// searches that property, e.g. `surname` or `proxyAddresses`.
+    ///
+    /// Both are advanced queries, so the results don't carry their manager;
+    /// fetch the chosen user again for that.
+    pub async fn search_users(&self, query: &str) -> anyhow::Result<Vec<User>> {
+        let (properties, employee_id) = match qualified_search(query) {
+            Some(("employeeId", value)) => (Vec::new(), Some(value)),
+            Some((property, value)) => (vec![search_clause(property, value)], None),
+            None => (
+                ["displayName", "mail", "userPrincipalName"]
+                    .iter()
+                    .map(|property| search_clause(property, query))
+                    .collect(),
+                Some(query),
+            ),
+        };
+        let mut users = Vec::new();
+        if !properties.is_empty() {
+            let mut url = Url::parse(&format!("{}/users", self.base_url()))?;
+            url.query_pairs_mut()
+                .append_pair("$search", &properties.join(" OR "))
+                .append_pair("$select", &self.select)
+                .append_pair("$count", "true");
+            users = self.fetch_users(url.as_str()).await?.value;
+        }
+
+        // `$search` doesn't cover employeeId
+        if let Some(employee_id) = employee_id {
+            let mut url = Url::parse(&format!("{}/users", self.base_url()))?;
+            url.query_pairs_mut()
+                .append_pair(
+                    "$filter",
+                    &format!("employeeId eq {}", odata_string(employee_id)),
+                )
+                .append_pair("$select", &self.select)
+                .append_pair("$count", "true");
+            for user in self.fetch_users(url.as_str()).await?.value {
+                if !users.iter().any(|found| found.id == user.id) {
+                    users.push(user);
+                }
+            }
+        }
+        Ok(users)
+    }
+
+    /// Fetches every user in the tenant with their manager expanded inline,
+    /// optionally restricted by an OData `$filter`.
+    pub async fn fetch_all_users(&self, filter: Option<&str>) -> anyhow::Result<Vec<User>> {
+        let mut url = Url::parse(&format!("{}/users", self.base_url()))?;
+        url.query_pairs_mut()
+            .append_pair("$top", &self.list_page_size().to_string())
+            .append_pair("$select", &self.select)
+            .append_pair("$expand", &self.expand);
+        self.append_filter(&mut url, filter);
+
+        let mut users = self.fetch_all_pages(url.to_string()).await?;
+        self.add_details(&mut users).await?;
+        Ok(users)
+    }
+
+    /// Finds a group by object id or exact display name.
+    pub async fn find_group(&self, id_or_name: &str) -> anyhow::Result<Group> {
+        if is_object_id(id_or_name) {
+            let url = format!(
+                "{}/groups/{}?$select=id,displayName",
+                self.base_url(),
+                id_or_name
+            );
+            return self
+                .get(&url)
+                .await
+                .map_err(|e| anyhow::anyhow!("fetching group {}; {}", id_or_name, e));
+        }
+
+        #[derive(Deserialize)]
+        struct Groups {
+            value: Vec<Group>,
+        }
+
+        let mut url = Url::parse(&format!("{}/groups", self.base_url()))?;
+        url.query_pairs_mut()
+            .append_pair(
+                "$filter",
+                &format!("displayName eq {}", odata_string(id_or_name)),
+            )
+            .append_pair("$select", "id,displayName");
+        let groups: Groups = self
+            .get(url.as_str())
+            .await
+            .map_err(|e| anyhow::anyhow!("searching group {}; {}", id_or_name, e))?;
+        match <[Group; 1]>::try_from(groups.value) {
+            Ok([group]) => Ok(group),
+            Err(groups) if groups.is_empty() => {
+                anyhow::bail!("no group named {:?}", id_or_name)
+            }
+            Err(groups) => {
+                let ids: Vec<&str> = groups.iter().map(|group| group.id.as_str()).collect();
+                anyhow::bail!(
+                    "{} groups are named {:?}; pass one of their ids: {}",
+                    ids.len(),
+                    id_or_name,
+                    ids.join(", ")
+                )
+            }
+        }
+    }
+
+    /// Fetches the users who are members of `group`, including members of
+    /// nested groups when `transitive`.
+    pub async fn fetch_group_members(
+        &self,
+        group: &Group,
+        transitive: bool,
+    ) -> anyhow::Result<Vec<User>> {
+        let members = if transitive {
+            "transitiveMembers"
+        } else {
+            "members"
+        };
+        // the cast leaves out devices, service principals and the groups
+        // themselves
+        let mut url = Url::parse(&format!(
+            "{}/groups/{}/{}/microsoft.graph.user",
+            self.base_url(),
+            group.id,
+            members
+        ))?;
+        url.query_pairs_mut()
+            .append_pair("$top", &self.list_page_size().to_string())
+            .append_pair("$select", &self.select);
+        self.append_filter(&mut url, None);
+        let mut users = self.fetch_all_pages(url.to_string()).await?;
+        self.add_details(&mut users).await?;
+        Ok(users)
+    }
+
+    /// Runs one round of `/users/delta`, starting a new delta sequence when
+    /// `delta_link` is `None`. Returns the changed users as raw property
+    /// maps (delta rounds only carry what changed) and the delta link for
+    /// the next round.
+    pub async fn fetch_users_delta(
+        &self,
+        delta_link: Option<&str>,
+    ) -> anyhow::Result<(Vec<Map<String, Value>>, String)> {
+        #[derive(Deserialize)]
+        struct DeltaPage {
+            value: Vec<Map<String, Value>>,
+            #[serde(rename = "@odata.nextLink")]
+            next_link: Option<String>,
+            #[serde(rename = "@odata.deltaLink")]
+            delta_link: Option<String>,
+        }
+
+        let mut url = match delta_link {
+            Some(delta_link) => delta_link.to_string(),
+            None => {
+                let mut url = Url::parse(&format!("{}/users/delta", self.base_url()))?;
+                // `manager` yields `manager@delta`
+                url.query_pairs_mut()
+                    .append_pair("$select", &format!("{},manager", self.select));
+                url.to_string()
+            }
+        };
+
+        let mut items = Vec::new();
+        loop {
+            // delta rounds depend on server-side state, so never cache them
+            let page: DeltaPage = self
+                .request(Method::GET, &url, None, None)
+                .await
+                .map_err(|e| anyhow::anyhow!("fetching users delta; {}", e))?;
+            if let Some(progress) = &self.progress {
+                progress.add_users(page.value.len());
+            }
+            items.extend(page.value);
+
+            match (page.next_link, page.delta_link) {
+                (Some(next_link), _) => url = next_link,
+                (None, Some(delta_link)) => return Ok((items, delta_link)),
+                (None, None) => {
+                    anyhow::bail!("fetching users delta; response has no next or delta link")
+                }
+            }
+        }
+    }
+
+    /// Finds the top of the org: users who have reports but no manager.
+    ///
+    /// Pages through `/users` selecting only ids, so this is cheap even on
+    /// large tenants.
+    pub async fn fetch_top_of_org(&self) -> anyhow::Result<Vec<User>> {
+        #[derive(Deserialize)]
+        struct IdOnly {
+            id: String,
+        }
+
+        #[derive(Deserialize)]
+        struct UserWithManager {
+            id: String,
+            manager: Option<IdOnly>,
+        }
+
+        #[derive(Deserialize)]
+        struct Page {
+            value: Vec<UserWithManager>,
+            #[serde(rename = "@odata.nextLink")]
+            next_link: Option<String>,
+        }
+
+        let mut url = Url::parse(&format!("{}/users", self.base_url()))?;
+        url.query_pairs_mut()
+            .append_pair("$top", &self.list_page_size().to_string())
+            .append_pair("$select", "id")
+            .append_pair("$expand", "manager($select=id)");
+
+        let mut url = url.to_string();
+        let mut unmanaged = Vec::new();
+        let mut managers = HashSet::new();
+        loop {
+            let page: Page = self
+                .get(&url)
+                .await
+                .map_err(|e| anyhow::anyhow!("fetching users; {}", e))?;
+            for user in page.value {
+                match user.manager {
+                    Some(manager) => {
+                        managers.insert(manager.id);
+                    }
+                    None => unmanaged.push(user.id),
+                }
+            }
+
+            if let Some(next_link) = page.next_link {
+                url = next_link;
+            } else {
+                break;
+            }
+        }
+
+        let mut roots = Vec::new();
+        for id in unmanaged.iter().filter(|id| managers.contains(*id)) {
+            roots.push(self.fetch_user(id).await?);
+        }
+        Ok(roots)
+    }
+
+    /// Fetches all direct reports of `manager`, following `@odata.nextLink`.
+    pub async fn fetch_direct_reports(&self, manager: &User) -> anyhow::Result<Vec<User>> {
+        let url = self.direct_reports_url(&manager.id)?;
+        let mut reportees = self.fetch_all_pages(url.to_string()).await?;
+        self.add_details(&mut reportees).await?;
+        Ok(reportees)
+    }
+
+    /// URL of `path` below the user `id`, e.g. `users/<id>/directReports`.
+    /// The id becomes a single path segment, so UPNs with `#` (guests) or
+    /// other reserved characters can't change the request.
+    pub(crate) fn user_url(&self, id: &str, path: &[&str]) -> anyhow::Result<Url> {
+        let mut url = Url::parse(&self.base_url())?;
+        url.path_segments_mut()
+            .map_err(|_| anyhow::anyhow!("invalid Graph base URL"))?
+            .extend(["users", id])
+            .extend(path);
+        Ok(url)
+    }
+
+    /// URL of the `directReports` of `manager_id` with the configured
+    /// properties and `$filter`.
+    fn direct_reports_url(&self, manager_id: &str) -> anyhow::Result<Url> {
+        let mut url = match self.odata_filter {
+            // reports are directory objects; user properties need the cast
+            Some(_) => self.user_url(manager_id, &["directReports", "microsoft.graph.user"])?,
+            None => self.user_url(manager_id, &["directReports"])?,
+        };
+        url.query_pairs_mut()
+            .append_pair("$select", &self.select)
+            .append_pair("$expand", &self.expand);
+        if let Some(page_size) = self.page_size {
+            url.query_pairs_mut()
+                .append_pair("$top", &page_size.to_string());
+        }
+        self.append_filter(&mut url, None);
+        Ok(url)
+    }
+
+    /// URL of a single direct report's id, to tell whether a user has any.
+    fn direct_reports_probe_url(&self, manager_id: &str) -> anyhow::Result<Url> {
+        let mut url = self.user_url(manager_id, &["directReports"])?;
+        url.query_pairs_mut()
+            .append_pair("$select", "id")
+            .append_pair("$top", "1");
+        Ok(url)
+    }
+
+    /// Adds `filter` and the `--odata-filter` to the query of `url`. The
+    /// latter is sent as an advanced query, which most filters on users
+    /// and all filters on related objects need.
+    fn append_filter(&self, url: &mut Url, filter: Option<&str>) {
+        let filter = match (filter, self.odata_filter.as_deref()) {
+            (Some(filter), Some(odata_filter)) => format!("({}) and ({})", filter, odata_filter),
+            (Some(filter), None) | (None, Some(filter)) => filter.to_string(),
+            (None, None) => return,
+        };
+        let counting = url.path().ends_with("/$count");
+        let mut query = url.query_pairs_mut();
+        query.append_pair("$filter", &filter);
+        if self.odata_filter.is_some() && !counting {
+            query.append_pair("$count", "true");
+        }
+    }
+
+    /// Walks up from `user` through their managers to the top of the org.
+    /// Returns the chain top first, ending with `user`.
+    pub async fn fetch_management_chain(&self, user: &User) -> anyhow::Result<Vec<User>> {
+        let mut chain = vec![user.clone()];
+        let mut seen = HashSet::from([user.id.clone()]);
+        while let Some(manager) = chain.last().and_then(|user| user.manager.as_deref()) {
+            let manager_id = manager.id.clone();
+            // stop rather than loop forever on a management cycle
+            if !seen.insert(manager_id.clone()) {
+                break;
+            }
+            chain.push(self.fetch_user(&manager_id).await?);
+        }
+        chain.reverse();
+        Ok(chain)
+    }
+
+    /// Walks the reporting tree below `manager`, calling `visit` with each
+    /// reportee and its manager.
+    ///
+    /// Sibling subtrees are fetched concurrently (bounded by the adaptive
+    /// concurrency limit), so reportees are visited in no particular order except
+    /// that a manager is always visited before their reports.
+    pub async fn fetch_reportee_tree<F>(&self, manager: &User, visit: &mut F) -> anyhow::Result<()>
+    where
+        F: FnMut(&User, &User) -> anyhow::Result<()> + Send,
+    {
+        self.expand_managers(vec![manager.clone()], &mut |manager, reportees| {
+            for reportee in reportees {
+                visit(reportee, manager)?;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Walks the reporting trees below all of `managers`, calling `expanded`
+    /// once per manager with their complete list of direct reports.
+    ///
+    /// This is the building block for resumable walks: once `expanded` has
+    /// returned for a manager, only their reports remain to be expanded.
+    pub async fn expand_managers<F>(
+        &self,
+        managers: Vec<User>,
+        expanded: &mut F,
+    ) -> anyhow::Result<()>
+    where
+        F: FnMut(&User, &[User]) -> anyhow::Result<()> + Send,
+    {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        let managers: Vec<Arc<User>> = managers
+            .into_iter()
+            .filter(|manager| !self.at_depth_limit(manager))
+            .map(Arc::new)
+            .collect();
+        let visited = Visited::new(&managers);
+        let walk = async {
+            if self.batch {
+                self.walk_tree_batched(managers, tx, &visited).await
+            } else {
+                let subtrees = managers
+                    .into_iter()
+                    .map(|manager| self.walk_subtree(manager, tx.clone(), &visited));
+                try_join_all(subtrees).await.map(|_| ())
+            }
+        };
+        let drain = async {
+            while let Some((manager, reportees)) = rx.recv().await {
+                if let Some(progress) = &self.progress {
+                    // the subtree below the root the manager is in
+                    let subtree = match manager.management_chain.get(1) {
+                        Some(name) => name,
+                        None => &manager.display_name,
+                    };
+                    progress.set_subtree(subtree);
+                }
+                let reportees: Vec<User> = reportees.iter().map(|r| User::clone(r)).collect();
+                expanded(&manager, &reportees)?;
+            }
+            Ok(())
+        };
+
+        tokio::try_join!(walk, drain)?;
+        let cycles = visited.cycles.into_inner().unwrap();
+        if !cycles.is_empty() {
+            warn!(
+                "Broke {} management cycles, whose users are written once: {}",
+                cycles.len(),
+                cycles.join("; ")
+            );
+        }
+        Ok(())
+    }
+
+    #[async_recursion]
+    async fn walk_subtree(
+        &self,
+        manager: Arc<User>,
+        tx: VisitSender,
+        visited: &Visited,
+    ) -> anyhow::Result<()> {
+        let depth = manager.depth.unwrap_or(0) + 1;
+        let mut reportees = self.fetch_direct_reports(&manager).await?;
+        for reportee in &mut reportees {
+            reportee.set_walk_manager(&manager);
+        }
+        visited.retain_new(&mut reportees);
+
+        // reportees are written with their manager, so mark them up front
+        let at_limit = self.max_depth.is_some_and(|max_depth| depth >= max_depth);
+        if at_limit && self.mark_truncated {
+            let checks = reportees
+                .iter()
+                .map(|reportee| self.has_direct_reports(reportee));
+            let truncated = try_join_all(checks).await?;
+            for (reportee, truncated) in reportees.iter_mut().zip(truncated) {
+                reportee.truncated = truncated;
+            }
+        }
+
+        let reportees: Vec<Arc<User>> = reportees.into_iter().map(Arc::new).collect();
+        tx.send((manager, reportees.clone()))
+            .map_err(|_| anyhow::anyhow!("reportee tree walk was cancelled"))?;
+        if at_limit {
+            return Ok(());
+        }
+
+        let subtrees = reportees
+            .into_iter()
+            .map(|reportee| self.walk_subtree(reportee, tx.clone(), visited));
+        try_join_all(subtrees).await?;
+        Ok(())
+    }
+
+    /// Adds the configured details that take requests of their own to
+    /// `users`.
+    async fn add_details(&self, users: &mut [User]) -> anyhow::Result<()> {
+        self.add_memberships(users).await?;
+        self.add_devices(users).await?;
+        self.add_joined_teams(users).await?;
+        self.add_license_names(users).await?;
+        self.add_mailbox_settings(users).await?;
+        self.add_presence(users).await?;
+        self.add_usage(users).await?;
+        self.add_mfa(users).await?;
+        self.save_photos(users).await;
+        Ok(())
+    }
+
+    /// Whether `user` is at `--max-depth` and must not be expanded.
+    fn at_depth_limit(&self, user: &User) -> bool {
+        self.max_depth
+            .is_some_and(|max_depth| user.depth.unwrap_or(0) >= max_depth)
+    }
+
+    /// Whether `user` has any direct reports, fetching at most one id.
+    async fn has_direct_reports(&self, user: &User) -> anyhow::Result<bool> {
+        let url = self.direct_reports_probe_url(&user.id)?;
+        let page: Value = self
+            .get(url.as_str())
+            .await
+            .map_err(|e| anyhow::anyhow!("fetching direct reports of {}; {}", user.id, e))?;
+        Ok(page["value"]
+            .as_array()
+            .is_some_and(|reports| !reports.is_empty()))
+    }
+
+    /// Fetches all pages of users starting at `url`.
+    async fn fetch_all_pages(&self, mut url: String) -> anyhow::Result<Vec<User>> {
+        let mut users = Vec::new();
+        loop {
+            let response = self.fetch_users(&url).await?;
+            if let Some(progress) = &self.progress {
+                progress.add_users(response.value.len());
+            }
+            users.extend(response.value);
+
+            if let Some(next_link) = response.next_link {
+                url = next_link;
+            } else {
+                break;
+            }
+        }
+        Ok(users)
+    }
+
+    /// Fetches every page of a collection of directory objects starting at
+    /// `url`.
+    async fn get_all<T: DeserializeOwned>(&self, mut url: String) -> anyhow::Result<Vec<T>> {
+        #[derive(Deserialize)]
+        struct Page<T> {
+            value: Vec<T>,
+            #[serde(rename = "@odata.nextLink")]
+            next_link: Option<String>,
+        }
+
+        let mut values = Vec::new();
+        loop {
+            let page: Page<T> = self.get(&url).await?;
+            values.extend(page.value);
+            match page.next_link {
+                Some(next_link) => url = next_link,
+                None => return Ok(values),
+            }
+        }
+    }
+
+    async fn get<T: DeserializeOwned>(&self, url: &str) -> anyhow::Result<T> {
+        self.request(Method::GET, url, None, self.cache.as_deref())
+            .await
+    }
+
+    /// POSTs a query that only reads, such as a `$batch` of GETs, so it is
+    /// retried like a GET.
+    async fn post_query<T: DeserializeOwned>(&self, url: &str, body: &Value) -> anyhow::Result<T> {
+        self.send_request(Method::POST, url, Some(body), None, true)
+            .await
+    }
+
+    /// POSTs a write, which is only retried when Graph turned it away.
+    async fn post<T: DeserializeOwned>(&self, url: &str, body: &Value) -> anyhow::Result<T> {
+        self.request(Method::POST, url, Some(body), None).await
+    }
+
+    async fn request<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        url: &str,
+        body: Option<&Value>,
+        cache: Option<&ResponseCache>,
+    ) -> anyhow::Result<T> {
+        let idempotent = method.is_idempotent();
+        self.send_request(method, url, body, cache, idempotent)
+            .await
+    }
+
+    async fn send_request<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        url: &str,
+        body: Option<&Value>,
+        cache: Option<&ResponseCache>,
+        idempotent: bool,
+    ) -> anyhow::Result<T> {
+        if let Some(cached) = match cache {
+            Some(cache) => cache.get(url).await,
+            None => None,
+        } {
+            debug!(url, "served from cache");
+            return Ok(serde_json::from_slice(&cached)?);
+        }
+
+        let response = self
+            .send_with_retries(&method, url, body, idempotent)
+            .await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let response_txt = response.text().await?;
+            anyhow::bail!("{}: {}", status, response_txt)
+        }
+
+        let body = response.bytes().await?;
+        // e.g. 204 No Content
+        if body.is_empty() {
+            return Ok(serde_json::from_value(Value::Null)?);
+        }
+        if let Some(cache) = cache {
+            cache.put(url, &body).await;
+        }
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    /// Sends a request, refreshing an expired token once and retrying
+    /// throttled and transiently failing requests; requests that are not
+    /// `idempotent` only when Graph turned them away. Returns the final
+    /// response, whatever its status.
+    async fn send_with_retries(
+        &self,
+        method: &Method,
+        url: &str,
+        body: Option<&Value>,
+        idempotent: bool,
+    ) -> anyhow::Result<Response> {
+        let span = info_span!("graph_request", %method, url);
+        async {
+            let mut access_token = self.token_provider.access_token().await?;
+            let mut refreshed = false;
+            let mut attempt = 0;
+            let response = loop {
+                // held for the request only, so waiting to retry does not
+                // keep others from running
+                let permit = self.concurrency.acquire().await;
+                if let Some(rate_limit) = &self.rate_limit {
+                    rate_limit.wait().await;
+                }
+                let started = Instant::now();
+                if let Some(progress) = &self.progress {
+                    progress.add_request();
+                }
+                let response = match self.send(method, url, body, &access_token).await {
+                    Ok(response) => response,
+                    Err(e)
+                        if idempotent
+                            && retry::is_transient_error(&e)
+                            && attempt < self.max_retries =>
+                    {
+                        drop(permit);
+                        let delay = retry::backoff(attempt);
+                        warn!(error = %e, attempt, "retrying in {:.1}s", delay.as_secs_f64());
+                        tokio::time::sleep(delay).await;
+                        attempt += 1;
+                        continue;
+                    }
+                    Err(e) => return Err(e.into()),
+                };
+                debug!(
+                    status = response.status().as_u16(),
+                    elapsed_ms = started.elapsed().as_millis() as u64,
+                    "response"
+                );
+                self.concurrency
+                    .record(response.status(), response.headers());
+
+                let status = response.status();
+                let retry_after = retry::retry_after(response.headers());
+                if status == StatusCode::UNAUTHORIZED && !refreshed {
+                    // the token expired mid-run; refresh it and retry once
+                    debug!("access token rejected; refreshing it");
+                    drop(permit);
+                    access_token = self.token_provider.refresh(&access_token).await?;
+                    refreshed = true;
+                } else if retry::is_retryable_response(status, retry_after, idempotent)
+                    && attempt < self.max_retries
+                {
+                    drop(permit);
+                    let delay = retry::delay(retry_after, attempt, self.max_retry_after);
+                    warn!(
+                        status = status.as_u16(),
+                        retry_after_s = retry_after.map(|d| d.as_secs()),
+                        attempt,
+                        "retrying in {:.1}s",
+                        delay.as_secs_f64()
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                } else {
+                    break response;
+                }
+            };
+            Ok(response)
+        }
+        .instrument(span)
+        .await
+    }
+
+    async fn send(
+        &self,
+        method: &Method,
+        url: &str,
+        body: Option<&Value>,
+        access_token: &str,
+    ) -> reqwest::Result<Response> {
+        let mut request = self
+            .client
+            .request(method.clone(), url)
+            .header(CONTENT_TYPE, "application/json")
+            .bearer_auth(access_token);
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+        let advanced = ["/$count", "$count=true", "%24count=true"];
+        if advanced.iter().any(|marker| url.contains(marker)) {
+            // `$count` and `--odata-filter` queries need advanced query
+            // support
+            request = request.header("ConsistencyLevel", "eventual");
+        }
+        request.send().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(id: &str, name: &str) -> User {
+        User::record(id, name, Map::new())
+    }
+
+    #[test]
+    fn cycle_back_to_the_root_is_reported() {
+        // A -> B -> A, walked from A
+        let a = user("a", "A");
+        let visited = Visited::new(&[Arc::new(a.clone())]);
+        let mut b = user("b", "B");
+        b.set_walk_manager(&a);
+        let mut reportees = vec![b.clone()];
+        visited.retain_new(&mut reportees);
+        assert_eq!(reportees.len(), 1);
+
+        let mut back = user("a", "A");
+        back.set_walk_manager(&b);
+        let mut reportees = vec![back];
+        visited.retain_new(&mut reportees);
+        assert!(reportees.is_empty());
+        assert_eq!(*visited.cycles.lock().unwrap(), vec!["A -> B -> A"]);
+    }
+
+    #[test]
+    fn other_roots_are_not_cycles() {
+        // A and B are both roots, B reports to A
+        let a = user("a", "A");
+        let visited = Visited::new(&[Arc::new(a.clone()), Arc::new(user("b", "B"))]);
+        let mut b = user("b", "B");
+        b.set_walk_manager(&a);
+        let mut reportees = vec![b];
+        visited.retain_new(&mut reportees);
+        assert!(reportees.is_empty());
+        assert!(visited.cycles.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn managers_of_the_same_name_are_not_cycles() {
+        // A manages another A, who reports to nobody else
+        let a = user("a1", "A");
+        let visited = Visited::new(&[Arc::new(a.clone())]);
+        let mut other = user("a2", "A");
+        other.set_walk_manager(&a);
+        let mut reportees = vec![other.clone()];
+        visited.retain_new(&mut reportees);
+        assert_eq!(reportees.len(), 1);
+
+        // and a cycle through the other A is named from where it starts
+        let mut back = user("a2", "A");
+        back.set_walk_manager(&other);
+        let mut reportees = vec![back];
+        visited.retain_new(&mut reportees);
+        assert!(reportees.is_empty());
+        assert_eq!(*visited.cycles.lock().unwrap(), vec!["A -> A"]);
+    }
+}
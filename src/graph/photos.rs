@@ -0,0 +1,76 @@
+use std::path::Path;
+
+use futures::future::join_all;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{Method, StatusCode};
+
+use super::GraphClient;
+use crate::model::User;
+
+impl GraphClient {
+    /// Fetches the profile photo of `user` and the file extension for its
+    /// type; `None` if the user has no photo.
+    pub async fn fetch_photo(
+        &self,
+        user: &User,
+    ) -> anyhow::Result<Option<(Vec<u8>, &'static str)>> {
+        let url = self.user_url(&user.id, &["photo", "$value"])?;
+        let response = self
+            .send_with_retries(&Method::GET, url.as_str(), None, true)
+            .await?;
+        match response.status() {
+            StatusCode::NOT_FOUND => return Ok(None),
+            status if !status.is_success() => {
+                anyhow::bail!("{}: {}", status, response.text().await?)
+            }
+            _ => {}
+        }
+        let extension = match response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+        {
+            Some("image/png") => "png",
+            Some("image/gif") => "gif",
+            _ => "jpg",
+        };
+        Ok(Some((response.bytes().await?.to_vec(), extension)))
+    }
+
+    /// Saves the photos of `users` into the directory configured with
+    /// [`with_photos`](Self::with_photos), named by user principal name (or
+    /// id). Users without a photo are skipped, and failures only warned
+    /// about, so they don't abort the dump.
+    pub(super) async fn save_photos(&self, users: &[User]) {
+        let Some(dir) = self.photo_dir.as_deref() else {
+            return;
+        };
+        let saves = users.iter().map(|user| async move {
+            if let Err(e) = self.save_photo(dir, user).await {
+                tracing::warn!("Saving the photo of {} failed; {}", user.id, e);
+            }
+        });
+        join_all(saves).await;
+    }
+
+    async fn save_photo(&self, dir: &Path, user: &User) -> anyhow::Result<()> {
+        let Some((photo, extension)) = self.fetch_photo(user).await? else {
+            return Ok(());
+        };
+        let name = match user.property("userPrincipalName").as_str() {
+            // guest UPNs contain '#'
+            Some(upn) => upn
+                .chars()
+                .map(|c| match c {
+                    c if c.is_ascii_alphanumeric() || "@.-_".contains(c) => c,
+                    _ => '_',
+                })
+                .collect(),
+            None => user.id.clone(),
+        };
+        let path = dir.join(format!("{}.{}", name, extension));
+        tokio::fs::write(&path, photo)
+            .await
+            .map_err(|e| anyhow::anyhow!("writing {}; {}", path.display(), e))
+    }
+}
@@ -0,0 +1,53 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use serde_json::json;
+
+use super::GraphClient;
+use crate::model::{Presence, User};
+
+/// Most user ids `getPresencesByUserId` accepts in one request.
+pub const MAX_PRESENCE_IDS: usize = 650;
+
+impl GraphClient {
+    /// Fetches the current presence of the users with `ids`, by id, in
+    /// requests of up to [`MAX_PRESENCE_IDS`] users.
+    pub async fn fetch_presences(&self, ids: &[&str]) -> anyhow::Result<HashMap<String, Presence>> {
+        #[derive(Deserialize)]
+        struct PresenceWithId {
+            id: String,
+            #[serde(flatten)]
+            presence: Presence,
+        }
+
+        #[derive(Deserialize)]
+        struct Presences {
+            value: Vec<PresenceWithId>,
+        }
+
+        let url = format!("{}/communications/getPresencesByUserId", self.base_url());
+        let mut presences = HashMap::new();
+        for chunk in ids.chunks(MAX_PRESENCE_IDS) {
+            let response: Presences = self
+                .post_query(&url, &json!({ "ids": chunk }))
+                .await
+                .map_err(|e| anyhow::anyhow!("fetching presences; {}", e))?;
+            presences.extend(response.value.into_iter().map(|p| (p.id, p.presence)));
+        }
+        Ok(presences)
+    }
+
+    /// Fills in the presence of `users` when configured with
+    /// [`with_presence`](Self::with_presence).
+    pub(super) async fn add_presence(&self, users: &mut [User]) -> anyhow::Result<()> {
+        if !self.presence || users.is_empty() {
+            return Ok(());
+        }
+        let ids: Vec<&str> = users.iter().map(|user| user.id.as_str()).collect();
+        let mut presences = self.fetch_presences(&ids).await?;
+        for user in users {
+            user.presence = presences.remove(&user.id);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,150 @@
+use std::time::Duration;
+
+use rand::Rng;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::StatusCode;
+
+/// Retries allowed per request unless configured otherwise.
+pub const DEFAULT_MAX_RETRIES: u32 = 5;
+
+/// First backoff delay; doubled on every further attempt.
+const BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// Upper bound for a single backoff delay.
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Longest `Retry-After` delay waited for unless configured otherwise.
+pub const DEFAULT_MAX_RETRY_AFTER: Duration = Duration::from_secs(5 * 60);
+
+/// Throttling and transient server errors worth retrying.
+pub fn is_retryable_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::INTERNAL_SERVER_ERROR
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+/// Whether a response is worth retrying. Requests that are not safe to
+/// repeat, such as writes, are only retried when the server turned them
+/// away unprocessed and said when to come back.
+pub fn is_retryable_response(
+    status: StatusCode,
+    retry_after: Option<Duration>,
+    idempotent: bool,
+) -> bool {
+    if idempotent {
+        return is_retryable_status(status);
+    }
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE
+    ) && retry_after.is_some()
+}
+
+/// Network failures (connection resets, timeouts) worth retrying.
+pub fn is_transient_error(error: &reqwest::Error) -> bool {
+    error.is_connect() || error.is_timeout() || error.is_request() || error.is_body()
+}
+
+/// Exponential backoff with full jitter for the given (zero-based) attempt.
+pub fn backoff(attempt: u32) -> Duration {
+    let ceiling = BASE_DELAY
+        .saturating_mul(1 << attempt.min(16))
+        .min(MAX_DELAY);
+    let millis = rand::thread_rng().gen_range(0..=ceiling.as_millis() as u64);
+    Duration::from_millis(millis)
+}
+
+/// Delay requested by the `Retry-After` header, in seconds.
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    retry_after_value(headers.get(RETRY_AFTER)?.to_str().ok()?)
+}
+
+/// Parses a `Retry-After` value given in seconds.
+pub fn retry_after_value(value: &str) -> Option<Duration> {
+    value.trim().parse().ok().map(Duration::from_secs)
+}
+
+/// Honors `Retry-After` when present, up to `max_retry_after`, falling back
+/// to jittered backoff.
+pub fn delay(retry_after: Option<Duration>, attempt: u32, max_retry_after: Duration) -> Duration {
+    match retry_after {
+        Some(retry_after) => retry_after.min(max_retry_after),
+        None => backoff(attempt),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_are_retried_only_when_turned_away() {
+        let later = Some(Duration::from_secs(2));
+        assert!(is_retryable_response(StatusCode::BAD_GATEWAY, None, true));
+        assert!(!is_retryable_response(
+            StatusCode::BAD_GATEWAY,
+            later,
+            false
+        ));
+        assert!(!is_retryable_response(
+            StatusCode::TOO_MANY_REQUESTS,
+            None,
+            false
+        ));
+        assert!(is_retryable_response(
+            StatusCode::TOO_MANY_REQUESTS,
+            later,
+            false
+        ));
+        assert!(is_retryable_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            later,
+            false
+        ));
+    }
+
+    #[test]
+    fn retry_after_is_capped() {
+        let max = Duration::from_secs(300);
+        let asked = Some(Duration::from_secs(3600));
+        assert_eq!(delay(asked, 0, max), max);
+        assert_eq!(
+            delay(Some(Duration::from_secs(7)), 0, max),
+            Duration::from_secs(7)
+        );
+    }
+
+    #[test]
+    fn backoff_grows_up_to_the_maximum() {
+        for _ in 0..100 {
+            assert!(backoff(0) <= BASE_DELAY);
+            assert!(backoff(3) <= BASE_DELAY * 8);
+            // the shift is bounded, however many attempts were made
+            assert!(backoff(u32::MAX) <= MAX_DELAY);
+        }
+    }
+
+    #[test]
+    fn retry_after_in_seconds() {
+        assert_eq!(retry_after_value(" 30 "), Some(Duration::from_secs(30)));
+        // HTTP dates are not used by Graph
+        assert_eq!(retry_after_value("Wed, 21 Oct 2015 07:28:00 GMT"), None);
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+        headers.insert(RETRY_AFTER, "7".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(7)));
+    }
+
+    #[test]
+    fn client_errors_are_not_retried() {
+        assert!(is_retryable_status(StatusCode::TOO_MANY_REQUESTS));
+        assert!(is_retryable_status(StatusCode::GATEWAY_TIMEOUT));
+        assert!(!is_retryable_status(StatusCode::NOT_FOUND));
+        assert!(!is_retryable_status(StatusCode::NOT_IMPLEMENTED));
+    }
+}
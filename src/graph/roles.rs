@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+
+use futures::future::try_join_all;
+use serde::Deserialize;
+
+use super::{DirectoryObject, GraphClient};
+
+/// An activated directory role with the objects holding it.
+#[derive(Clone, Debug)]
+pub struct DirectoryRole {
+    pub id: String,
+    /// e.g. `Global Administrator`.
+    pub display_name: String,
+    /// Id of the role across tenants.
+    pub role_template_id: Option<String>,
+    pub members: Vec<DirectoryObject>,
+}
+
+/// A role a principal can activate through Privileged Identity Management.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoleEligibility {
+    /// Id of the eligibility schedule.
+    pub id: String,
+    pub principal: DirectoryObject,
+    pub role_definition: RoleDefinition,
+    /// `/` for the tenant, or e.g. `/administrativeUnits/<id>`.
+    pub directory_scope_id: Option<String>,
+    pub schedule_info: Option<ScheduleInfo>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoleDefinition {
+    pub display_name: String,
+    pub template_id: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduleInfo {
+    pub start_date_time: Option<String>,
+    pub expiration: Option<Expiration>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Expiration {
+    /// Missing for eligibilities that do not expire.
+    pub end_date_time: Option<String>,
+}
+
+impl GraphClient {
+    /// Fetches the activated directory roles with their members, sorted by
+    /// name. Members include active Privileged Identity Management
+    /// assignments, but not eligible ones.
+    pub async fn fetch_directory_roles(&self) -> anyhow::Result<Vec<DirectoryRole>> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Role {
+            id: String,
+            display_name: String,
+            role_template_id: Option<String>,
+        }
+
+        let roles: Vec<Role> = self
+            .get_all(format!(
+                "{}/directoryRoles?$select=id,displayName,roleTemplateId",
+                self.base_url()
+            ))
+            .await
+            .map_err(|e| anyhow::anyhow!("fetching directory roles; {}", e))?;
+        let mut roles = try_join_all(roles.into_iter().map(|role| async {
+            let members = self
+                .get_all(format!(
+                    "{}/directoryRoles/{}/members?$select=id,displayName,userPrincipalName",
+                    self.base_url(),
+                    role.id
+                ))
+                .await
+                .map_err(|e| anyhow::anyhow!("fetching members of {}; {}", role.display_name, e))?;
+            anyhow::Ok(DirectoryRole {
+                id: role.id,
+                display_name: role.display_name,
+                role_template_id: role.role_template_id,
+                members,
+            })
+        }))
+        .await?;
+        roles.sort_by(|a, b| a.display_name.cmp(&b.display_name));
+        Ok(roles)
+    }
+
+    /// Fetches the roles principals are eligible for in Privileged Identity
+    /// Management. Needs a Microsoft Entra ID P2 license.
+    pub async fn fetch_role_eligibilities(&self) -> anyhow::Result<Vec<RoleEligibility>> {
+        self.get_all(format!(
+            "{}/roleManagement/directory/roleEligibilitySchedules?$expand=principal,roleDefinition",
+            self.base_url()
+        ))
+        .await
+        .map_err(|e| anyhow::anyhow!("fetching role eligibilities; {}", e))
+    }
+
+    /// Names of the activated directory roles by their object ids, as used
+    /// by scoped role assignments.
+    pub(super) async fn fetch_directory_role_names(
+        &self,
+    ) -> anyhow::Result<HashMap<String, String>> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Role {
+            id: String,
+            display_name: String,
+        }
+
+        let roles: Vec<Role> = self
+            .get_all(format!(
+                "{}/directoryRoles?$select=id,displayName",
+                self.base_url()
+            ))
+            .await
+            .map_err(|e| anyhow::anyhow!("fetching directory roles; {}", e))?;
+        Ok(roles
+            .into_iter()
+            .map(|role| (role.id, role.display_name))
+            .collect())
+    }
+}
@@ -0,0 +1,72 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use reqwest::Method;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::GraphClient;
+
+/// A Graph change notification subscription.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Subscription {
+    pub id: String,
+    pub expiration_date_time: DateTime<Utc>,
+}
+
+impl GraphClient {
+    /// Subscribes `notification_url` to changes of any user. Graph validates
+    /// the URL before this returns, so it must already be served.
+    pub async fn create_users_subscription(
+        &self,
+        notification_url: &str,
+        client_state: &str,
+        expiration: DateTime<Utc>,
+    ) -> anyhow::Result<Subscription> {
+        let body = json!({
+            "changeType": "updated,deleted",
+            "notificationUrl": notification_url,
+            "resource": "users",
+            "expirationDateTime": expiration.to_rfc3339_opts(SecondsFormat::Secs, true),
+            "clientState": client_state,
+        });
+        self.request(
+            Method::POST,
+            &format!("{}/subscriptions", self.base_url()),
+            Some(&body),
+            None,
+        )
+        .await
+        .map_err(|e| anyhow::anyhow!("creating subscription; {}", e))
+    }
+
+    /// Extends the subscription with `id` until `expiration`.
+    pub async fn renew_subscription(
+        &self,
+        id: &str,
+        expiration: DateTime<Utc>,
+    ) -> anyhow::Result<Subscription> {
+        let body = json!({
+            "expirationDateTime": expiration.to_rfc3339_opts(SecondsFormat::Secs, true),
+        });
+        self.request(
+            Method::PATCH,
+            &format!("{}/subscriptions/{}", self.base_url(), id),
+            Some(&body),
+            None,
+        )
+        .await
+        .map_err(|e| anyhow::anyhow!("renewing subscription {}; {}", id, e))
+    }
+
+    pub async fn delete_subscription(&self, id: &str) -> anyhow::Result<()> {
+        self.request::<Value>(
+            Method::DELETE,
+            &format!("{}/subscriptions/{}", self.base_url(), id),
+            None,
+            None,
+        )
+        .await
+        .map_err(|e| anyhow::anyhow!("deleting subscription {}; {}", id, e))?;
+        Ok(())
+    }
+}
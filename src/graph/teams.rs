@@ -0,0 +1,75 @@
+use futures::future::try_join_all;
+use reqwest::Method;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::GraphClient;
+use crate::model::User;
+
+impl GraphClient {
+    /// Posts an Adaptive Card to a Teams channel as the signed-in user.
+    pub async fn post_channel_card(
+        &self,
+        team_id: &str,
+        channel_id: &str,
+        card: &Value,
+    ) -> anyhow::Result<()> {
+        let mut url = reqwest::Url::parse(&self.base_url())?;
+        url.path_segments_mut()
+            .map_err(|_| anyhow::anyhow!("invalid Graph base URL"))?
+            .extend(["teams", team_id, "channels", channel_id, "messages"]);
+        // the card is referenced from the body and passed as a JSON string
+        let body = json!({
+            "body": {
+                "contentType": "html",
+                "content": "<attachment id=\"card\"></attachment>",
+            },
+            "attachments": [{
+                "id": "card",
+                "contentType": "application/vnd.microsoft.card.adaptive",
+                "content": card.to_string(),
+            }],
+        });
+        self.request::<Value>(Method::POST, url.as_str(), Some(&body), None)
+            .await
+            .map_err(|e| anyhow::anyhow!("posting to channel {}; {}", channel_id, e))?;
+        Ok(())
+    }
+
+    /// Fetches the display names of the teams `user` is a member of,
+    /// sorted.
+    pub async fn fetch_joined_team_names(&self, user: &User) -> anyhow::Result<Vec<String>> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct TeamName {
+            display_name: Option<String>,
+        }
+
+        let mut url = self.user_url(&user.id, &["joinedTeams"])?;
+        url.query_pairs_mut().append_pair("$select", "displayName");
+        let teams: Vec<TeamName> = self
+            .get_all(url.to_string())
+            .await
+            .map_err(|e| anyhow::anyhow!("fetching teams of {}; {}", user.id, e))?;
+        let mut names: Vec<String> = teams
+            .into_iter()
+            .filter_map(|team| team.display_name)
+            .collect();
+        names.sort();
+        Ok(names)
+    }
+
+    /// Fills in the teams of `users` when configured with
+    /// [`with_joined_teams`](Self::with_joined_teams).
+    pub(super) async fn add_joined_teams(&self, users: &mut [User]) -> anyhow::Result<()> {
+        if !self.joined_teams {
+            return Ok(());
+        }
+        let fetches = users.iter().map(|user| self.fetch_joined_team_names(user));
+        let teams = try_join_all(fetches).await?;
+        for (user, teams) in users.iter_mut().zip(teams) {
+            user.joined_teams = teams;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,168 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+
+/// In-flight requests allowed when the run starts.
+pub const INITIAL_CONCURRENCY: usize = 10;
+
+/// Bounds the adaptive limit moves between.
+pub const MIN_CONCURRENCY: usize = 1;
+pub const MAX_CONCURRENCY: usize = 32;
+
+/// Consecutive healthy responses needed before the limit grows by one.
+const SUCCESSES_PER_INCREASE: usize = 20;
+
+/// Prefix of the headers Graph sends when a client nears its quota, e.g.
+/// `x-ms-throttle-limit-percentage`.
+const THROTTLE_HEADER_PREFIX: &str = "x-ms-throttle-";
+
+/// Adaptive (AIMD) limit on in-flight Graph requests.
+///
+/// The limit is halved on `429`/`503` responses, cut by a quarter when Graph
+/// warns that the quota is nearly used up, and grows by one after a streak of
+/// healthy responses.
+pub struct ConcurrencyController {
+    state: Mutex<State>,
+    released: Notify,
+}
+
+struct State {
+    limit: usize,
+    min: usize,
+    max: usize,
+    in_flight: usize,
+    successes: usize,
+}
+
+/// Holds one in-flight slot until dropped.
+pub struct Permit<'a> {
+    controller: &'a ConcurrencyController,
+}
+
+impl Default for ConcurrencyController {
+    fn default() -> Self {
+        Self::new(INITIAL_CONCURRENCY, MIN_CONCURRENCY, MAX_CONCURRENCY)
+    }
+}
+
+impl ConcurrencyController {
+    pub fn new(initial: usize, min: usize, max: usize) -> Self {
+        let min = min.max(1);
+        let max = max.max(min);
+        Self {
+            state: Mutex::new(State {
+                limit: initial.clamp(min, max),
+                min,
+                max,
+                in_flight: 0,
+                successes: 0,
+            }),
+            released: Notify::new(),
+        }
+    }
+
+    /// Current in-flight limit.
+    pub fn limit(&self) -> usize {
+        self.state.lock().unwrap().limit
+    }
+
+    /// Waits for a free slot under the current limit.
+    pub async fn acquire(&self) -> Permit<'_> {
+        loop {
+            let notified = self.released.notified();
+            {
+                let mut state = self.state.lock().unwrap();
+                if state.in_flight < state.limit {
+                    state.in_flight += 1;
+                    return Permit { controller: self };
+                }
+            }
+            notified.await;
+        }
+    }
+
+    /// Adjusts the limit from the status and headers of a response.
+    pub fn record(&self, status: StatusCode, headers: &HeaderMap) {
+        let throttled =
+            status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::SERVICE_UNAVAILABLE;
+        let warned = headers
+            .keys()
+            .any(|name| name.as_str().starts_with(THROTTLE_HEADER_PREFIX));
+
+        if warned {
+            for (name, value) in headers {
+                if name.as_str().starts_with(THROTTLE_HEADER_PREFIX) {
+                    tracing::trace!(header = name.as_str(), value = ?value, "throttle warning");
+                }
+            }
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let before = state.limit;
+        if throttled {
+            state.limit = (state.limit / 2).max(state.min);
+            state.successes = 0;
+        } else if warned {
+            state.limit = (state.limit * 3 / 4).max(state.min);
+            state.successes = 0;
+        } else if status.is_success() {
+            state.successes += 1;
+            if state.successes >= SUCCESSES_PER_INCREASE {
+                state.limit = (state.limit + 1).min(state.max);
+                state.successes = 0;
+                let limit = state.limit;
+                drop(state);
+                self.released.notify_waiters();
+                if limit > before {
+                    tracing::trace!(limit, "raised the concurrency limit");
+                }
+                return;
+            }
+        }
+        if state.limit < before {
+            tracing::debug!(limit = state.limit, "lowered the concurrency limit");
+        }
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        self.controller.state.lock().unwrap().in_flight -= 1;
+        self.controller.released.notify_waiters();
+    }
+}
+
+/// Spaces requests evenly so no more than a fixed number start per second.
+pub struct RateLimiter {
+    interval: Duration,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    /// Allows `per_second` requests per second, which must be positive.
+    pub fn new(per_second: f64) -> Self {
+        Self {
+            interval: Duration::from_secs_f64(1.0 / per_second),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub fn per_second(&self) -> f64 {
+        1.0 / self.interval.as_secs_f64()
+    }
+
+    /// Waits until the next request may start.
+    pub async fn wait(&self) {
+        let at = {
+            let mut next = self.next.lock().unwrap();
+            let at = (*next).max(Instant::now());
+            *next = at + self.interval;
+            at
+        };
+        tokio::time::sleep_until(at).await;
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+
+use chrono::NaiveDate;
+use clap::ValueEnum;
+use reqwest::Method;
+use serde::Deserialize;
+
+use super::GraphClient;
+use crate::model::{Usage, User};
+
+/// Days of Microsoft 365 activity the usage report covers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum UsagePeriod {
+    D7,
+    D30,
+    D90,
+    D180,
+}
+
+impl UsagePeriod {
+    /// The period as Graph names it, e.g. `D30`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UsagePeriod::D7 => "D7",
+            UsagePeriod::D30 => "D30",
+            UsagePeriod::D90 => "D90",
+            UsagePeriod::D180 => "D180",
+        }
+    }
+}
+
+impl GraphClient {
+    /// Fetches the last activity dates per workload of the users active in
+    /// Microsoft 365 over `period`, by lowercase user principal name.
+    pub async fn fetch_usage(&self, period: UsagePeriod) -> anyhow::Result<HashMap<String, Usage>> {
+        #[derive(Deserialize)]
+        struct Row {
+            #[serde(rename = "User Principal Name")]
+            user_principal_name: String,
+            #[serde(rename = "Exchange Last Activity Date")]
+            exchange: Option<NaiveDate>,
+            #[serde(rename = "OneDrive Last Activity Date")]
+            one_drive: Option<NaiveDate>,
+            #[serde(rename = "SharePoint Last Activity Date")]
+            share_point: Option<NaiveDate>,
+            #[serde(rename = "Teams Last Activity Date")]
+            teams: Option<NaiveDate>,
+            #[serde(rename = "Yammer Last Activity Date")]
+            yammer: Option<NaiveDate>,
+        }
+
+        // Graph answers with a redirect to a CSV download, which reqwest
+        // follows
+        let url = format!(
+            "{}/reports/getOffice365ActiveUserDetail(period='{}')",
+            self.base_url(),
+            period.as_str()
+        );
+        let response = self
+            .send_with_retries(&Method::GET, &url, None, true)
+            .await?;
+        let status = response.status();
+        let body = response.text().await?;
+        if !status.is_success() {
+            anyhow::bail!("fetching the usage report; {}: {}", status, body)
+        }
+        let mut reader = csv::Reader::from_reader(body.trim_start_matches('\u{feff}').as_bytes());
+        let mut usage = HashMap::new();
+        for row in reader.deserialize() {
+            let row: Row = row.map_err(|e| anyhow::anyhow!("reading the usage report; {}", e))?;
+            usage.insert(
+                row.user_principal_name.to_lowercase(),
+                Usage {
+                    exchange: row.exchange,
+                    one_drive: row.one_drive,
+                    share_point: row.share_point,
+                    teams: row.teams,
+                    yammer: row.yammer,
+                },
+            );
+        }
+        Ok(usage)
+    }
+
+    /// The usage of [`fetch_usage`](Self::fetch_usage), or `None` if the
+    /// report conceals user names and cannot be joined.
+    async fn fetch_joinable_usage(
+        &self,
+        period: UsagePeriod,
+    ) -> anyhow::Result<Option<HashMap<String, Usage>>> {
+        let usage = self.fetch_usage(period).await?;
+        // hashed names are the default since 2021
+        if !usage.is_empty() && !usage.keys().any(|name| name.contains('@')) {
+            tracing::warn!(
+                "Leaving out usage; the report conceals user names, which is set in the \
+                 Microsoft 365 admin center under Settings > Org settings > Reports"
+            );
+            return Ok(None);
+        }
+        Ok(Some(usage))
+    }
+
+    /// Fills in the usage of `users`, looked up by user principal name,
+    /// when configured with [`with_usage`](Self::with_usage). Users missing
+    /// from the report have no activity in the period.
+    pub(super) async fn add_usage(&self, users: &mut [User]) -> anyhow::Result<()> {
+        let Some(period) = self.usage_period else {
+            return Ok(());
+        };
+        let Some(usage) = self
+            .usage
+            .get_or_try_init(|| self.fetch_joinable_usage(period))
+            .await?
+        else {
+            return Ok(());
+        };
+        for user in users {
+            let Some(name) = user
+                .property("userPrincipalName")
+                .as_str()
+                .map(str::to_lowercase)
+            else {
+                continue;
+            };
+            user.usage = Some(usage.get(&name).cloned().unwrap_or_default());
+        }
+        Ok(())
+    }
+}
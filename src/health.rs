@@ -0,0 +1,126 @@
+//! The health of `--daemon`, served on `--health-listen`.
+
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::webhook::{read_request, respond_with};
+
+/// What the daemon has done so far, shared with the health listener.
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthState {
+    pub started: DateTime<Utc>,
+    pub runs: u64,
+    pub consecutive_failures: u64,
+    pub last_run_started: Option<DateTime<Utc>>,
+    pub last_run_finished: Option<DateTime<Utc>>,
+    pub last_success: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub running: bool,
+    pub next_run: Option<DateTime<Utc>>,
+}
+
+/// The daemon's [`HealthState`], updated as runs start and finish.
+#[derive(Clone, Debug)]
+pub struct Health {
+    state: Arc<Mutex<HealthState>>,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Health {
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(HealthState {
+                started: Utc::now(),
+                ..HealthState::default()
+            })),
+        }
+    }
+
+    pub fn state(&self) -> HealthState {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn update(&self, f: impl FnOnce(&mut HealthState)) {
+        f(&mut self.state.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    pub fn scheduled(&self, next_run: Option<DateTime<Utc>>) {
+        self.update(|state| state.next_run = next_run)
+    }
+
+    pub fn run_started(&self) {
+        self.update(|state| {
+            state.running = true;
+            state.last_run_started = Some(Utc::now());
+        })
+    }
+
+    pub fn run_finished(&self, result: &anyhow::Result<()>) {
+        self.update(|state| {
+            let now = Utc::now();
+            state.running = false;
+            state.runs += 1;
+            state.last_run_finished = Some(now);
+            match result {
+                Ok(()) => {
+                    state.consecutive_failures = 0;
+                    state.last_success = Some(now);
+                    state.last_error = None;
+                }
+                Err(e) => {
+                    state.consecutive_failures += 1;
+                    state.last_error = Some(format!("{:#}", e));
+                }
+            }
+        })
+    }
+
+    /// Answers `GET /healthz` on `listen` with the state as JSON: 200 while
+    /// the latest run succeeded or none has finished yet, else 503.
+    pub async fn serve(self, listen: SocketAddr) -> anyhow::Result<()> {
+        let listener = TcpListener::bind(listen)
+            .await
+            .map_err(|e| anyhow::anyhow!("listening on {}; {}", listen, e))?;
+        tracing::info!("Serving health on http://{}/healthz", listen);
+        tokio::spawn(async move {
+            loop {
+                let Ok((stream, _)) = listener.accept().await else {
+                    continue;
+                };
+                let health = self.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = health.handle(stream).await {
+                        tracing::debug!("Handling a health request failed; {}", e);
+                    }
+                });
+            }
+        });
+        Ok(())
+    }
+
+    async fn handle(&self, mut stream: TcpStream) -> anyhow::Result<()> {
+        let (head, _) = read_request(&mut stream).await?;
+        // e.g. "GET /healthz HTTP/1.1"
+        let path = head.lines().next().and_then(|l| l.split(' ').nth(1));
+        if path.and_then(|path| path.split('?').next()) != Some("/healthz") {
+            return respond_with(&mut stream, "404 Not Found", "text/plain", "").await;
+        }
+        let state = self.state();
+        let status = match state.consecutive_failures {
+            0 => "200 OK",
+            _ => "503 Service Unavailable",
+        };
+        let body = serde_json::to_string_pretty(&state)?;
+        respond_with(&mut stream, status, "application/json", &body).await
+    }
+}
@@ -0,0 +1,23 @@
+//! Dump the reporting tree of a Microsoft Graph (Azure AD) user.
+
+pub mod auth;
+pub mod browse;
+pub mod checkpoint;
+pub mod classification;
+pub mod cloud;
+pub mod delta;
+pub mod diff;
+pub mod graph;
+pub mod health;
+pub mod logging;
+pub mod model;
+pub mod output;
+pub mod progress;
+pub mod report;
+pub mod schedule;
+pub mod serve;
+pub mod snapshot;
+pub mod webhook;
+
+pub use graph::GraphClient;
+pub use model::{User, UsersResponse};
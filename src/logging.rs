@@ -0,0 +1,306 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{self, Write as _};
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use chrono::{SecondsFormat, Utc};
+use clap::ValueEnum;
+use serde_json::{Map, Value};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Metadata, Subscriber};
+
+/// How log lines are written to stderr.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// One human readable line per event.
+    #[default]
+    Text,
+    /// One JSON object per event, with the fields of its spans.
+    Json,
+}
+
+/// Level of the events logged for the number of `-v` flags: progress at
+/// `0`, every Graph request at `1` and throttling details at `2`.
+pub fn level(verbosity: u8) -> Level {
+    match verbosity {
+        0 => Level::INFO,
+        1 => Level::DEBUG,
+        _ => Level::TRACE,
+    }
+}
+
+/// Logs the events at `level` and above to stderr for the rest of the run.
+pub fn init(level: Level, format: LogFormat) -> anyhow::Result<()> {
+    tracing::subscriber::set_global_default(Logger::new(level, format))?;
+    Ok(())
+}
+
+/// A span and the fields recorded on it.
+struct SpanData {
+    name: &'static str,
+    fields: Vec<(&'static str, Value)>,
+    parent: Option<u64>,
+    references: usize,
+}
+
+thread_local! {
+    /// Spans entered on this thread, innermost last.
+    static CURRENT: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+}
+
+/// A small subscriber writing events with the context of their spans.
+pub struct Logger {
+    level: Level,
+    format: LogFormat,
+    spans: Mutex<HashMap<u64, SpanData>>,
+    next_id: AtomicU64,
+}
+
+impl Logger {
+    pub fn new(level: Level, format: LogFormat) -> Self {
+        Self {
+            level,
+            format,
+            spans: Mutex::default(),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    fn current() -> Option<u64> {
+        CURRENT.with(|current| current.borrow().last().copied())
+    }
+
+    /// Names and fields of `span` and its parents, outermost first.
+    fn scope(&self, mut span: Option<u64>) -> Vec<(&'static str, Vec<(&'static str, Value)>)> {
+        let spans = self.spans.lock().unwrap();
+        let mut scope = Vec::new();
+        while let Some(data) = span.and_then(|id| spans.get(&id)) {
+            scope.push((data.name, data.fields.clone()));
+            span = data.parent;
+        }
+        scope.reverse();
+        scope
+    }
+}
+
+/// Names and fields of the spans an event happened in, outermost first.
+type Scope = [(&'static str, Vec<(&'static str, Value)>)];
+
+fn format_text(
+    metadata: &Metadata<'_>,
+    scope: &Scope,
+    message: &str,
+    fields: &[(&'static str, Value)],
+) -> String {
+    let mut line = format!(
+        "{} {:>5} ",
+        Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        metadata.level()
+    );
+    for (name, fields) in scope {
+        line.push_str(name);
+        if !fields.is_empty() {
+            line.push('{');
+            write_fields(&mut line, fields);
+            line.push('}');
+        }
+        line.push(':');
+    }
+    if !scope.is_empty() {
+        line.push(' ');
+    }
+    line.push_str(message);
+    if !fields.is_empty() {
+        line.push(' ');
+        write_fields(&mut line, fields);
+    }
+    line
+}
+
+fn format_json(
+    metadata: &Metadata<'_>,
+    scope: &Scope,
+    message: &str,
+    fields: &[(&'static str, Value)],
+) -> String {
+    let object = |name: Option<&str>, fields: &[(&'static str, Value)]| {
+        let mut object = Map::new();
+        if let Some(name) = name {
+            object.insert("name".to_string(), name.into());
+        }
+        for (key, value) in fields {
+            object.insert(key.to_string(), value.clone());
+        }
+        Value::Object(object)
+    };
+    let mut line = Map::new();
+    line.insert(
+        "timestamp".to_string(),
+        Utc::now()
+            .to_rfc3339_opts(SecondsFormat::Millis, true)
+            .into(),
+    );
+    line.insert("level".to_string(), metadata.level().as_str().into());
+    line.insert("target".to_string(), metadata.target().into());
+    line.insert("message".to_string(), message.into());
+    line.insert("fields".to_string(), object(None, fields));
+    line.insert(
+        "spans".to_string(),
+        scope
+            .iter()
+            .map(|(name, fields)| object(Some(name), fields))
+            .collect(),
+    );
+    Value::Object(line).to_string()
+}
+
+fn write_fields(line: &mut String, fields: &[(&'static str, Value)]) {
+    for (i, (key, value)) in fields.iter().enumerate() {
+        if i > 0 {
+            line.push(' ');
+        }
+        let _ = match value {
+            Value::String(s) => write!(line, "{}={}", key, s),
+            value => write!(line, "{}={}", key, value),
+        };
+    }
+}
+
+/// Collects the fields of a span or event, keeping `message` apart.
+#[derive(Default)]
+struct Fields {
+    message: Option<String>,
+    fields: Vec<(&'static str, Value)>,
+}
+
+impl Fields {
+    fn push(&mut self, field: &Field, value: Value) {
+        match (field.name(), value) {
+            ("message", Value::String(message)) => self.message = Some(message),
+            (name, value) => self.fields.push((name, value)),
+        }
+    }
+}
+
+impl Visit for Fields {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.push(field, value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.push(field, value.into());
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.push(field, value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.push(field, value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.push(field, value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.push(field, format!("{:?}", value).into());
+    }
+}
+
+impl Subscriber for Logger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        // leaves out the events of dependencies such as hyper
+        metadata.level() <= &self.level && metadata.target().starts_with(env!("CARGO_CRATE_NAME"))
+    }
+
+    fn max_level_hint(&self) -> Option<tracing::level_filters::LevelFilter> {
+        Some(self.level.into())
+    }
+
+    fn new_span(&self, attributes: &Attributes<'_>) -> Id {
+        let mut fields = Fields::default();
+        attributes.record(&mut fields);
+        let parent = if attributes.is_contextual() {
+            Self::current()
+        } else {
+            attributes.parent().map(Id::into_u64)
+        };
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.spans.lock().unwrap().insert(
+            id,
+            SpanData {
+                name: attributes.metadata().name(),
+                fields: fields.fields,
+                parent,
+                references: 1,
+            },
+        );
+        Id::from_u64(id)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        let mut fields = Fields::default();
+        values.record(&mut fields);
+        if let Some(data) = self.spans.lock().unwrap().get_mut(&span.into_u64()) {
+            data.fields.extend(fields.fields);
+        }
+    }
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let span = if event.is_contextual() {
+            Self::current()
+        } else {
+            event.parent().map(Id::into_u64)
+        };
+        let scope = self.scope(span);
+        let message = fields.message.unwrap_or_default();
+        let line = match self.format {
+            LogFormat::Text => format_text(event.metadata(), &scope, &message, &fields.fields),
+            LogFormat::Json => format_json(event.metadata(), &scope, &message, &fields.fields),
+        };
+        crate::progress::suspend(|| {
+            let _ = writeln!(std::io::stderr().lock(), "{}", line);
+        });
+    }
+
+    fn enter(&self, span: &Id) {
+        CURRENT.with(|current| current.borrow_mut().push(span.into_u64()));
+    }
+
+    fn exit(&self, span: &Id) {
+        CURRENT.with(|current| {
+            let mut current = current.borrow_mut();
+            if let Some(i) = current.iter().rposition(|&id| id == span.into_u64()) {
+                current.remove(i);
+            }
+        });
+    }
+
+    fn clone_span(&self, span: &Id) -> Id {
+        if let Some(data) = self.spans.lock().unwrap().get_mut(&span.into_u64()) {
+            data.references += 1;
+        }
+        span.clone()
+    }
+
+    fn try_close(&self, span: Id) -> bool {
+        let mut spans = self.spans.lock().unwrap();
+        let Some(data) = spans.get_mut(&span.into_u64()) else {
+            return false;
+        };
+        data.references -= 1;
+        if data.references == 0 {
+            spans.remove(&span.into_u64());
+            return true;
+        }
+        false
+    }
+}
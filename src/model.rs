@@ -0,0 +1,482 @@
+use std::fmt::Display;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::classification::Classification;
+
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct User {
+    pub id: String,
+    pub display_name: String,
+    pub job_title: Option<String>,
+    pub department: Option<String>,
+    pub mail: Option<String>,
+    pub office_location: Option<String>,
+    /// Id of the user in the HR system.
+    pub employee_id: Option<String>,
+    /// e.g. `Employee`, `Contractor` or `Vendor`.
+    pub employee_type: Option<String>,
+    pub employee_hire_date: Option<String>,
+    pub company_name: Option<String>,
+    /// Contact details, selected with `--contact`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub business_phones: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mobile_phone: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub city: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+    /// Two-letter country code where the user's services are used, e.g.
+    /// `US`; required for assigning licenses.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage_location: Option<String>,
+    /// Every address the user receives mail at, e.g.
+    /// `SMTP:primary@contoso.com` and `smtp:alias@contoso.com`; selected with
+    /// `--addresses`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub proxy_addresses: Vec<String>,
+    /// Manager expanded inline with `$expand=manager`; the root of a dump
+    /// carries its own manager this way.
+    #[serde(default, skip_serializing)]
+    pub manager: Option<Box<User>>,
+    /// How the user changed since the previous run, in delta mode.
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub change: Option<Change>,
+    /// Levels below the root of the walk, once known; roots are at depth 0.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub depth: Option<usize>,
+    /// Display names of the managers from the root of the walk down to the
+    /// user's manager.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub management_chain: Vec<String>,
+    /// Ids of the managers in [`management_chain`](Self::management_chain),
+    /// which tell apart managers of the same name.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub management_chain_ids: Vec<String>,
+    /// Set when the walk stopped at `--max-depth` although the user has
+    /// reports.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+    /// Number of direct and indirect reports in the dump, once the whole
+    /// tree is known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub headcount: Option<usize>,
+    /// Display names of the groups the user is a member of, with
+    /// `--memberships`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<String>,
+    /// Display names of the teams the user is a member of, with
+    /// `--joined-teams`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub joined_teams: Vec<String>,
+    /// Devices of the user, with `--devices`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub devices: Vec<Device>,
+    /// Part numbers of the licenses assigned to the user, with `--licenses`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub licenses: Vec<String>,
+    /// Settings of the user's mailbox, with `--mailbox-settings`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mailbox_settings: Option<MailboxSettings>,
+    /// The user's Microsoft 365 activity, with `--usage`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+    /// The authentication methods the user registered, with `--mfa`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mfa: Option<MfaRegistration>,
+    /// The user's current Teams presence, with `--presence`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub presence: Option<Presence>,
+    /// Any other properties returned by Graph, e.g. those selected with
+    /// `--fields`.
+    #[serde(flatten)]
+    pub properties: Map<String, Value>,
+}
+
+/// The parts of a user's mailbox settings that are dumped.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MailboxSettings {
+    /// e.g. `Pacific Standard Time`.
+    pub time_zone: Option<String>,
+    /// e.g. `en-US`.
+    pub locale: Option<String>,
+    /// Whether automatic replies are enabled or scheduled.
+    pub auto_reply: bool,
+    /// `disabled`, `alwaysEnabled` or `scheduled`.
+    #[serde(default)]
+    pub auto_reply_status: Option<String>,
+    /// When scheduled automatic replies start.
+    #[serde(default)]
+    pub auto_reply_start: Option<DateTime<Utc>>,
+    /// When scheduled automatic replies end.
+    #[serde(default)]
+    pub auto_reply_end: Option<DateTime<Utc>>,
+}
+
+impl MailboxSettings {
+    /// Whether automatic replies are on at `now`: always, or within their
+    /// schedule.
+    pub fn out_of_office(&self, now: DateTime<Utc>) -> bool {
+        match self.auto_reply_status.as_deref() {
+            Some("alwaysEnabled") => true,
+            Some("scheduled") => {
+                self.auto_reply_start.is_none_or(|start| start <= now)
+                    && self.auto_reply_end.is_none_or(|end| now < end)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// A device owned by or registered to a user.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Device {
+    pub display_name: Option<String>,
+    /// e.g. `Windows` or `iOS`.
+    pub operating_system: Option<String>,
+    pub operating_system_version: Option<String>,
+    /// Whether Intune deems the device compliant; missing for unmanaged
+    /// devices.
+    pub is_compliant: Option<bool>,
+}
+
+impl Display for Device {
+    /// e.g. `LAPTOP-42 (Windows 10.0.22631, compliant)`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.display_name.as_deref().unwrap_or("unnamed"))?;
+        let os = [&self.operating_system, &self.operating_system_version]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join(" ");
+        let compliance = self.is_compliant.map(|compliant| match compliant {
+            true => "compliant",
+            false => "noncompliant",
+        });
+        let details: Vec<&str> = [(!os.is_empty()).then_some(os.as_str()), compliance]
+            .into_iter()
+            .flatten()
+            .collect();
+        if !details.is_empty() {
+            write!(f, " ({})", details.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// A user's presence at the time of the dump.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Presence {
+    /// e.g. `Available`, `Busy`, `Away` or `Offline`.
+    pub availability: Option<String>,
+    /// e.g. `InACall`, `InAMeeting` or `OutOfOffice`.
+    pub activity: Option<String>,
+}
+
+/// The authentication methods a user registered.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MfaRegistration {
+    /// Whether the user registered a method that counts for MFA.
+    #[serde(default)]
+    pub is_mfa_registered: bool,
+    /// Whether that method is also allowed by the tenant's policies.
+    #[serde(default)]
+    pub is_mfa_capable: bool,
+    /// e.g. `microsoftAuthenticatorPush`, `fido2` or `mobilePhone`.
+    #[serde(default)]
+    pub methods_registered: Vec<String>,
+}
+
+/// When a user was last active in each Microsoft 365 workload, from the
+/// usage report; `None` if not in its period.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Usage {
+    pub exchange: Option<NaiveDate>,
+    pub one_drive: Option<NaiveDate>,
+    pub share_point: Option<NaiveDate>,
+    pub teams: Option<NaiveDate>,
+    pub yammer: Option<NaiveDate>,
+}
+
+impl Usage {
+    /// When the user was last active in `workload`.
+    pub fn last_activity_in(&self, workload: Workload) -> Option<NaiveDate> {
+        match workload {
+            Workload::Exchange => self.exchange,
+            Workload::OneDrive => self.one_drive,
+            Workload::SharePoint => self.share_point,
+            Workload::Teams => self.teams,
+            Workload::Yammer => self.yammer,
+        }
+    }
+
+    /// When the user was last active in any workload.
+    pub fn last_activity(&self) -> Option<NaiveDate> {
+        Workload::ALL
+            .into_iter()
+            .filter_map(|workload| self.last_activity_in(workload))
+            .max()
+    }
+}
+
+/// A Microsoft 365 service whose use the usage report tracks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Workload {
+    Exchange,
+    OneDrive,
+    SharePoint,
+    Teams,
+    Yammer,
+}
+
+impl Workload {
+    pub const ALL: [Workload; 5] = [
+        Workload::Exchange,
+        Workload::OneDrive,
+        Workload::SharePoint,
+        Workload::Teams,
+        Workload::Yammer,
+    ];
+}
+
+/// Kind of change reported by a delta query.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    Add,
+    Update,
+    Delete,
+}
+
+impl Change {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Change::Add => "add",
+            Change::Update => "update",
+            Change::Delete => "delete",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsersResponse {
+    pub value: Vec<User>,
+    #[serde(rename = "@odata.nextLink")]
+    pub next_link: Option<String>,
+}
+
+impl User {
+    /// A row of a [`Report`](crate::report::Report) on other directory
+    /// objects, carried as a user so it is written by the same sinks; its
+    /// columns are [`Column::Property`](crate::output::Column::Property)s
+    /// looked up in `properties`.
+    pub fn record(
+        id: impl Into<String>,
+        display_name: impl Into<String>,
+        properties: Map<String, Value>,
+    ) -> Self {
+        Self {
+            id: id.into(),
+            display_name: display_name.into(),
+            job_title: None,
+            department: None,
+            mail: None,
+            office_location: None,
+            employee_id: None,
+            employee_type: None,
+            employee_hire_date: None,
+            company_name: None,
+            business_phones: Vec::new(),
+            mobile_phone: None,
+            city: None,
+            country: None,
+            usage_location: None,
+            proxy_addresses: Vec::new(),
+            manager: None,
+            change: None,
+            depth: None,
+            management_chain: Vec::new(),
+            management_chain_ids: Vec::new(),
+            truncated: false,
+            headcount: None,
+            groups: Vec::new(),
+            joined_teams: Vec::new(),
+            devices: Vec::new(),
+            licenses: Vec::new(),
+            mailbox_settings: None,
+            presence: None,
+            usage: None,
+            mfa: None,
+            properties,
+        }
+    }
+
+    /// Records `manager` as the user's manager in the walk, below the root.
+    pub fn set_walk_manager(&mut self, manager: &User) {
+        self.depth = Some(manager.depth.unwrap_or(0) + 1);
+        self.management_chain = manager.management_chain.clone();
+        self.management_chain.push(manager.display_name.clone());
+        self.management_chain_ids = manager.management_chain_ids.clone();
+        self.management_chain_ids.push(manager.id.clone());
+    }
+
+    /// When the user last signed in interactively, if `signInActivity` was
+    /// fetched and they ever did.
+    pub fn last_sign_in(&self) -> Option<DateTime<Utc>> {
+        let at = self.properties.get("signInActivity")?["lastSignInDateTime"].as_str()?;
+        DateTime::parse_from_rfc3339(at)
+            .ok()
+            .map(|at| at.with_timezone(&Utc))
+    }
+
+    /// Whether `accountEnabled` was fetched and is false.
+    pub fn is_disabled(&self) -> bool {
+        self.properties.get("accountEnabled") == Some(&Value::Bool(false))
+    }
+
+    /// Whether `userType` was fetched and is `Guest`.
+    pub fn is_guest(&self) -> bool {
+        self.properties.get("userType").and_then(Value::as_str) == Some("Guest")
+    }
+
+    /// The properties of the open extension named `name`, if expanded;
+    /// `Null` if missing.
+    pub fn open_extension(&self, name: &str) -> Value {
+        let extensions = self.properties.get("extensions").and_then(Value::as_array);
+        let Some(extension) = extensions
+            .into_iter()
+            .flatten()
+            .find(|extension| extension["extensionName"] == name)
+        else {
+            return Value::Null;
+        };
+        let mut properties = extension.as_object().cloned().unwrap_or_default();
+        properties.retain(|key, _| key != "id" && key != "extensionName" && !key.starts_with('@'));
+        Value::Object(properties)
+    }
+
+    /// Looks up a Graph property by its camelCase name; `Null` if missing.
+    pub fn property(&self, name: &str) -> Value {
+        let known = match name {
+            "id" => return Value::String(self.id.clone()),
+            "displayName" => return Value::String(self.display_name.clone()),
+            "jobTitle" => &self.job_title,
+            "department" => &self.department,
+            "mail" => &self.mail,
+            "officeLocation" => &self.office_location,
+            "employeeId" => &self.employee_id,
+            "employeeType" => &self.employee_type,
+            "employeeHireDate" => &self.employee_hire_date,
+            "companyName" => &self.company_name,
+            "mobilePhone" => &self.mobile_phone,
+            "city" => &self.city,
+            "country" => &self.country,
+            "usageLocation" => &self.usage_location,
+            "businessPhones" if self.business_phones.is_empty() => return Value::Null,
+            "businessPhones" => return Value::from(self.business_phones.join("; ")),
+            "proxyAddresses" if self.proxy_addresses.is_empty() => return Value::Null,
+            "proxyAddresses" => return Value::from(self.proxy_addresses.join("; ")),
+            _ => return self.properties.get(name).cloned().unwrap_or(Value::Null),
+        };
+        known.clone().map_or(Value::Null, Value::String)
+    }
+
+    pub fn get_email(&self) -> &str {
+        self.mail.as_deref().unwrap_or("unknown")
+    }
+
+    pub fn get_department(&self) -> &str {
+        self.department.as_deref().unwrap_or("unknown")
+    }
+
+    pub fn get_job_title(&self) -> &str {
+        self.job_title.as_deref().unwrap_or("unknown")
+    }
+
+    pub fn get_office_location(&self) -> &str {
+        self.office_location.as_deref().unwrap_or("unknown")
+    }
+
+    /// Employment type and location labels, from the classification rules.
+    pub fn get_category(&self) -> (&str, &str) {
+        let classification = Classification::current();
+        (
+            classification.employment_type.classify(self),
+            classification.location.classify(self),
+        )
+    }
+}
+
+impl Display for User {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let job_title = self.get_job_title();
+        let office_location = self.get_office_location();
+        let mail = self.get_email();
+        let department = self.get_department();
+        let (employment_type, location) = self.get_category();
+        write!(
+            f,
+            "{}, {}, {}, {}, {}, {}, {}, {}",
+            self.id,
+            self.display_name,
+            mail,
+            job_title,
+            department,
+            office_location,
+            employment_type,
+            location
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    fn settings(status: &str, start: Option<&str>, end: Option<&str>) -> MailboxSettings {
+        MailboxSettings {
+            time_zone: None,
+            locale: None,
+            auto_reply: status != "disabled",
+            auto_reply_status: Some(status.to_string()),
+            auto_reply_start: start.map(at),
+            auto_reply_end: end.map(at),
+        }
+    }
+
+    #[test]
+    fn out_of_office() {
+        let now = at("2024-07-10T12:00:00Z");
+        assert!(settings("alwaysEnabled", None, None).out_of_office(now));
+        assert!(!settings("disabled", None, None).out_of_office(now));
+        let scheduled = |start, end| settings("scheduled", start, end).out_of_office(now);
+        assert!(scheduled(
+            Some("2024-07-01T00:00:00Z"),
+            Some("2024-07-15T00:00:00Z")
+        ));
+        assert!(!scheduled(
+            Some("2024-07-11T00:00:00Z"),
+            Some("2024-07-15T00:00:00Z")
+        ));
+        // the end is exclusive
+        assert!(!scheduled(
+            Some("2024-07-01T00:00:00Z"),
+            Some("2024-07-10T12:00:00Z")
+        ));
+        assert!(scheduled(None, None));
+    }
+}
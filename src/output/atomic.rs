@@ -0,0 +1,81 @@
+use std::path::{Path, PathBuf};
+
+use super::OutputSink;
+use crate::model::User;
+
+/// The hidden file next to `path` the output is written to until the dump
+/// is complete.
+pub fn partial_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.partial", name))
+}
+
+/// Writes the output to a partial file and renames it to the output path
+/// once the dump is complete, so readers never see a half-written dump.
+///
+/// The partial file is removed when the dump fails, unless it was flushed
+/// for a checkpoint.
+pub struct AtomicFileSink {
+    inner: Option<Box<dyn OutputSink + Send>>,
+    partial: PathBuf,
+    path: PathBuf,
+    flushed: bool,
+}
+
+impl AtomicFileSink {
+    /// Wraps `inner`, which writes to `partial`.
+    pub fn new(inner: Box<dyn OutputSink + Send>, partial: PathBuf, path: PathBuf) -> Self {
+        Self {
+            inner: Some(inner),
+            partial,
+            path,
+            flushed: false,
+        }
+    }
+
+    fn inner(&mut self) -> anyhow::Result<&mut Box<dyn OutputSink + Send>> {
+        self.inner
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("{} is already complete", self.path.display()))
+    }
+}
+
+impl OutputSink for AtomicFileSink {
+    fn write_user(&mut self, user: &User, manager: Option<&User>) -> anyhow::Result<()> {
+        self.inner()?.write_user(user, manager)
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.flushed = true;
+        self.inner()?.flush()
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        // only complete output is renamed, e.g. with the end of a
+        // compressed stream written
+        self.inner()?.finish()?;
+        // close the file (or database) before renaming it
+        self.inner = None;
+        std::fs::rename(&self.partial, &self.path)
+            .map_err(|e| anyhow::anyhow!("saving {}; {}", self.path.display(), e))
+    }
+}
+
+impl Drop for AtomicFileSink {
+    fn drop(&mut self) {
+        if self.inner.take().is_none() {
+            return;
+        }
+        if self.flushed {
+            tracing::warn!(
+                "The dump is incomplete; the users written so far are in {}",
+                self.partial.display()
+            );
+        } else {
+            let _ = std::fs::remove_file(&self.partial);
+        }
+    }
+}
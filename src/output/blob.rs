@@ -0,0 +1,153 @@
+use std::sync::Arc;
+
+use base64::Engine;
+use chrono::Utc;
+use reqwest::{Client, Url};
+
+use super::upload::{block_on, Upload};
+use crate::auth::TokenProvider;
+
+/// Resource of the managed identity tokens for Azure Storage.
+pub const STORAGE_RESOURCE: &str = "https://storage.azure.com/";
+
+/// Storage REST API version of the requests.
+const API_VERSION: &str = "2021-08-06";
+
+/// Output uploaded as one block. A blob has at most
+/// 50,000 blocks, so dumps of up to about 200 GB fit.
+const BLOCK_SIZE: usize = 4 * 1024 * 1024;
+
+/// A blob the output is uploaded to, e.g.
+/// `https://account.blob.core.windows.net/dumps/users.csv`.
+#[derive(Clone)]
+pub struct BlobDestination {
+    client: Client,
+    url: Url,
+    /// Authorizes the requests unless the URL carries a SAS token.
+    token_provider: Option<Arc<dyn TokenProvider>>,
+}
+
+impl std::fmt::Debug for BlobDestination {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // leaves out the SAS token
+        f.debug_struct("BlobDestination")
+            .field("url", &self.display_url())
+            .finish()
+    }
+}
+
+impl BlobDestination {
+    /// Uploads to `url`, authorized by its SAS token (`sig=...`) or else by
+    /// tokens of `token_provider`.
+    pub fn new(client: Client, url: Url, token_provider: Arc<dyn TokenProvider>) -> Self {
+        let has_sas = url.query_pairs().any(|(key, _)| key == "sig");
+        Self {
+            client,
+            url,
+            token_provider: (!has_sas).then_some(token_provider),
+        }
+    }
+
+    /// The URL without its query, for messages.
+    pub fn display_url(&self) -> String {
+        let mut url = self.url.clone();
+        url.set_query(None);
+        url.to_string()
+    }
+
+    /// The file name at the end of the URL path, e.g. `users.csv.gz`.
+    pub fn file_name(&self) -> Option<&str> {
+        self.url
+            .path_segments()?
+            .next_back()
+            .filter(|name| !name.is_empty())
+    }
+
+    /// Starts an upload; nothing is visible in the container until it is
+    /// committed at the end of the dump.
+    pub fn upload(&self) -> Box<dyn Upload> {
+        Box::new(BlobUpload {
+            destination: self.clone(),
+            blocks: Vec::new(),
+        })
+    }
+
+    async fn put(&self, query: &[(&str, &str)], body: Vec<u8>) -> anyhow::Result<()> {
+        let mut url = self.url.clone();
+        url.query_pairs_mut().extend_pairs(query);
+        let mut request = self
+            .client
+            .put(url)
+            .header("x-ms-version", API_VERSION)
+            .header(
+                "x-ms-date",
+                Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string(),
+            )
+            .body(body);
+        if let Some(token_provider) = &self.token_provider {
+            request = request.bearer_auth(token_provider.access_token().await?);
+        }
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let response_txt = response.text().await?;
+            anyhow::bail!("{}: {}", status, response_txt)
+        }
+        Ok(())
+    }
+}
+
+/// The blocks of a blob staged so far.
+struct BlobUpload {
+    destination: BlobDestination,
+    /// Ids of the staged blocks, in order.
+    blocks: Vec<String>,
+}
+
+impl Upload for BlobUpload {
+    fn part_size(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    fn put_part(&mut self, data: Vec<u8>, _last: bool) -> anyhow::Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        // ids must be the same length within a blob
+        let id = base64::engine::general_purpose::STANDARD
+            .encode(format!("block-{:08}", self.blocks.len()));
+        block_on(
+            self.destination
+                .put(&[("comp", "block"), ("blockid", &id)], data),
+        )
+        .map_err(|e| {
+            anyhow::anyhow!(
+                "uploading block {} of {}; {}",
+                self.blocks.len(),
+                self.destination.display_url(),
+                e
+            )
+        })?;
+        self.blocks.push(id);
+        Ok(())
+    }
+
+    /// Makes the staged blocks the content of the blob. The blocks of a
+    /// failed dump are discarded by Azure Storage after a week.
+    fn commit(&mut self) -> anyhow::Result<()> {
+        let mut list = String::from(r#"<?xml version="1.0" encoding="utf-8"?><BlockList>"#);
+        for id in &self.blocks {
+            list.push_str(&format!("<Latest>{}</Latest>", id));
+        }
+        list.push_str("</BlockList>");
+        block_on(
+            self.destination
+                .put(&[("comp", "blocklist")], list.into_bytes()),
+        )
+        .map_err(|e| anyhow::anyhow!("saving {}; {}", self.destination.display_url(), e))
+    }
+
+    fn describe(&self) -> String {
+        self.destination.display_url()
+    }
+}
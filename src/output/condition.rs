@@ -0,0 +1,673 @@
+use std::cmp::Ordering;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde_json::Value;
+
+use super::{Column, OutputSink};
+use crate::model::User;
+
+/// A `--where` condition on the written users, e.g.
+/// `department == 'Engineering' && location == 'On-Site'`.
+///
+/// Fields are the `--fields` names; values are single or double quoted
+/// strings, numbers, `true`, `false` and `null`. Comparisons are `==`, `!=`,
+/// `<`, `<=`, `>`, `>=` and `contains`, combined with `&&`, `||`, `!` and
+/// parentheses. A field on its own is true unless it is missing, `false`,
+/// `0` or empty. Functions such as `years_since(employeeHireDate)` compute
+/// values from fields; see [`Function`].
+///
+/// Evaluated with [`Condition::eval`], a condition is also the expression of
+/// a [`Computed`] column.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Condition {
+    Compare(Operand, Op, Operand),
+    Truthy(Operand),
+    Not(Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Operand {
+    Field(Column),
+    Value(Value),
+    Call(Function, Vec<Condition>),
+}
+
+/// A function of an expression.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Function {
+    /// `years_since(date)`: whole years since a date, e.g. a hire date.
+    YearsSince,
+    /// `days_since(date)`: whole days since a date.
+    DaysSince,
+    /// `lower(text)`
+    Lower,
+    /// `upper(text)`
+    Upper,
+    /// `len(value)`: characters of a text or items of a list.
+    Len,
+    /// `if(condition, then, else)`
+    If,
+    /// `coalesce(a, b, ...)`: the first argument that is not missing.
+    Coalesce,
+}
+
+impl Function {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "years_since" => Self::YearsSince,
+            "days_since" => Self::DaysSince,
+            "lower" => Self::Lower,
+            "upper" => Self::Upper,
+            "len" => Self::Len,
+            "if" => Self::If,
+            "coalesce" => Self::Coalesce,
+            _ => return None,
+        })
+    }
+
+    /// Whether the function takes `count` arguments.
+    fn takes(&self, count: usize) -> bool {
+        match self {
+            Self::If => count == 3,
+            Self::Coalesce => count > 0,
+            _ => count == 1,
+        }
+    }
+
+    fn call(&self, arguments: &[Condition], user: &User, manager: Option<&User>) -> Value {
+        let argument = |i: usize| arguments[i].eval(user, manager);
+        match self {
+            Self::YearsSince => parse_date(&argument(0))
+                .and_then(|date| Utc::now().date_naive().years_since(date))
+                .map_or(Value::Null, Value::from),
+            Self::DaysSince => parse_date(&argument(0)).map_or(Value::Null, |date| {
+                Value::from((Utc::now().date_naive() - date).num_days())
+            }),
+            Self::Lower | Self::Upper => match argument(0) {
+                Value::String(s) if *self == Self::Lower => Value::from(s.to_lowercase()),
+                Value::String(s) => Value::from(s.to_uppercase()),
+                value => value,
+            },
+            Self::Len => match argument(0) {
+                Value::String(s) => Value::from(s.chars().count()),
+                Value::Array(items) => Value::from(items.len()),
+                _ => Value::Null,
+            },
+            Self::If => match arguments[0].matches(user, manager) {
+                true => argument(1),
+                false => argument(2),
+            },
+            Self::Coalesce => (0..arguments.len())
+                .map(argument)
+                .find(|value| !value.is_null())
+                .unwrap_or(Value::Null),
+        }
+    }
+}
+
+/// A date or date and time such as `2019-03-01T00:00:00Z`.
+fn parse_date(value: &Value) -> Option<NaiveDate> {
+    let s = value.as_str()?;
+    match DateTime::parse_from_rfc3339(s) {
+        Ok(at) => Some(at.with_timezone(&Utc).date_naive()),
+        Err(_) => NaiveDate::parse_from_str(s, "%Y-%m-%d").ok(),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+}
+
+impl Op {
+    fn symbol(&self) -> &'static str {
+        match self {
+            Op::Eq => "==",
+            Op::Ne => "!=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+            Op::Contains => "contains",
+        }
+    }
+}
+
+impl Condition {
+    /// Parses a condition, naming the position of the first error.
+    pub fn parse(condition: &str) -> anyhow::Result<Self> {
+        let tokens = tokenize(condition)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let parsed = parser.or()?;
+        if let Some((at, token)) = parser.tokens.get(parser.pos) {
+            anyhow::bail!("unexpected {} at position {}", token, at)
+        }
+        Ok(parsed)
+    }
+
+    /// The columns the condition looks at.
+    pub fn columns(&self) -> Vec<Column> {
+        let mut columns = Vec::new();
+        self.collect_columns(&mut columns);
+        columns
+    }
+
+    fn collect_columns(&self, columns: &mut Vec<Column>) {
+        match self {
+            Self::Compare(left, _, right) => {
+                left.collect_columns(columns);
+                right.collect_columns(columns);
+            }
+            Self::Truthy(operand) => operand.collect_columns(columns),
+            Self::Not(condition) => condition.collect_columns(columns),
+            Self::And(left, right) | Self::Or(left, right) => {
+                left.collect_columns(columns);
+                right.collect_columns(columns);
+            }
+        }
+    }
+
+    /// Whether `user`, written below `manager`, matches.
+    pub fn matches(&self, user: &User, manager: Option<&User>) -> bool {
+        match self {
+            Self::Compare(left, op, right) => {
+                compare(&left.value(user, manager), *op, &right.value(user, manager))
+            }
+            Self::Truthy(operand) => is_truthy(&operand.value(user, manager)),
+            Self::Not(condition) => !condition.matches(user, manager),
+            Self::And(left, right) => left.matches(user, manager) && right.matches(user, manager),
+            Self::Or(left, right) => left.matches(user, manager) || right.matches(user, manager),
+        }
+    }
+
+    /// The value of the expression for `user`: the value of a lone field,
+    /// value or function, and otherwise whether `user` matches.
+    pub fn eval(&self, user: &User, manager: Option<&User>) -> Value {
+        match self {
+            Self::Truthy(operand) => operand.value(user, manager),
+            condition => Value::Bool(condition.matches(user, manager)),
+        }
+    }
+}
+
+impl Operand {
+    fn value(&self, user: &User, manager: Option<&User>) -> Value {
+        match self {
+            Self::Field(column) => column.value(user, manager),
+            Self::Value(value) => value.clone(),
+            Self::Call(function, arguments) => function.call(arguments, user, manager),
+        }
+    }
+
+    fn collect_columns(&self, columns: &mut Vec<Column>) {
+        match self {
+            Self::Field(column) => {
+                if !columns.contains(column) {
+                    columns.push(column.clone());
+                }
+            }
+            Self::Value(_) => {}
+            Self::Call(_, arguments) => {
+                for argument in arguments {
+                    argument.collect_columns(columns);
+                }
+            }
+        }
+    }
+}
+
+/// A column computed from the others, e.g.
+/// `tenure_years = years_since(employeeHireDate)` or
+/// `is_manager = headcount > 0`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Computed {
+    pub name: String,
+    pub expression: Condition,
+}
+
+impl Computed {
+    /// Parses a `NAME = EXPRESSION` definition.
+    pub fn parse(definition: &str) -> anyhow::Result<Self> {
+        let (name, expression) = definition
+            .split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("expected NAME = EXPRESSION in {:?}", definition))?;
+        let name = name.trim();
+        let Column::Property(name) = Column::parse(name)? else {
+            anyhow::bail!("{:?} is already a column", name)
+        };
+        let expression = Condition::parse(expression)
+            .map_err(|e| anyhow::anyhow!("parsing column {}; {}", name, e))?;
+        Ok(Self { name, expression })
+    }
+}
+
+fn compare(left: &Value, op: Op, right: &Value) -> bool {
+    match op {
+        Op::Eq => equals(left, right),
+        Op::Ne => !equals(left, right),
+        Op::Contains => match (left, right) {
+            (Value::String(left), Value::String(right)) => left.contains(right.as_str()),
+            (Value::Array(items), right) => items.iter().any(|item| equals(item, right)),
+            _ => false,
+        },
+        Op::Lt | Op::Le | Op::Gt | Op::Ge => {
+            let Some(ordering) = order(left, right) else {
+                return false;
+            };
+            match op {
+                Op::Lt => ordering.is_lt(),
+                Op::Le => ordering.is_le(),
+                Op::Gt => ordering.is_gt(),
+                _ => ordering.is_ge(),
+            }
+        }
+    }
+}
+
+/// Numbers compare as numbers, also when one side is numeric text such as
+/// an `employeeId`.
+fn as_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+fn equals(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Number(_), _) | (_, Value::Number(_)) => {
+            match (as_number(left), as_number(right)) {
+                (Some(left), Some(right)) => left == right,
+                _ => false,
+            }
+        }
+        _ => left == right,
+    }
+}
+
+fn order(left: &Value, right: &Value) -> Option<Ordering> {
+    match (left, right) {
+        (Value::String(left), Value::String(right)) => Some(left.cmp(right)),
+        (Value::Null, _) | (_, Value::Null) => None,
+        _ => as_number(left)?.partial_cmp(&as_number(right)?),
+    }
+}
+
+fn is_truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64() != Some(0.0),
+        Value::String(s) => !s.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(_) => true,
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Number(f64),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+    Comma,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Ident(name) => write!(f, "{:?}", name),
+            Token::Str(s) => write!(f, "string {:?}", s),
+            Token::Number(n) => write!(f, "number {}", n),
+            Token::Op(op) => write!(f, "{:?}", op.symbol()),
+            Token::And => write!(f, "\"&&\""),
+            Token::Or => write!(f, "\"||\""),
+            Token::Not => write!(f, "\"!\""),
+            Token::Open => write!(f, "\"(\""),
+            Token::Close => write!(f, "\")\""),
+            Token::Comma => write!(f, "\",\""),
+        }
+    }
+}
+
+/// Splits a condition into tokens and the positions they start at.
+fn tokenize(condition: &str) -> anyhow::Result<Vec<(usize, Token)>> {
+    let mut tokens = Vec::new();
+    let mut chars = condition.char_indices().peekable();
+    while let Some(&(at, c)) = chars.peek() {
+        let token = match c {
+            c if c.is_whitespace() => {
+                chars.next();
+                continue;
+            }
+            '(' | ')' | ',' => {
+                chars.next();
+                match c {
+                    '(' => Token::Open,
+                    ')' => Token::Close,
+                    _ => Token::Comma,
+                }
+            }
+            '\'' | '"' => {
+                chars.next();
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, end)) if end == c => break,
+                        // `\'` and `\\` stand for the character itself
+                        Some((_, '\\')) if chars.peek().is_some() => {
+                            s.push(chars.next().unwrap().1)
+                        }
+                        Some((_, other)) => s.push(other),
+                        None => anyhow::bail!("unterminated string at position {}", at),
+                    }
+                }
+                Token::Str(s)
+            }
+            '=' | '!' | '<' | '>' | '&' | '|' => {
+                chars.next();
+                let next = chars.peek().map(|&(_, next)| next);
+                let (token, two) = match (c, next) {
+                    ('=', Some('=')) => (Token::Op(Op::Eq), true),
+                    ('!', Some('=')) => (Token::Op(Op::Ne), true),
+                    ('<', Some('=')) => (Token::Op(Op::Le), true),
+                    ('>', Some('=')) => (Token::Op(Op::Ge), true),
+                    ('&', Some('&')) => (Token::And, true),
+                    ('|', Some('|')) => (Token::Or, true),
+                    ('<', _) => (Token::Op(Op::Lt), false),
+                    ('>', _) => (Token::Op(Op::Gt), false),
+                    ('!', _) => (Token::Not, false),
+                    _ => anyhow::bail!("unknown operator {:?} at position {}", c, at),
+                };
+                if two {
+                    chars.next();
+                }
+                token
+            }
+            c if c.is_ascii_digit()
+                || c == '-' && condition[at + 1..].starts_with(|c: char| c.is_ascii_digit()) =>
+            {
+                let mut number = String::new();
+                while let Some(&(_, c)) = chars.peek() {
+                    if !(c.is_ascii_digit() || c == '.' || (c == '-' && number.is_empty())) {
+                        break;
+                    }
+                    number.push(c);
+                    chars.next();
+                }
+                let number = number.parse().map_err(|_| {
+                    anyhow::anyhow!("invalid number {:?} at position {}", number, at)
+                })?;
+                Token::Number(number)
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut name = String::new();
+                while let Some(&(_, c)) = chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_' || c == '.') {
+                        break;
+                    }
+                    name.push(c);
+                    chars.next();
+                }
+                match name.as_str() {
+                    "contains" => Token::Op(Op::Contains),
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    _ => Token::Ident(name),
+                }
+            }
+            c => anyhow::bail!("unexpected {:?} at position {}", c, at),
+        };
+        tokens.push((at, token));
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent over the tokens; `||` binds weakest, then `&&`, then
+/// `!`.
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, token)| token)
+    }
+
+    fn next(&mut self) -> anyhow::Result<Token> {
+        let (_, token) = self
+            .tokens
+            .get(self.pos)
+            .ok_or_else(|| anyhow::anyhow!("unexpected end of condition"))?;
+        self.pos += 1;
+        Ok(token.clone())
+    }
+
+    fn or(&mut self) -> anyhow::Result<Condition> {
+        let mut condition = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            condition = Condition::Or(Box::new(condition), Box::new(self.and()?));
+        }
+        Ok(condition)
+    }
+
+    fn and(&mut self) -> anyhow::Result<Condition> {
+        let mut condition = self.not()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            condition = Condition::And(Box::new(condition), Box::new(self.not()?));
+        }
+        Ok(condition)
+    }
+
+    fn not(&mut self) -> anyhow::Result<Condition> {
+        if self.peek() == Some(&Token::Not) {
+            self.pos += 1;
+            return Ok(Condition::Not(Box::new(self.not()?)));
+        }
+        if self.peek() == Some(&Token::Open) {
+            self.pos += 1;
+            let condition = self.or()?;
+            match self.next()? {
+                Token::Close => return Ok(condition),
+                token => anyhow::bail!("expected \")\" instead of {}", token),
+            }
+        }
+        let left = self.operand()?;
+        match self.peek() {
+            Some(&Token::Op(op)) => {
+                self.pos += 1;
+                Ok(Condition::Compare(left, op, self.operand()?))
+            }
+            _ => Ok(Condition::Truthy(left)),
+        }
+    }
+
+    fn operand(&mut self) -> anyhow::Result<Operand> {
+        Ok(match self.next()? {
+            Token::Ident(name) => match name.as_str() {
+                "true" => Operand::Value(Value::Bool(true)),
+                "false" => Operand::Value(Value::Bool(false)),
+                "null" => Operand::Value(Value::Null),
+                _ if self.peek() == Some(&Token::Open) => {
+                    let function = Function::parse(&name)
+                        .ok_or_else(|| anyhow::anyhow!("unknown function {:?}", name))?;
+                    let arguments = self.arguments()?;
+                    if !function.takes(arguments.len()) {
+                        anyhow::bail!("wrong number of arguments to {}", name)
+                    }
+                    Operand::Call(function, arguments)
+                }
+                _ => Operand::Field(Column::extension(&name)?),
+            },
+            Token::Str(s) => Operand::Value(Value::String(s)),
+            // whole numbers stay integers in computed columns
+            Token::Number(n) if n.fract() == 0.0 && n.abs() < i64::MAX as f64 => {
+                Operand::Value(Value::from(n as i64))
+            }
+            Token::Number(n) => Operand::Value(Value::from(n)),
+            token => anyhow::bail!("expected a field or value instead of {}", token),
+        })
+    }
+
+    /// The parenthesized, comma separated arguments of a function.
+    fn arguments(&mut self) -> anyhow::Result<Vec<Condition>> {
+        self.pos += 1;
+        let mut arguments = Vec::new();
+        if self.peek() == Some(&Token::Close) {
+            self.pos += 1;
+            return Ok(arguments);
+        }
+        loop {
+            arguments.push(self.or()?);
+            match self.next()? {
+                Token::Comma => {}
+                Token::Close => return Ok(arguments),
+                token => anyhow::bail!("expected \",\" or \")\" instead of {}", token),
+            }
+        }
+    }
+}
+
+/// Passes only the users matching a [`Condition`] to the inner sink.
+pub(super) struct ConditionSink {
+    pub(super) inner: Box<dyn OutputSink + Send>,
+    pub(super) condition: Condition,
+}
+
+impl OutputSink for ConditionSink {
+    fn write_user(&mut self, user: &User, manager: Option<&User>) -> anyhow::Result<()> {
+        if self.condition.matches(user, manager) {
+            self.inner.write_user(user, manager)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.inner.flush()
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.inner.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Map;
+
+    use super::*;
+
+    fn parse(condition: &str) -> Condition {
+        Condition::parse(condition).unwrap()
+    }
+
+    fn error(condition: &str) -> String {
+        Condition::parse(condition).unwrap_err().to_string()
+    }
+
+    fn user(department: Option<&str>) -> User {
+        let mut user = User::record("u1", "Ann", Map::new());
+        user.employee_id = Some("0042".to_string());
+        user.department = department.map(str::to_string);
+        user
+    }
+
+    fn matches(condition: &str, user: &User) -> bool {
+        parse(condition).matches(user, None)
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(parse("a || b && c"), parse("a || (b && c)"));
+        assert_ne!(parse("a || b && c"), parse("(a || b) && c"));
+        assert_eq!(parse("a && b || c && d"), parse("(a && b) || (c && d)"));
+        assert_eq!(parse("!a && b"), parse("(!a) && b"));
+        assert_eq!(parse("a or b and not c"), parse("a || (b && !c)"));
+
+        let sales = user(Some("Sales"));
+        assert!(matches(
+            "department == 'Sales' || department == 'HR' && false",
+            &sales
+        ));
+        assert!(!matches(
+            "(department == 'Sales' || department == 'HR') && false",
+            &sales
+        ));
+    }
+
+    #[test]
+    fn string_escapes() {
+        let value = |condition: &str| match parse(condition) {
+            Condition::Truthy(Operand::Value(value)) => value,
+            condition => panic!("{:?} is not a value", condition),
+        };
+        assert_eq!(value(r"'O\'Brien'"), "O'Brien");
+        assert_eq!(value(r#""say \"hi\"""#), "say \"hi\"");
+        assert_eq!(value(r"'C:\\Users'"), r"C:\Users");
+        assert_eq!(value(r#""it's""#), "it's");
+        assert_eq!(value("''"), "");
+    }
+
+    #[test]
+    fn missing_fields() {
+        let nobody = user(None);
+        assert!(matches("department == null", &nobody));
+        assert!(matches("department != 'Sales'", &nobody));
+        assert!(!matches("department == 'Sales'", &nobody));
+        assert!(!matches("department < 'Z'", &nobody));
+        assert!(!matches("department >= 'A'", &nobody));
+        assert!(!matches("department contains 'a'", &nobody));
+        assert!(!matches("department", &nobody));
+        assert!(matches("!department", &nobody));
+        assert_eq!(
+            parse("coalesce(department, 'none')").eval(&nobody, None),
+            "none"
+        );
+        assert_eq!(parse("len(department)").eval(&nobody, None), Value::Null);
+    }
+
+    #[test]
+    fn numeric_text_compares_as_number() {
+        let ann = user(Some("Sales"));
+        assert!(matches("employeeId == 42", &ann));
+        assert!(matches("employeeId < 100", &ann));
+        assert!(matches("employeeId == '0042'", &ann));
+    }
+
+    #[test]
+    fn malformed_conditions() {
+        assert_eq!(error("department =="), "unexpected end of condition");
+        assert_eq!(error("'Sales"), "unterminated string at position 0");
+        assert_eq!(error("a = b"), "unknown operator '=' at position 2");
+        assert_eq!(error("a & b"), "unknown operator '&' at position 2");
+        assert_eq!(error("a b"), "unexpected \"b\" at position 2");
+        assert_eq!(error("a == #"), "unexpected '#' at position 5");
+        assert_eq!(error("(a == 1"), "unexpected end of condition");
+        assert_eq!(error("(a == 1 b"), "expected \")\" instead of \"b\"");
+        assert_eq!(error("== 1"), "expected a field or value instead of \"==\"");
+        assert_eq!(error("foo(1)"), "unknown function \"foo\"");
+        assert_eq!(error("len(1, 2)"), "wrong number of arguments to len");
+        assert_eq!(
+            error("coalesce(a b)"),
+            "expected \",\" or \")\" instead of \"b\""
+        );
+    }
+}
@@ -0,0 +1,120 @@
+use std::io::Write;
+
+use clap::ValueEnum;
+
+use super::{Column, OutputSink};
+use crate::model::User;
+
+/// The dialect of `csv` output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CsvOptions {
+    /// e.g. `;` for Excel in European locales, or a tab.
+    pub delimiter: u8,
+    /// Whether the first row names the columns.
+    pub header: bool,
+    pub quoting: Quoting,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            header: true,
+            quoting: Quoting::Necessary,
+        }
+    }
+}
+
+/// Which fields of `csv` output are quoted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Quoting {
+    /// Fields containing the delimiter, a quote or a line break.
+    #[default]
+    Necessary,
+    /// Every field.
+    Always,
+    /// Every field that isn't a number.
+    NonNumeric,
+    /// No field, even if that makes the row ambiguous.
+    Never,
+}
+
+impl Quoting {
+    fn style(self) -> ::csv::QuoteStyle {
+        match self {
+            Quoting::Necessary => ::csv::QuoteStyle::Necessary,
+            Quoting::Always => ::csv::QuoteStyle::Always,
+            Quoting::NonNumeric => ::csv::QuoteStyle::NonNumeric,
+            Quoting::Never => ::csv::QuoteStyle::Never,
+        }
+    }
+}
+
+/// Writes one CSV row per user, quoting fields as needed.
+///
+/// The header, if any, is written with the first row; in delta mode every
+/// row has a trailing `change` column.
+pub struct CsvSink<W: Write> {
+    writer: ::csv::Writer<W>,
+    columns: Vec<Column>,
+    null_as: Option<String>,
+    header_written: bool,
+}
+
+impl<W: Write> CsvSink<W> {
+    pub fn new(writer: W, columns: Vec<Column>) -> Self {
+        Self::with_options(writer, columns, CsvOptions::default())
+    }
+
+    pub fn with_options(writer: W, columns: Vec<Column>, options: CsvOptions) -> Self {
+        Self {
+            writer: ::csv::WriterBuilder::new()
+                .delimiter(options.delimiter)
+                .quote_style(options.quoting.style())
+                .from_writer(writer),
+            columns,
+            null_as: None,
+            // without a header there is none to write
+            header_written: !options.header,
+        }
+    }
+
+    /// Writes `null_as` for missing values instead of the placeholders.
+    pub fn with_null_as(mut self, null_as: Option<String>) -> Self {
+        self.null_as = null_as;
+        self
+    }
+}
+
+impl<W: Write> OutputSink for CsvSink<W> {
+    fn write_user(&mut self, user: &User, manager: Option<&User>) -> anyhow::Result<()> {
+        let change = user.change.map(|change| change.as_str());
+        if !self.header_written {
+            let mut header: Vec<String> = self.columns.iter().map(Column::name).collect();
+            if change.is_some() {
+                header.push("change".to_string());
+            }
+            self.writer.write_record(&header)?;
+            self.header_written = true;
+        }
+
+        let mut row: Vec<String> = self
+            .columns
+            .iter()
+            .map(|column| column.text_or(user, manager, self.null_as.as_deref()))
+            .collect();
+        row.extend(change.map(str::to_string));
+        self.writer.write_record(&row)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,81 @@
+use std::io::Write;
+
+use super::OutputSink;
+use crate::model::User;
+
+/// Writes the reporting tree as a Graphviz digraph, one node per user and an
+/// edge from each manager to their reportees.
+///
+/// Render with e.g. `dot -Tsvg org.dot -o org.svg`.
+pub struct DotSink<W: Write> {
+    writer: W,
+    started: bool,
+}
+
+impl<W: Write> DotSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            started: false,
+        }
+    }
+
+    fn start(&mut self) -> anyhow::Result<()> {
+        if !self.started {
+            writeln!(self.writer, "digraph org {{")?;
+            writeln!(self.writer, "    rankdir=TB;")?;
+            writeln!(
+                self.writer,
+                "    node [shape=box, style=rounded, fontname=\"Helvetica\"];"
+            )?;
+            self.started = true;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> OutputSink for DotSink<W> {
+    fn write_user(&mut self, user: &User, manager: Option<&User>) -> anyhow::Result<()> {
+        self.start()?;
+
+        let label = match &user.job_title {
+            Some(job_title) => format!("{}\n{}", user.display_name, job_title),
+            None => user.display_name.clone(),
+        };
+        writeln!(
+            self.writer,
+            "    \"{}\" [label=\"{}\"];",
+            escape(&user.id),
+            escape(&label)
+        )?;
+
+        if let Some(manager) = manager {
+            writeln!(
+                self.writer,
+                "    \"{}\" -> \"{}\";",
+                escape(&manager.id),
+                escape(&user.id)
+            )?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.start()?;
+        writeln!(self.writer, "}}")?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Escapes a string for use inside a double-quoted DOT id.
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
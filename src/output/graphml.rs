@@ -0,0 +1,107 @@
+use std::io::Write;
+
+use super::{escape_xml, OutputSink};
+use crate::model::User;
+
+/// Node attributes written for each user, in GraphML key order.
+const ATTRIBUTES: [&str; 7] = [
+    "display_name",
+    "mail",
+    "job_title",
+    "department",
+    "office_location",
+    "employment_type",
+    "location",
+];
+
+/// Writes the reporting tree as GraphML with user attributes as node data,
+/// for Gephi, Cytoscape and similar network analysis tools.
+pub struct GraphMlSink<W: Write> {
+    writer: W,
+    started: bool,
+}
+
+impl<W: Write> GraphMlSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            started: false,
+        }
+    }
+
+    fn start(&mut self) -> anyhow::Result<()> {
+        if self.started {
+            return Ok(());
+        }
+
+        writeln!(self.writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            self.writer,
+            r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+        )?;
+        for attribute in ATTRIBUTES {
+            writeln!(
+                self.writer,
+                r#"  <key id="{0}" for="node" attr.name="{0}" attr.type="string"/>"#,
+                attribute
+            )?;
+        }
+        writeln!(self.writer, r#"  <graph id="org" edgedefault="directed">"#)?;
+        self.started = true;
+        Ok(())
+    }
+}
+
+impl<W: Write> OutputSink for GraphMlSink<W> {
+    fn write_user(&mut self, user: &User, manager: Option<&User>) -> anyhow::Result<()> {
+        self.start()?;
+
+        let (employment_type, location) = user.get_category();
+        let values = [
+            Some(user.display_name.as_str()),
+            user.mail.as_deref(),
+            user.job_title.as_deref(),
+            user.department.as_deref(),
+            user.office_location.as_deref(),
+            Some(employment_type),
+            Some(location),
+        ];
+
+        writeln!(self.writer, r#"    <node id="{}">"#, escape_xml(&user.id))?;
+        for (attribute, value) in ATTRIBUTES.iter().zip(values) {
+            // missing attributes are left out rather than written as empty
+            if let Some(value) = value {
+                writeln!(
+                    self.writer,
+                    r#"      <data key="{}">{}</data>"#,
+                    attribute,
+                    escape_xml(value)
+                )?;
+            }
+        }
+        writeln!(self.writer, "    </node>")?;
+
+        if let Some(manager) = manager {
+            writeln!(
+                self.writer,
+                r#"    <edge source="{}" target="{}"/>"#,
+                escape_xml(&manager.id),
+                escape_xml(&user.id)
+            )?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.start()?;
+        writeln!(self.writer, "  </graph>")?;
+        writeln!(self.writer, "</graphml>")?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+use std::io::Write;
+
+use super::tree::{OrgNode, TreeBuilder};
+use super::{escape_xml, OutputSink};
+use crate::model::User;
+
+const HEAD: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>Org chart</title>
+<style>
+body { font-family: Helvetica, Arial, sans-serif; margin: 2em; }
+#search { width: 24em; padding: 0.4em; margin-bottom: 1em; }
+ul { list-style: none; padding-left: 1.5em; }
+li { margin: 0.2em 0; }
+summary, .leaf { cursor: default; }
+summary { cursor: pointer; }
+.name { font-weight: bold; }
+.title { color: #555; }
+.mail { color: #06c; font-size: 0.9em; }
+.count { color: #999; font-size: 0.85em; }
+.hidden { display: none; }
+.match > details > summary .name, .match > .leaf .name { background: #ff0; }
+</style>
+</head>
+<body>
+<input id="search" type="search" placeholder="Search name, title or email">
+<button id="expand">Expand all</button>
+<button id="collapse">Collapse all</button>
+"#;
+
+const SCRIPT: &str = r#"<script>
+const items = Array.from(document.querySelectorAll("li"));
+document.getElementById("search").addEventListener("input", (e) => {
+  const q = e.target.value.trim().toLowerCase();
+  items.forEach((li) => li.classList.remove("match", "hidden"));
+  if (!q) return;
+  items.forEach((li) => li.classList.add("hidden"));
+  items.forEach((li) => {
+    if (!li.dataset.search.includes(q)) return;
+    li.classList.add("match");
+    li.querySelectorAll("li").forEach((d) => d.classList.remove("hidden"));
+    for (let p = li; p; p = p.parentElement.closest("li")) {
+      p.classList.remove("hidden");
+      const details = p.querySelector(":scope > details");
+      if (details && p !== li) details.open = true;
+    }
+  });
+});
+const setAll = (open) => document.querySelectorAll("details").forEach((d) => (d.open = open));
+document.getElementById("expand").addEventListener("click", () => setAll(true));
+document.getElementById("collapse").addEventListener("click", () => setAll(false));
+</script>
+</body>
+</html>
+"#;
+
+/// Writes a self-contained HTML page with a collapsible, searchable org tree.
+pub struct HtmlSink<W: Write> {
+    writer: W,
+    tree: TreeBuilder,
+}
+
+impl<W: Write> HtmlSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            tree: TreeBuilder::default(),
+        }
+    }
+}
+
+impl<W: Write> OutputSink for HtmlSink<W> {
+    fn write_user(&mut self, user: &User, manager: Option<&User>) -> anyhow::Result<()> {
+        self.tree.push(user, manager);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        let roots = std::mem::take(&mut self.tree).build();
+
+        self.writer.write_all(HEAD.as_bytes())?;
+        writeln!(self.writer, "<ul>")?;
+        for root in &roots {
+            write_node(&mut self.writer, root, true)?;
+        }
+        writeln!(self.writer, "</ul>")?;
+        self.writer.write_all(SCRIPT.as_bytes())?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+fn write_node<W: Write>(writer: &mut W, node: &OrgNode, open: bool) -> anyhow::Result<()> {
+    let user = &node.user;
+    let search = [
+        Some(user.display_name.as_str()),
+        user.job_title.as_deref(),
+        user.mail.as_deref(),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>()
+    .join(" ")
+    .to_lowercase();
+
+    let mut label = format!(
+        r#"<span class="name">{}</span>"#,
+        escape_xml(&user.display_name)
+    );
+    if let Some(job_title) = &user.job_title {
+        label += &format!(r#" <span class="title">{}</span>"#, escape_xml(job_title));
+    }
+    if let Some(mail) = &user.mail {
+        label += &format!(
+            r#" <a class="mail" href="mailto:{0}">{0}</a>"#,
+            escape_xml(mail)
+        );
+    }
+
+    write!(writer, r#"<li data-search="{}">"#, escape_xml(&search))?;
+    if node.reports.is_empty() {
+        writeln!(writer, r#"<div class="leaf">{}</div></li>"#, label)?;
+        return Ok(());
+    }
+
+    writeln!(
+        writer,
+        r#"<details{}><summary>{} <span class="count">({})</span></summary><ul>"#,
+        if open { " open" } else { "" },
+        label,
+        node.reports.len()
+    )?;
+    for report in &node.reports {
+        write_node(writer, report, false)?;
+    }
+    writeln!(writer, "</ul></details></li>")?;
+    Ok(())
+}
@@ -0,0 +1,174 @@
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+use serde::ser::SerializeMap;
+use serde::{Serialize, Serializer};
+
+use super::{Column, OutputSink};
+use crate::model::User;
+
+/// A user with its manager linkage, as written to JSON outputs.
+pub struct JsonRecord<'a> {
+    pub user: &'a User,
+    pub manager: Option<&'a User>,
+    pub columns: &'a [Column],
+}
+
+impl<'a> JsonRecord<'a> {
+    pub fn new(user: &'a User, manager: Option<&'a User>, columns: &'a [Column]) -> Self {
+        Self {
+            user,
+            manager,
+            columns,
+        }
+    }
+}
+
+impl Serialize for JsonRecord<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut record = serializer.serialize_map(None)?;
+        for column in self.columns {
+            record.serialize_entry(column.json_name(), &column.value(self.user, self.manager))?;
+        }
+        if let Some(change) = self.user.change {
+            record.serialize_entry("change", &change)?;
+        }
+        record.end()
+    }
+}
+
+/// Writes all users as a single flat JSON array, streaming elements as they
+/// are fetched.
+pub struct JsonSink<W: Write> {
+    writer: W,
+    columns: Vec<Column>,
+    first: bool,
+}
+
+impl<W: Write> JsonSink<W> {
+    pub fn new(writer: W, columns: Vec<Column>) -> Self {
+        Self {
+            writer,
+            columns,
+            first: true,
+        }
+    }
+}
+
+impl<W: Write> OutputSink for JsonSink<W> {
+    fn write_user(&mut self, user: &User, manager: Option<&User>) -> anyhow::Result<()> {
+        if self.first {
+            self.writer.write_all(b"[\n")?;
+            self.first = false;
+        } else {
+            self.writer.write_all(b",\n")?;
+        }
+        serde_json::to_writer(
+            &mut self.writer,
+            &JsonRecord::new(user, manager, &self.columns),
+        )?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        if self.first {
+            self.writer.write_all(b"[")?;
+        }
+        self.writer.write_all(b"\n]\n")?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Users written between flushes of [`JsonLinesSink`] at most.
+const FLUSH_USERS: usize = 100;
+
+/// Time between flushes of [`JsonLinesSink`] at most, while users come in.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Writes one JSON object per line (JSON Lines), flushing every
+/// [`FLUSH_USERS`] users or [`FLUSH_INTERVAL`] so the output can be piped
+/// into other tools while the dump is running.
+pub struct JsonLinesSink<W: Write> {
+    writer: W,
+    columns: Vec<Column>,
+    /// Users written since the last flush.
+    unflushed: usize,
+    last_flush: Instant,
+}
+
+impl<W: Write> JsonLinesSink<W> {
+    pub fn new(writer: W, columns: Vec<Column>) -> Self {
+        Self {
+            writer,
+            columns,
+            unflushed: 0,
+            last_flush: Instant::now(),
+        }
+    }
+}
+
+impl<W: Write> OutputSink for JsonLinesSink<W> {
+    fn write_user(&mut self, user: &User, manager: Option<&User>) -> anyhow::Result<()> {
+        serde_json::to_writer(
+            &mut self.writer,
+            &JsonRecord::new(user, manager, &self.columns),
+        )?;
+        self.writer.write_all(b"\n")?;
+        self.unflushed += 1;
+        if self.unflushed >= FLUSH_USERS || self.last_flush.elapsed() >= FLUSH_INTERVAL {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.writer.flush()?;
+        self.unflushed = 0;
+        self.last_flush = Instant::now();
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Map;
+
+    use super::*;
+
+    /// Counts the flushes of the written output.
+    #[derive(Default)]
+    struct Flushes(usize);
+
+    impl Write for Flushes {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.0 += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn json_lines_are_flushed_in_batches() {
+        let mut sink = JsonLinesSink::new(Flushes::default(), Column::defaults());
+        let user = User::record("u1", "Ann", Map::new());
+        for _ in 0..250 {
+            sink.write_user(&user, None).unwrap();
+        }
+        assert_eq!(sink.writer.0, 2);
+        sink.finish().unwrap();
+        assert_eq!(sink.writer.0, 3);
+    }
+}
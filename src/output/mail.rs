@@ -0,0 +1,121 @@
+use std::path::{Path, PathBuf};
+
+use super::summary::OrgStats;
+use super::upload::block_on;
+use super::OutputSink;
+use crate::graph::mail::{Attachment, Mail};
+use crate::graph::GraphClient;
+use crate::model::User;
+
+/// Recipients the output file is emailed to once the dump is complete, from
+/// `--mail-to`.
+#[derive(Clone)]
+pub struct MailReport {
+    graph: GraphClient,
+    to: Vec<String>,
+    from: Option<String>,
+    subject: Option<String>,
+}
+
+impl std::fmt::Debug for MailReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MailReport")
+            .field("to", &self.to)
+            .field("from", &self.from)
+            .finish()
+    }
+}
+
+impl MailReport {
+    pub fn new(graph: GraphClient, to: Vec<String>) -> Self {
+        Self {
+            graph,
+            to,
+            from: None,
+            subject: None,
+        }
+    }
+
+    /// Sends from the mailbox of `from`, as app-only sign-ins have no
+    /// mailbox of their own, instead of the signed-in user's.
+    pub fn with_from(mut self, from: Option<String>) -> Self {
+        self.from = from;
+        self
+    }
+
+    /// Replaces the subject, by default `Org dump <file name>`.
+    pub fn with_subject(mut self, subject: Option<String>) -> Self {
+        self.subject = subject;
+        self
+    }
+}
+
+/// Emails the output file once the inner sink has completed it, with the
+/// [`OrgStats`] of the users written as the body.
+pub struct MailSink {
+    inner: Box<dyn OutputSink + Send>,
+    report: MailReport,
+    path: PathBuf,
+    stats: OrgStats,
+}
+
+impl MailSink {
+    pub fn new(inner: Box<dyn OutputSink + Send>, report: MailReport, path: &Path) -> Self {
+        Self {
+            inner,
+            report,
+            path: path.to_path_buf(),
+            stats: OrgStats::default(),
+        }
+    }
+}
+
+impl OutputSink for MailSink {
+    fn write_user(&mut self, user: &User, manager: Option<&User>) -> anyhow::Result<()> {
+        self.stats.add(user, manager);
+        self.inner.write_user(user, manager)
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.inner.flush()
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.inner.finish()?;
+        let name = self
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let content = std::fs::read(&self.path)
+            .map_err(|e| anyhow::anyhow!("reading {}; {}", self.path.display(), e))?;
+        let subject = self
+            .report
+            .subject
+            .clone()
+            .unwrap_or_else(|| format!("Org dump {}", name));
+        let body = format!(
+            "The {} dump of {} is attached.\n\n{}",
+            name,
+            chrono::Utc::now().format("%Y-%m-%d %H:%M UTC"),
+            self.stats
+        );
+        let mail = Mail {
+            to: &self.report.to,
+            subject: &subject,
+            body: &body,
+            attachment: Attachment { name, content },
+        };
+        block_on(
+            self.report
+                .graph
+                .send_mail(self.report.from.as_deref(), mail),
+        )?;
+        tracing::info!(
+            "Mailed {} to {}",
+            self.path.display(),
+            self.report.to.join(", ")
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,78 @@
+use std::io::Write;
+
+use super::OutputSink;
+use crate::model::User;
+
+/// Writes the reporting tree as a Mermaid flowchart, for wikis and Markdown
+/// renderers that support Mermaid natively.
+pub struct MermaidSink<W: Write> {
+    writer: W,
+    started: bool,
+}
+
+impl<W: Write> MermaidSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            started: false,
+        }
+    }
+
+    fn start(&mut self) -> anyhow::Result<()> {
+        if !self.started {
+            writeln!(self.writer, "flowchart TD")?;
+            self.started = true;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> OutputSink for MermaidSink<W> {
+    fn write_user(&mut self, user: &User, manager: Option<&User>) -> anyhow::Result<()> {
+        self.start()?;
+
+        let label = match &user.job_title {
+            Some(job_title) => format!("{}<br/>{}", escape(&user.display_name), escape(job_title)),
+            None => escape(&user.display_name),
+        };
+        writeln!(self.writer, "    {}[\"{}\"]", node_id(&user.id), label)?;
+
+        if let Some(manager) = manager {
+            writeln!(
+                self.writer,
+                "    {} --> {}",
+                node_id(&manager.id),
+                node_id(&user.id)
+            )?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.start()?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Mermaid node ids may only contain word characters.
+fn node_id(id: &str) -> String {
+    let id: String = id
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("u_{}", id)
+}
+
+/// Escapes a label for use inside a quoted Mermaid node text.
+fn escape(s: &str) -> String {
+    s.replace('&', "#amp;")
+        .replace('"', "#quot;")
+        .replace('<', "#lt;")
+        .replace('>', "#gt;")
+}
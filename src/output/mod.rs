@@ -0,0 +1,1247 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use clap::ValueEnum;
+use serde_json::Value;
+
+use crate::classification::Classification;
+use crate::model::{User, Workload};
+use crate::snapshot::SnapshotStore;
+use condition::{Computed, Condition, ConditionSink};
+
+pub mod atomic;
+pub mod blob;
+pub mod condition;
+pub mod csv;
+pub mod dot;
+pub mod graphml;
+pub mod html;
+pub mod json;
+pub mod mail;
+pub mod mermaid;
+pub mod parquet;
+pub mod post;
+pub mod postgres;
+pub mod s3;
+pub mod sharepoint;
+pub mod sqlite;
+pub mod summary;
+pub mod svg;
+pub mod teams;
+pub mod tree;
+pub mod upload;
+pub mod xlsx;
+
+/// Supported output formats.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// One row per user.
+    Csv,
+    /// A flat JSON array of users with their manager linkage.
+    Json,
+    /// One JSON object per line, written as soon as each user is fetched.
+    Jsonl,
+    /// The org as nested JSON, `{"user": ..., "reports": [...]}`.
+    JsonTree,
+    /// A Parquet file with the CSV columns, for data lakes.
+    Parquet,
+    /// A SQLite database with a `users` table; requires `--output`.
+    Sqlite,
+    /// An Excel workbook with a frozen, filterable header row.
+    Xlsx,
+    /// A Graphviz digraph of the reporting tree.
+    Dot,
+    /// A Mermaid flowchart of the reporting tree.
+    Mermaid,
+    /// GraphML with user attributes as node data.
+    Graphml,
+    /// A self-contained HTML page with a collapsible, searchable org tree.
+    Html,
+    /// An SVG org chart, laid out and rendered without external tools.
+    Svg,
+}
+
+/// Compression of the output stream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Compression {
+    /// gzip, readable everywhere (`.gz`).
+    Gzip,
+    /// Zstandard, smaller and faster (`.zst`).
+    Zstd,
+}
+
+impl Compression {
+    /// Guesses the compression from the extension of an output file.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "gz" | "gzip" => Some(Self::Gzip),
+            "zst" | "zstd" => Some(Self::Zstd),
+            _ => None,
+        }
+    }
+
+    /// Compresses everything written to `writer` as it is written. The
+    /// stream is only complete once the encoder is finished.
+    fn encoder(self, writer: Box<dyn Write + Send>) -> anyhow::Result<Encoder> {
+        Ok(match self {
+            Compression::Gzip => Encoder::Gzip(flate2::write::GzEncoder::new(
+                writer,
+                flate2::Compression::default(),
+            )),
+            Compression::Zstd => Encoder::Zstd(zstd::Encoder::new(writer, 0)?),
+        })
+    }
+}
+
+/// A compressing writer, which must be finished to write the end of the
+/// stream; dropping it ignores errors.
+enum Encoder {
+    Gzip(flate2::write::GzEncoder<Box<dyn Write + Send>>),
+    Zstd(zstd::Encoder<'static, Box<dyn Write + Send>>),
+}
+
+impl Encoder {
+    /// Writes the end of the stream and returns the flushed inner writer.
+    fn finish(self) -> io::Result<Box<dyn Write + Send>> {
+        let mut writer = match self {
+            Encoder::Gzip(encoder) => encoder.finish()?,
+            Encoder::Zstd(encoder) => encoder.finish()?,
+        };
+        writer.flush()?;
+        Ok(writer)
+    }
+}
+
+impl Write for Encoder {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Encoder::Gzip(encoder) => encoder.write(buf),
+            Encoder::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Encoder::Gzip(encoder) => encoder.flush(),
+            Encoder::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// The encoder shared by a format sink, which writes to it, and the
+/// [`CompressedSink`] finishing it.
+type SharedEncoder = Arc<Mutex<Option<Encoder>>>;
+
+/// Writes to a shared encoder until it is finished.
+struct CompressedWriter(SharedEncoder);
+
+impl Write for CompressedWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.0.lock().unwrap().as_mut() {
+            Some(encoder) => encoder.write(buf),
+            None => Err(io::Error::other(
+                "the compressed output is already complete",
+            )),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.0.lock().unwrap().as_mut() {
+            Some(encoder) => encoder.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Finishes the compressed stream once the inner sink, which writes to
+/// it, is complete, so a failure to write its end fails the dump.
+struct CompressedSink {
+    inner: Box<dyn OutputSink + Send>,
+    encoder: SharedEncoder,
+    /// The writer under the finished encoder, closed with the sink, e.g.
+    /// an upload that sends its last part then.
+    finished: Option<Box<dyn Write + Send>>,
+}
+
+impl OutputSink for CompressedSink {
+    fn write_user(&mut self, user: &User, manager: Option<&User>) -> anyhow::Result<()> {
+        self.inner.write_user(user, manager)
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.inner.flush()
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.inner.finish()?;
+        let encoder = self
+            .encoder
+            .lock()
+            .unwrap()
+            .take()
+            .ok_or_else(|| anyhow::anyhow!("the compressed output is already complete"))?;
+        let writer = encoder
+            .finish()
+            .map_err(|e| anyhow::anyhow!("completing the compressed output; {}", e))?;
+        self.finished = Some(writer);
+        Ok(())
+    }
+}
+
+/// Graph properties added by `--on-premises`, for joining the dump against
+/// on-premises Active Directory exports.
+pub const ON_PREMISES_PROPERTIES: [&str; 3] = [
+    "onPremisesSamAccountName",
+    "onPremisesDistinguishedName",
+    "onPremisesSyncEnabled",
+];
+
+/// Graph properties added by `--contact`, e.g. for emergency contact lists
+/// along the reporting line.
+pub const CONTACT_PROPERTIES: [&str; 5] = [
+    "businessPhones",
+    "mobilePhone",
+    "city",
+    "country",
+    "usageLocation",
+];
+
+/// Graph properties added by `--addresses`, for mail routing cleanups.
+pub const ADDRESS_PROPERTIES: [&str; 2] = ["userPrincipalName", "proxyAddresses"];
+
+/// Every [`Column::placeholder`], so readers of a dump can tell missing
+/// values apart.
+pub const PLACEHOLDERS: [&str; 4] = ["unknown", "none", "never", "inactive"];
+
+/// Format-specific output settings.
+#[derive(Clone, Debug)]
+pub struct OutputOptions {
+    /// Write one worksheet per department in `xlsx` output.
+    pub sheet_per_department: bool,
+    /// Delimiter, header and quoting of `csv` output.
+    pub csv: csv::CsvOptions,
+    /// Text written for missing values in `csv` and `xlsx` output instead
+    /// of the column's placeholder, e.g. an empty string.
+    pub null_as: Option<String>,
+    /// Direction in which the `svg` org chart grows.
+    pub svg_orientation: svg::Orientation,
+    /// Colors for the subtrees below the root in `svg` output.
+    pub svg_colors: Vec<String>,
+    /// Columns of the flat outputs.
+    pub columns: Vec<Column>,
+    /// Print org statistics to stderr once the dump is complete.
+    pub summary: bool,
+    /// Also record the run as a snapshot in this directory.
+    pub snapshot_dir: Option<PathBuf>,
+    /// Users left out of the output and the headcounts.
+    pub filter: UserFilter,
+    /// Only users matching this `--where` condition are written; the
+    /// headcounts still include the others.
+    pub condition: Option<Condition>,
+    /// Compress the output stream.
+    pub compression: Option<Compression>,
+    /// Write the output file under a temporary name and rename it once the
+    /// dump is complete.
+    pub atomic_output: bool,
+    /// Write each user id once, leaving out and logging later duplicates.
+    pub deduplicate: bool,
+    /// Upload the output instead of writing it to a file or stdout.
+    pub remote: Option<RemoteOutput>,
+    /// POST the users as JSON to an endpoint instead of writing them in
+    /// `format`.
+    pub post: Option<post::PostTarget>,
+    /// Upsert the users into a PostgreSQL table instead of writing them in
+    /// `format`.
+    pub postgres: Option<postgres::PostgresTarget>,
+    /// Write the users to a SharePoint list instead of in `format`.
+    pub sharepoint: Option<sharepoint::SharePointTarget>,
+    /// Email the output file once the dump is complete.
+    pub mail: Option<mail::MailReport>,
+    /// Post a summary card to Teams once the dump is complete.
+    pub teams: Option<teams::TeamsNotice>,
+}
+
+/// A remote object the output is uploaded to.
+#[derive(Clone, Debug)]
+pub enum RemoteOutput {
+    /// A blob in Azure Blob Storage, from `--blob-url`.
+    Blob(blob::BlobDestination),
+    /// An Amazon S3 object, from `--output s3://bucket/key`.
+    S3(s3::S3Object),
+}
+
+impl RemoteOutput {
+    /// The name of the object, which the format and compression are guessed
+    /// from like from a file name.
+    pub fn file_name(&self) -> Option<&str> {
+        match self {
+            Self::Blob(blob) => blob.file_name(),
+            Self::S3(object) => object.file_name(),
+        }
+    }
+
+    /// The object, for messages; without credentials.
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Blob(blob) => blob.display_url(),
+            Self::S3(object) => format!("{:?}", object),
+        }
+    }
+
+    fn upload(&self) -> Box<dyn upload::Upload> {
+        match self {
+            Self::Blob(blob) => blob.upload(),
+            Self::S3(object) => object.upload(),
+        }
+    }
+}
+
+/// Which users are left out of the output.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct UserFilter {
+    /// Leave out users whose account is disabled.
+    pub exclude_disabled: bool,
+    /// Leave out guest (B2B) users.
+    pub exclude_guests: bool,
+}
+
+impl UserFilter {
+    pub fn is_active(&self) -> bool {
+        self.exclude_disabled || self.exclude_guests
+    }
+
+    /// The Graph properties the filter looks at.
+    pub fn select(&self) -> Vec<String> {
+        let mut select = Vec::new();
+        if self.exclude_disabled {
+            select.push("accountEnabled".to_string());
+        }
+        if self.exclude_guests {
+            select.push("userType".to_string());
+        }
+        select
+    }
+
+    /// Whether `user` is written. Users missing the properties, e.g. those
+    /// deleted in a delta round, are kept.
+    pub fn includes(&self, user: &User) -> bool {
+        !(self.exclude_disabled && user.is_disabled() || self.exclude_guests && user.is_guest())
+    }
+}
+
+/// Passes only the users included by a [`UserFilter`] to the inner sink.
+struct FilterSink {
+    inner: Box<dyn OutputSink + Send>,
+    filter: UserFilter,
+}
+
+impl OutputSink for FilterSink {
+    fn write_user(&mut self, user: &User, manager: Option<&User>) -> anyhow::Result<()> {
+        if self.filter.includes(user) {
+            self.inner.write_user(user, manager)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.inner.flush()
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.inner.finish()
+    }
+}
+
+/// Passes each user id to the inner sink once; duplicates, e.g. from
+/// overlapping roots or messy directory data, are logged at the end.
+struct DedupSink {
+    inner: Box<dyn OutputSink + Send>,
+    ids: HashSet<String>,
+    /// Display names of the duplicates left out.
+    duplicates: Vec<String>,
+}
+
+/// Duplicates named in the warning; the rest are only counted.
+const DUPLICATES_NAMED: usize = 10;
+
+impl DedupSink {
+    fn new(inner: Box<dyn OutputSink + Send>) -> Self {
+        Self {
+            inner,
+            ids: HashSet::new(),
+            duplicates: Vec::new(),
+        }
+    }
+}
+
+impl OutputSink for DedupSink {
+    fn write_user(&mut self, user: &User, manager: Option<&User>) -> anyhow::Result<()> {
+        if self.ids.insert(user.id.clone()) {
+            return self.inner.write_user(user, manager);
+        }
+        tracing::debug!(
+            id = user.id,
+            "left out a duplicate of {}",
+            user.display_name
+        );
+        self.duplicates.push(user.display_name.clone());
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.inner.flush()
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        if !self.duplicates.is_empty() {
+            let mut named =
+                self.duplicates[..self.duplicates.len().min(DUPLICATES_NAMED)].join(", ");
+            if self.duplicates.len() > DUPLICATES_NAMED {
+                named.push_str(", ...");
+            }
+            tracing::warn!(
+                "Left out {} duplicate users: {}",
+                self.duplicates.len(),
+                named
+            );
+        }
+        self.inner.finish()
+    }
+}
+
+impl Default for OutputOptions {
+    fn default() -> Self {
+        Self {
+            sheet_per_department: false,
+            csv: csv::CsvOptions::default(),
+            null_as: None,
+            svg_orientation: Default::default(),
+            svg_colors: Vec::new(),
+            columns: Column::defaults(),
+            summary: false,
+            snapshot_dir: None,
+            filter: UserFilter::default(),
+            condition: None,
+            compression: None,
+            atomic_output: true,
+            deduplicate: true,
+            remote: None,
+            post: None,
+            postgres: None,
+            sharepoint: None,
+            mail: None,
+            teams: None,
+        }
+    }
+}
+
+impl OutputFormat {
+    /// Guesses the format from the extension of an output file.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "csv" | "tsv" => Some(Self::Csv),
+            "json" => Some(Self::Json),
+            "jsonl" | "ndjson" => Some(Self::Jsonl),
+            "parquet" => Some(Self::Parquet),
+            "db" | "sqlite" | "sqlite3" => Some(Self::Sqlite),
+            "xlsx" => Some(Self::Xlsx),
+            "dot" | "gv" => Some(Self::Dot),
+            "mmd" | "mermaid" => Some(Self::Mermaid),
+            "graphml" => Some(Self::Graphml),
+            "html" | "htm" => Some(Self::Html),
+            "svg" => Some(Self::Svg),
+            _ => None,
+        }
+    }
+}
+
+/// Creates the sink for `format`, writing to the file at `path` or to
+/// stdout, or uploading to [`OutputOptions::remote`]. With
+/// [`OutputOptions::post`] the users are POSTed instead.
+///
+/// With a [`Column::Headcount`] the users are held back until the whole tree
+/// is known, so that the headcounts can be rolled up. With
+/// [`OutputOptions::summary`] the org statistics are printed to stderr at the
+/// end, and with [`OutputOptions::snapshot_dir`] the run is also recorded as a
+/// snapshot. Users excluded by [`OutputOptions::filter`] or not matching
+/// [`OutputOptions::condition`] are dropped before all of these, and with [`OutputOptions::deduplicate`] repeated user ids
+/// before that.
+pub fn sink(
+    format: OutputFormat,
+    path: Option<&Path>,
+    options: &OutputOptions,
+) -> anyhow::Result<Box<dyn OutputSink + Send>> {
+    let mut sink = destination_sink(format, path, options)?;
+    if let Some(report) = &options.mail {
+        let path = path.ok_or_else(|| anyhow::anyhow!("only an output file can be mailed"))?;
+        sink = Box::new(mail::MailSink::new(sink, report.clone(), path));
+    }
+    if let Some(dir) = &options.snapshot_dir {
+        sink = Box::new(SnapshotStore::new(dir).create(sink, options.columns.clone())?);
+    }
+    if let Some(notice) = &options.teams {
+        // outside the snapshot, which it compares with the previous one
+        sink = Box::new(teams::TeamsSink::new(
+            sink,
+            notice.clone(),
+            options.snapshot_dir.as_deref(),
+        ));
+    }
+    if options.summary {
+        sink = Box::new(summary::SummarySink::new(sink, io::stderr()));
+    }
+    if options.filter.is_active() {
+        sink = Box::new(FilterSink {
+            inner: sink,
+            filter: options.filter,
+        });
+    }
+    if let Some(condition) = &options.condition {
+        sink = Box::new(ConditionSink {
+            inner: sink,
+            condition: condition.clone(),
+        });
+    }
+    if options
+        .columns
+        .iter()
+        .any(|column| column.uses(|column| *column == Column::Headcount))
+    {
+        sink = Box::new(tree::HeadcountSink::new(sink, options.filter));
+    }
+    Ok(if options.deduplicate {
+        Box::new(DedupSink::new(sink))
+    } else {
+        sink
+    })
+}
+
+/// The sink that writes the users to where they go: an endpoint, a
+/// database, a list, an upload, or a file or stdout in `format`.
+fn destination_sink(
+    format: OutputFormat,
+    path: Option<&Path>,
+    options: &OutputOptions,
+) -> anyhow::Result<Box<dyn OutputSink + Send>> {
+    if let Some(target) = &options.post {
+        return Ok(Box::new(post::PostSink::new(
+            target.clone(),
+            options.columns.clone(),
+        )));
+    }
+    if let Some(target) = &options.postgres {
+        return Ok(Box::new(postgres::PostgresSink::connect(
+            target.clone(),
+            options.columns.clone(),
+        )?));
+    }
+    if let Some(target) = &options.sharepoint {
+        return Ok(Box::new(sharepoint::SharePointSink::open(
+            target.clone(),
+            options.columns.clone(),
+        )?));
+    }
+    Ok(match (&options.remote, path) {
+        (Some(remote), _) => {
+            if let OutputFormat::Sqlite = format {
+                anyhow::bail!("sqlite output cannot be uploaded")
+            }
+            Box::new(upload::start(remote.upload(), |writer| {
+                writer_sink(format, Box::new(writer), options)
+            })?)
+        }
+        (None, Some(path)) if options.atomic_output => {
+            let partial = atomic::partial_path(path);
+            Box::new(atomic::AtomicFileSink::new(
+                format_sink(format, Some(&partial), options)?,
+                partial,
+                path.to_path_buf(),
+            ))
+        }
+        (None, path) => format_sink(format, path, options)?,
+    })
+}
+
+fn format_sink(
+    format: OutputFormat,
+    path: Option<&Path>,
+    options: &OutputOptions,
+) -> anyhow::Result<Box<dyn OutputSink + Send>> {
+    let columns = options.columns.clone();
+    if let OutputFormat::Sqlite = format {
+        if options.compression.is_some() {
+            anyhow::bail!("sqlite output cannot be compressed")
+        }
+        let path = path.ok_or_else(|| anyhow::anyhow!("sqlite output requires an output file"))?;
+        return Ok(Box::new(sqlite::SqliteSink::create(path, columns)?));
+    }
+
+    let writer: Box<dyn Write + Send> = match path {
+        Some(path) => {
+            Box::new(BufWriter::new(File::create(path).map_err(|e| {
+                anyhow::anyhow!("creating {}; {}", path.display(), e)
+            })?))
+        }
+        None => Box::new(io::stdout()),
+    };
+    writer_sink(format, writer, options)
+}
+
+/// Creates the sink for `format`, writing to `writer`; not for sqlite.
+fn writer_sink(
+    format: OutputFormat,
+    writer: Box<dyn Write + Send>,
+    options: &OutputOptions,
+) -> anyhow::Result<Box<dyn OutputSink + Send>> {
+    let Some(compression) = options.compression else {
+        return uncompressed_sink(format, writer, options);
+    };
+    let encoder = Arc::new(Mutex::new(Some(compression.encoder(writer)?)));
+    let inner = uncompressed_sink(format, Box::new(CompressedWriter(encoder.clone())), options)?;
+    Ok(Box::new(CompressedSink {
+        inner,
+        encoder,
+        finished: None,
+    }))
+}
+
+fn uncompressed_sink(
+    format: OutputFormat,
+    writer: Box<dyn Write + Send>,
+    options: &OutputOptions,
+) -> anyhow::Result<Box<dyn OutputSink + Send>> {
+    let columns = options.columns.clone();
+    Ok(match format {
+        OutputFormat::Csv => Box::new(
+            csv::CsvSink::with_options(writer, columns, options.csv)
+                .with_null_as(options.null_as.clone()),
+        ),
+        OutputFormat::Json => Box::new(json::JsonSink::new(writer, columns)),
+        OutputFormat::Jsonl => Box::new(json::JsonLinesSink::new(writer, columns)),
+        OutputFormat::JsonTree => Box::new(tree::JsonTreeSink::new(writer, columns)),
+        OutputFormat::Parquet => Box::new(parquet::ParquetSink::new(writer, columns)?),
+        OutputFormat::Xlsx => Box::new(
+            xlsx::XlsxSink::new(writer, columns, options.sheet_per_department)
+                .with_null_as(options.null_as.clone()),
+        ),
+        OutputFormat::Dot => Box::new(dot::DotSink::new(writer)),
+        OutputFormat::Mermaid => Box::new(mermaid::MermaidSink::new(writer)),
+        OutputFormat::Graphml => Box::new(graphml::GraphMlSink::new(writer)),
+        OutputFormat::Html => Box::new(html::HtmlSink::new(writer)),
+        OutputFormat::Svg => Box::new(svg::SvgSink::new(
+            writer,
+            options.svg_orientation,
+            options.svg_colors.clone(),
+        )),
+        OutputFormat::Sqlite => unreachable!("sqlite sinks write to a file"),
+    })
+}
+
+/// Destination for the users of a dump, in traversal order.
+pub trait OutputSink {
+    /// Writes `user`; the root of the tree has no manager.
+    fn write_user(&mut self, user: &User, manager: Option<&User>) -> anyhow::Result<()>;
+
+    /// Makes everything written so far durable, for checkpointing. Formats
+    /// that are only rendered by [`finish`](Self::finish) ignore this.
+    fn flush(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Flushes any buffered output once the dump is complete.
+    fn finish(&mut self) -> anyhow::Result<()>;
+}
+
+/// A column of the flat outputs (CSV, JSON, Parquet, SQLite, Excel).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Column {
+    /// A Graph user property, by its camelCase name.
+    Property(String),
+    /// Vendor or employee by default, derived from the job title by the
+    /// classification rules.
+    EmploymentType,
+    /// On-site or off-shore by default, derived from the office location by
+    /// the classification rules.
+    Location,
+    ManagerId,
+    ManagerDisplayName,
+    /// Levels below the root of the dump.
+    Depth,
+    /// The user's managers from the root down, e.g. `CEO > VP Eng`.
+    ManagementChain,
+    /// Whether the walk stopped at `--max-depth` above the user's reports.
+    Truncated,
+    /// Direct and indirect reports of the user in the dump.
+    Headcount,
+    /// Names of the groups the user is a member of, separated by `; `.
+    Groups,
+    /// Names of the teams the user is a member of, separated by `; `.
+    JoinedTeams,
+    /// The user's devices with their OS and compliance, separated by `; `.
+    Devices,
+    /// SKU part numbers of the user's licenses, separated by `; `.
+    Licenses,
+    /// When the user last signed in, from `signInActivity`.
+    LastSignIn,
+    /// Whole days since the user last signed in.
+    DaysSinceSignIn,
+    /// One of `extensionAttribute1` to `extensionAttribute15` of the user's
+    /// `onPremisesExtensionAttributes`, by name.
+    ExtensionAttribute(String),
+    /// An open extension of the user by its `extensionName`, written as
+    /// JSON.
+    OpenExtension(String),
+    /// Time zone set in the user's mailbox.
+    MailboxTimeZone,
+    /// Locale (language) set in the user's mailbox.
+    MailboxLocale,
+    /// Whether the user has automatic replies on.
+    AutoReply,
+    /// `disabled`, `alwaysEnabled` or `scheduled`.
+    AutoReplyStatus,
+    /// When the user's scheduled automatic replies end.
+    AutoReplyEnd,
+    /// Whether the user's automatic replies are on right now.
+    OutOfOffice,
+    /// When the user was last active in any Microsoft 365 workload.
+    LastActivity,
+    /// When the user was last active in one Microsoft 365 workload.
+    WorkloadLastActivity(Workload),
+    /// Whether the user registered a method for MFA.
+    MfaRegistered,
+    /// Whether the user's registered MFA method is allowed by policy.
+    MfaCapable,
+    /// The authentication methods the user registered, separated by `; `.
+    MfaMethods,
+    /// The user's current presence availability, e.g. `Available`.
+    Availability,
+    /// The user's current presence activity, e.g. `InAMeeting`.
+    Activity,
+    /// A column computed from the others with an expression.
+    Computed(Box<Computed>),
+}
+
+impl Column {
+    /// Parses an entry of `--fields`.
+    pub fn parse(field: &str) -> anyhow::Result<Self> {
+        Ok(match field {
+            "employmentType" => Self::EmploymentType,
+            "location" => Self::Location,
+            "managerId" => Self::ManagerId,
+            "managerDisplayName" => Self::ManagerDisplayName,
+            "depth" => Self::Depth,
+            "managementChain" => Self::ManagementChain,
+            "truncated" => Self::Truncated,
+            "headcount" => Self::Headcount,
+            "groups" => Self::Groups,
+            "joinedTeams" => Self::JoinedTeams,
+            "devices" => Self::Devices,
+            "licenses" => Self::Licenses,
+            "lastSignIn" => Self::LastSignIn,
+            "daysSinceSignIn" => Self::DaysSinceSignIn,
+            "mailboxTimeZone" => Self::MailboxTimeZone,
+            "mailboxLocale" => Self::MailboxLocale,
+            "autoReply" => Self::AutoReply,
+            "autoReplyStatus" => Self::AutoReplyStatus,
+            "autoReplyEnd" => Self::AutoReplyEnd,
+            "outOfOffice" => Self::OutOfOffice,
+            "lastActivity" => Self::LastActivity,
+            "exchangeLastActivity" => Self::WorkloadLastActivity(Workload::Exchange),
+            "oneDriveLastActivity" => Self::WorkloadLastActivity(Workload::OneDrive),
+            "sharePointLastActivity" => Self::WorkloadLastActivity(Workload::SharePoint),
+            "teamsLastActivity" => Self::WorkloadLastActivity(Workload::Teams),
+            "yammerLastActivity" => Self::WorkloadLastActivity(Workload::Yammer),
+            "mfaRegistered" => Self::MfaRegistered,
+            "mfaCapable" => Self::MfaCapable,
+            "mfaMethods" => Self::MfaMethods,
+            "availability" => Self::Availability,
+            "activity" => Self::Activity,
+            _ if Self::is_extension_attribute(field) => Self::ExtensionAttribute(field.to_string()),
+            _ => {
+                // names end up in headers and SQL, so only allow identifiers
+                let valid = field.starts_with(|c: char| c.is_ascii_alphabetic())
+                    && field.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+                if !valid {
+                    anyhow::bail!("invalid field name {:?}", field)
+                }
+                Self::Property(field.to_string())
+            }
+        })
+    }
+
+    /// `extensionAttribute1` to `extensionAttribute15`.
+    fn is_extension_attribute(field: &str) -> bool {
+        (1..=15).any(|n| field == format!("extensionAttribute{}", n))
+    }
+
+    /// Parses an entry of `--extensions`: a schema or directory extension
+    /// property, or an open extension if the name contains a dot, e.g.
+    /// `com.contoso.roamingSettings`.
+    pub fn extension(name: &str) -> anyhow::Result<Self> {
+        if !name.contains('.') {
+            return Self::parse(name);
+        }
+        let valid = name.starts_with(|c: char| c.is_ascii_alphabetic())
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.');
+        if !valid {
+            anyhow::bail!("invalid extension name {:?}", name)
+        }
+        Ok(Self::OpenExtension(name.to_string()))
+    }
+
+    /// The columns written without `--fields`.
+    pub fn defaults() -> Vec<Self> {
+        let property = |name: &str| Self::Property(name.to_string());
+        vec![
+            property("id"),
+            property("displayName"),
+            property("mail"),
+            property("jobTitle"),
+            property("department"),
+            property("officeLocation"),
+            property("employeeId"),
+            property("employeeType"),
+            property("employeeHireDate"),
+            property("companyName"),
+            Self::EmploymentType,
+            Self::Location,
+            Self::ManagerId,
+            Self::ManagerDisplayName,
+            Self::Depth,
+        ]
+    }
+
+    /// The columns for `fields`, or the defaults. The `id` column and the
+    /// manager linkage referring to it are always included, first and last
+    /// unless listed explicitly.
+    pub fn list(fields: Option<&[String]>) -> anyhow::Result<Vec<Self>> {
+        let Some(fields) = fields else {
+            return Ok(Self::defaults());
+        };
+        let mut columns = fields
+            .iter()
+            .map(|field| Self::parse(field.trim()))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let id = Self::Property("id".to_string());
+        if !columns.contains(&id) {
+            columns.insert(0, id);
+        }
+        for column in [Self::ManagerId, Self::ManagerDisplayName] {
+            if !columns.contains(&column) {
+                columns.push(column);
+            }
+        }
+        Ok(columns)
+    }
+
+    /// The Graph properties to `$select` for `columns`, including the ones
+    /// derived columns and the tree walk depend on.
+    pub fn select(columns: &[Self]) -> Vec<String> {
+        let mut select = vec!["id".to_string(), "displayName".to_string()];
+        for column in columns {
+            let property = match column {
+                Self::Property(name) => name.as_str(),
+                Self::EmploymentType => Classification::current().employment_type.property.as_str(),
+                Self::Location => Classification::current().location.property.as_str(),
+                Self::Licenses => "assignedLicenses",
+                Self::LastSignIn | Self::DaysSinceSignIn => "signInActivity",
+                // the usage report is joined on it
+                Self::LastActivity | Self::WorkloadLastActivity(_) => "userPrincipalName",
+                Self::ExtensionAttribute(_) => "onPremisesExtensionAttributes",
+                Self::ManagerId
+                | Self::ManagerDisplayName
+                | Self::Depth
+                | Self::ManagementChain
+                | Self::Truncated
+                | Self::Headcount
+                | Self::Groups
+                | Self::JoinedTeams
+                | Self::Devices
+                | Self::OpenExtension(_)
+                | Self::MailboxTimeZone
+                | Self::MailboxLocale
+                | Self::AutoReply
+                | Self::AutoReplyStatus
+                | Self::AutoReplyEnd
+                | Self::OutOfOffice
+                | Self::MfaRegistered
+                | Self::MfaCapable
+                | Self::MfaMethods
+                | Self::Availability
+                | Self::Activity => continue,
+                Self::Computed(computed) => {
+                    for property in Self::select(&computed.expression.columns()) {
+                        if !select.contains(&property) {
+                            select.push(property);
+                        }
+                    }
+                    continue;
+                }
+            };
+            if !select.iter().any(|p| p == property) {
+                select.push(property.to_string());
+            }
+        }
+        select
+    }
+
+    /// Name of the column in JSON outputs.
+    pub fn json_name(&self) -> &str {
+        match self {
+            Self::Property(name) => name,
+            Self::EmploymentType => "employmentType",
+            Self::Location => "location",
+            Self::ManagerId => "managerId",
+            Self::ManagerDisplayName => "managerDisplayName",
+            Self::Depth => "depth",
+            Self::ManagementChain => "managementChain",
+            Self::Truncated => "truncated",
+            Self::Headcount => "headcount",
+            Self::Groups => "groups",
+            Self::JoinedTeams => "joinedTeams",
+            Self::Devices => "devices",
+            Self::Licenses => "licenses",
+            Self::LastSignIn => "lastSignIn",
+            Self::DaysSinceSignIn => "daysSinceSignIn",
+            Self::ExtensionAttribute(name) | Self::OpenExtension(name) => name,
+            Self::MailboxTimeZone => "mailboxTimeZone",
+            Self::MailboxLocale => "mailboxLocale",
+            Self::AutoReply => "autoReply",
+            Self::AutoReplyStatus => "autoReplyStatus",
+            Self::AutoReplyEnd => "autoReplyEnd",
+            Self::OutOfOffice => "outOfOffice",
+            Self::LastActivity => "lastActivity",
+            Self::WorkloadLastActivity(workload) => match workload {
+                Workload::Exchange => "exchangeLastActivity",
+                Workload::OneDrive => "oneDriveLastActivity",
+                Workload::SharePoint => "sharePointLastActivity",
+                Workload::Teams => "teamsLastActivity",
+                Workload::Yammer => "yammerLastActivity",
+            },
+            Self::MfaRegistered => "mfaRegistered",
+            Self::MfaCapable => "mfaCapable",
+            Self::MfaMethods => "mfaMethods",
+            Self::Availability => "availability",
+            Self::Activity => "activity",
+            Self::Computed(computed) => &computed.name,
+        }
+    }
+
+    /// Name of the column in CSV headers and tables, in snake_case.
+    pub fn name(&self) -> String {
+        snake_case(self.json_name())
+    }
+
+    /// Whether the column can be missing for a user.
+    pub fn nullable(&self) -> bool {
+        match self {
+            Self::Property(name) => name != "id" && name != "displayName",
+            Self::EmploymentType
+            | Self::Location
+            | Self::ManagementChain
+            | Self::Truncated
+            | Self::Groups
+            | Self::JoinedTeams
+            | Self::Devices
+            | Self::Licenses => false,
+            Self::ManagerId
+            | Self::ManagerDisplayName
+            | Self::Depth
+            | Self::Headcount
+            | Self::LastSignIn
+            | Self::DaysSinceSignIn
+            | Self::ExtensionAttribute(_)
+            | Self::OpenExtension(_)
+            | Self::MailboxTimeZone
+            | Self::MailboxLocale
+            | Self::AutoReply
+            | Self::AutoReplyStatus
+            | Self::AutoReplyEnd
+            | Self::OutOfOffice
+            | Self::LastActivity
+            | Self::WorkloadLastActivity(_)
+            | Self::MfaRegistered
+            | Self::MfaCapable
+            | Self::MfaMethods
+            | Self::Availability
+            | Self::Activity
+            | Self::Computed(_) => true,
+        }
+    }
+
+    /// Placeholder for missing values in text outputs, one of
+    /// [`PLACEHOLDERS`].
+    pub fn placeholder(&self) -> &'static str {
+        match self {
+            Self::ManagerId | Self::ManagerDisplayName => "none",
+            Self::LastSignIn | Self::DaysSinceSignIn => "never",
+            Self::LastActivity | Self::WorkloadLastActivity(_) => "inactive",
+            _ => "unknown",
+        }
+    }
+
+    /// The value of the column for `user`, `Null` if missing.
+    pub fn value(&self, user: &User, manager: Option<&User>) -> Value {
+        match self {
+            Self::Property(name) => user.property(name),
+            Self::EmploymentType => Value::from(user.get_category().0),
+            Self::Location => Value::from(user.get_category().1),
+            Self::ManagerId => manager.map_or(Value::Null, |m| Value::from(m.id.as_str())),
+            Self::ManagerDisplayName => {
+                manager.map_or(Value::Null, |m| Value::from(m.display_name.as_str()))
+            }
+            Self::Depth => user.depth.map_or(Value::Null, Value::from),
+            Self::ManagementChain => Value::from(user.management_chain.join(" > ")),
+            Self::Truncated => Value::Bool(user.truncated),
+            Self::Headcount => user.headcount.map_or(Value::Null, Value::from),
+            Self::Groups => Value::from(user.groups.join("; ")),
+            Self::JoinedTeams => Value::from(user.joined_teams.join("; ")),
+            Self::Devices => Value::from(
+                user.devices
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join("; "),
+            ),
+            Self::Licenses => Value::from(user.licenses.join("; ")),
+            Self::LastSignIn => user
+                .last_sign_in()
+                .map_or(Value::Null, |at| Value::from(at.to_rfc3339())),
+            Self::DaysSinceSignIn => user.last_sign_in().map_or(Value::Null, |at| {
+                Value::from((chrono::Utc::now() - at).num_days())
+            }),
+            Self::ExtensionAttribute(name) => {
+                user.property("onPremisesExtensionAttributes")[name.as_str()].clone()
+            }
+            Self::OpenExtension(name) => user.open_extension(name),
+            Self::MailboxTimeZone => {
+                let time_zone = user
+                    .mailbox_settings
+                    .as_ref()
+                    .and_then(|m| m.time_zone.clone());
+                time_zone.map_or(Value::Null, Value::from)
+            }
+            Self::MailboxLocale => {
+                let locale = user
+                    .mailbox_settings
+                    .as_ref()
+                    .and_then(|m| m.locale.clone());
+                locale.map_or(Value::Null, Value::from)
+            }
+            Self::AutoReply => user
+                .mailbox_settings
+                .as_ref()
+                .map_or(Value::Null, |m| Value::Bool(m.auto_reply)),
+            Self::AutoReplyStatus => {
+                let status = user
+                    .mailbox_settings
+                    .as_ref()
+                    .and_then(|m| m.auto_reply_status.clone());
+                status.map_or(Value::Null, Value::from)
+            }
+            Self::AutoReplyEnd => {
+                let end = user
+                    .mailbox_settings
+                    .as_ref()
+                    .and_then(|m| m.auto_reply_end);
+                end.map_or(Value::Null, |end| Value::from(end.to_rfc3339()))
+            }
+            Self::OutOfOffice => user.mailbox_settings.as_ref().map_or(Value::Null, |m| {
+                Value::Bool(m.out_of_office(chrono::Utc::now()))
+            }),
+            Self::LastActivity => {
+                let at = user.usage.as_ref().and_then(|u| u.last_activity());
+                at.map_or(Value::Null, |at| Value::from(at.to_string()))
+            }
+            Self::WorkloadLastActivity(workload) => {
+                let at = user
+                    .usage
+                    .as_ref()
+                    .and_then(|u| u.last_activity_in(*workload));
+                at.map_or(Value::Null, |at| Value::from(at.to_string()))
+            }
+            Self::MfaRegistered => user
+                .mfa
+                .as_ref()
+                .map_or(Value::Null, |m| Value::Bool(m.is_mfa_registered)),
+            Self::MfaCapable => user
+                .mfa
+                .as_ref()
+                .map_or(Value::Null, |m| Value::Bool(m.is_mfa_capable)),
+            Self::MfaMethods => user.mfa.as_ref().map_or(Value::Null, |m| {
+                Value::from(m.methods_registered.join("; "))
+            }),
+            Self::Availability => {
+                let availability = user.presence.as_ref().and_then(|p| p.availability.clone());
+                availability.map_or(Value::Null, Value::from)
+            }
+            Self::Activity => {
+                let activity = user.presence.as_ref().and_then(|p| p.activity.clone());
+                activity.map_or(Value::Null, Value::from)
+            }
+            Self::Computed(computed) => computed.expression.eval(user, manager),
+        }
+    }
+
+    /// Whether the column, or a column it is computed from, is `wanted`.
+    pub fn uses(&self, wanted: impl Fn(&Column) -> bool) -> bool {
+        match self {
+            Self::Computed(computed) => computed.expression.columns().iter().any(wanted),
+            column => wanted(column),
+        }
+    }
+
+    /// The value of the column as text, `None` if missing. Non-string
+    /// properties are written as JSON.
+    pub fn text(&self, user: &User, manager: Option<&User>) -> Option<String> {
+        match self.value(user, manager) {
+            Value::Null => None,
+            Value::String(s) => Some(s),
+            value => Some(value.to_string()),
+        }
+    }
+
+    /// The [`text`](Self::text) of the column, or `null_as` if missing,
+    /// falling back to the column's [`placeholder`](Self::placeholder).
+    pub fn text_or(&self, user: &User, manager: Option<&User>, null_as: Option<&str>) -> String {
+        self.text(user, manager)
+            .unwrap_or_else(|| null_as.unwrap_or(self.placeholder()).to_string())
+    }
+}
+
+/// Converts a camelCase name to snake_case, e.g. `managerId` to
+/// `manager_id`.
+pub(crate) fn snake_case(camel: &str) -> String {
+    let mut name = String::new();
+    for c in camel.chars() {
+        if c.is_ascii_uppercase() {
+            if !name.is_empty() && !name.ends_with('_') {
+                name.push('_');
+            }
+            name.push(c.to_ascii_lowercase());
+        } else {
+            name.push(c);
+        }
+    }
+    name
+}
+
+/// Escapes text for use in XML (and HTML) content and attribute values.
+pub(crate) fn escape_xml(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use serde_json::Map;
+
+    use super::*;
+
+    /// A writer whose output stays readable after the sink is dropped.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// A writer that fails, like a full disk.
+    struct Full;
+
+    impl Write for Full {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::Error::other("no space left on device"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn gzip_options() -> OutputOptions {
+        OutputOptions {
+            compression: Some(Compression::Gzip),
+            columns: vec![Column::Property("displayName".to_string())],
+            ..OutputOptions::default()
+        }
+    }
+
+    #[test]
+    fn compressed_output_is_complete_after_finish() {
+        let output = Shared::default();
+        let mut sink =
+            writer_sink(OutputFormat::Csv, Box::new(output.clone()), &gzip_options()).unwrap();
+        sink.write_user(&User::record("u1", "Ann", Map::new()), None)
+            .unwrap();
+        sink.finish().unwrap();
+        let compressed = output.0.lock().unwrap().clone();
+        let mut csv = String::new();
+        flate2::read::GzDecoder::new(&compressed[..])
+            .read_to_string(&mut csv)
+            .unwrap();
+        assert_eq!(csv, "display_name\nAnn\n");
+    }
+
+    #[test]
+    fn failing_to_complete_compressed_output_fails_the_dump() {
+        let mut sink = writer_sink(OutputFormat::Csv, Box::new(Full), &gzip_options()).unwrap();
+        sink.write_user(&User::record("u1", "Ann", Map::new()), None)
+            .unwrap();
+        assert!(sink.finish().is_err());
+    }
+
+    /// Records the ids written to it.
+    #[derive(Clone, Default)]
+    struct Ids(Arc<Mutex<Vec<String>>>);
+
+    impl OutputSink for Ids {
+        fn write_user(&mut self, user: &User, _manager: Option<&User>) -> anyhow::Result<()> {
+            self.0.lock().unwrap().push(user.id.clone());
+            Ok(())
+        }
+
+        fn finish(&mut self) -> anyhow::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn duplicates_are_written_once() {
+        let ids = Ids::default();
+        let mut sink = DedupSink::new(Box::new(ids.clone()));
+        for id in ["a", "b", "a", "c", "b"] {
+            sink.write_user(&User::record(id, id, Map::new()), None)
+                .unwrap();
+        }
+        sink.finish().unwrap();
+        assert_eq!(*ids.0.lock().unwrap(), ["a", "b", "c"]);
+        assert_eq!(sink.duplicates, ["a", "b"]);
+    }
+}
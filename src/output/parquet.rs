@@ -0,0 +1,91 @@
+use std::io::Write;
+use std::sync::Arc;
+
+use arrow_array::builder::StringBuilder;
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+
+use super::{Column, OutputSink};
+use crate::model::User;
+
+/// Rows buffered before a record batch is handed to the writer.
+const BATCH_SIZE: usize = 8192;
+
+/// Writes users to a Parquet file with Snappy-compressed string columns.
+///
+/// Has the same columns as the CSV output; missing attributes are written
+/// as nulls.
+pub struct ParquetSink<W: Write + Send> {
+    writer: Option<ArrowWriter<W>>,
+    schema: SchemaRef,
+    columns: Vec<Column>,
+    builders: Vec<StringBuilder>,
+    rows: usize,
+}
+
+impl<W: Write + Send> ParquetSink<W> {
+    pub fn new(writer: W, columns: Vec<Column>) -> anyhow::Result<Self> {
+        let schema: SchemaRef = Arc::new(Schema::new(
+            columns
+                .iter()
+                .map(|column| Field::new(column.name(), DataType::Utf8, column.nullable()))
+                .collect::<Vec<_>>(),
+        ));
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+        let writer = ArrowWriter::try_new(writer, schema.clone(), Some(properties))?;
+
+        Ok(Self {
+            writer: Some(writer),
+            schema,
+            builders: columns.iter().map(|_| StringBuilder::new()).collect(),
+            columns,
+            rows: 0,
+        })
+    }
+
+    fn flush_batch(&mut self) -> anyhow::Result<()> {
+        if self.rows == 0 {
+            return Ok(());
+        }
+
+        let arrays: Vec<ArrayRef> = self
+            .builders
+            .iter_mut()
+            .map(|builder| Arc::new(builder.finish()) as ArrayRef)
+            .collect();
+        let batch = RecordBatch::try_new(self.schema.clone(), arrays)?;
+        if let Some(writer) = self.writer.as_mut() {
+            writer.write(&batch)?;
+        }
+        self.rows = 0;
+        Ok(())
+    }
+}
+
+impl<W: Write + Send> OutputSink for ParquetSink<W> {
+    fn write_user(&mut self, user: &User, manager: Option<&User>) -> anyhow::Result<()> {
+        for (column, builder) in self.columns.iter().zip(&mut self.builders) {
+            builder.append_option(column.text(user, manager));
+        }
+
+        self.rows += 1;
+        if self.rows >= BATCH_SIZE {
+            self.flush_batch()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.flush_batch()?;
+        if let Some(writer) = self.writer.take() {
+            let mut writer = writer.into_inner()?;
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
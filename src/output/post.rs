@@ -0,0 +1,188 @@
+use std::time::Duration;
+
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
+use reqwest::{Client, Url};
+use serde_json::Value;
+
+use super::json::JsonRecord;
+use super::upload::block_on;
+use super::{Column, OutputSink};
+use crate::graph::retry;
+use crate::model::User;
+
+/// Records per request unless configured otherwise.
+pub const DEFAULT_BATCH_SIZE: usize = 100;
+
+/// An endpoint the users are POSTed to, e.g. an internal ingestion API.
+#[derive(Clone)]
+pub struct PostTarget {
+    client: Client,
+    url: Url,
+    headers: HeaderMap,
+    batch_size: usize,
+    max_retries: u32,
+    max_retry_after: Duration,
+}
+
+impl std::fmt::Debug for PostTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // leaves out the headers, which usually carry credentials
+        f.debug_struct("PostTarget")
+            .field("url", &self.url.as_str())
+            .field("batch_size", &self.batch_size)
+            .finish()
+    }
+}
+
+impl PostTarget {
+    /// POSTs to `url`, which must be HTTPS unless it is on this machine.
+    pub fn new(client: Client, url: Url) -> anyhow::Result<Self> {
+        let local = matches!(url.host_str(), Some("localhost" | "127.0.0.1" | "[::1]"));
+        if url.scheme() != "https" && !(url.scheme() == "http" && local) {
+            anyhow::bail!("{} is not an HTTPS URL", url)
+        }
+        Ok(Self {
+            client,
+            url,
+            headers: HeaderMap::new(),
+            batch_size: DEFAULT_BATCH_SIZE,
+            max_retries: retry::DEFAULT_MAX_RETRIES,
+            max_retry_after: retry::DEFAULT_MAX_RETRY_AFTER,
+        })
+    }
+
+    /// Sends `headers`, e.g. `Authorization`, with every request.
+    pub fn with_headers(
+        mut self,
+        headers: impl IntoIterator<Item = (HeaderName, HeaderValue)>,
+    ) -> Self {
+        self.headers.extend(headers);
+        self
+    }
+
+    /// Sends up to `batch_size` records per request.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Retries throttled and failed requests up to `max_retries` times.
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Waits at most `max_retry_after` when the endpoint asks to retry
+    /// later.
+    pub fn with_max_retry_after(mut self, max_retry_after: Duration) -> Self {
+        self.max_retry_after = max_retry_after;
+        self
+    }
+
+    /// POSTs `records` as a JSON array, retrying throttling, server errors
+    /// and network failures with backoff.
+    async fn post(&self, records: &[Value]) -> anyhow::Result<()> {
+        let body = serde_json::to_vec(records)?;
+        let mut attempt = 0;
+        loop {
+            let sent = self
+                .client
+                .post(self.url.clone())
+                .headers(self.headers.clone())
+                .header(CONTENT_TYPE, "application/json")
+                .body(body.clone())
+                .send()
+                .await;
+            let response = match sent {
+                Err(e) if retry::is_transient_error(&e) && attempt < self.max_retries => {
+                    tokio::time::sleep(retry::backoff(attempt)).await;
+                    attempt += 1;
+                    continue;
+                }
+                sent => sent?,
+            };
+            let status = response.status();
+            if status.is_success() {
+                return Ok(());
+            }
+            if retry::is_retryable_status(status) && attempt < self.max_retries {
+                let delay = retry::delay(
+                    retry::retry_after(response.headers()),
+                    attempt,
+                    self.max_retry_after,
+                );
+                tracing::debug!(%status, ?delay, "Retrying the POST");
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+                continue;
+            }
+            let response_txt = response.text().await?;
+            anyhow::bail!("{}: {}", status, response_txt)
+        }
+    }
+}
+
+/// POSTs the users in batches of JSON records with the columns of the
+/// `json` output.
+pub struct PostSink {
+    target: PostTarget,
+    columns: Vec<Column>,
+    batch: Vec<Value>,
+    /// Records sent so far.
+    posted: usize,
+}
+
+impl PostSink {
+    pub fn new(target: PostTarget, columns: Vec<Column>) -> Self {
+        Self {
+            batch: Vec::with_capacity(target.batch_size),
+            target,
+            columns,
+            posted: 0,
+        }
+    }
+
+    fn post_batch(&mut self) -> anyhow::Result<()> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+        block_on(self.target.post(&self.batch)).map_err(|e| {
+            anyhow::anyhow!(
+                "posting users {} to {} to {}; {}",
+                self.posted + 1,
+                self.posted + self.batch.len(),
+                self.target.url,
+                e
+            )
+        })?;
+        self.posted += self.batch.len();
+        self.batch.clear();
+        Ok(())
+    }
+}
+
+impl OutputSink for PostSink {
+    fn write_user(&mut self, user: &User, manager: Option<&User>) -> anyhow::Result<()> {
+        self.batch.push(serde_json::to_value(JsonRecord::new(
+            user,
+            manager,
+            &self.columns,
+        ))?);
+        if self.batch.len() >= self.target.batch_size {
+            self.post_batch()?;
+        }
+        Ok(())
+    }
+
+    /// Sends the partial batch, so that a checkpoint never skips users the
+    /// endpoint has not received.
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.post_batch()
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.post_batch()?;
+        tracing::info!("Posted {} users to {}", self.posted, self.target.url);
+        Ok(())
+    }
+}
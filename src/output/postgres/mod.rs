@@ -0,0 +1,339 @@
+mod connection;
+
+use std::collections::HashSet;
+
+use chrono::Utc;
+
+use connection::Connection;
+pub use connection::{Config, SslMode};
+
+use super::{Column, OutputSink};
+use crate::model::{Change, User};
+
+/// Rows per `INSERT` statement.
+const BATCH_SIZE: usize = 500;
+
+/// Columns indexed when present, besides `manager_id`.
+const INDEXED: [&str; 4] = ["department", "display_name", "mail", "employee_id"];
+
+/// A PostgreSQL table the users are upserted into, from
+/// `--output postgres://...`.
+#[derive(Clone)]
+pub struct PostgresTarget {
+    config: Config,
+    table: String,
+}
+
+impl std::fmt::Debug for PostgresTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // leaves out the password
+        f.debug_struct("PostgresTarget")
+            .field("database", &self.describe())
+            .finish()
+    }
+}
+
+impl PostgresTarget {
+    /// Whether `path` is a `postgres://` or `postgresql://` URL rather than
+    /// a file.
+    pub fn is_url(path: &std::path::Path) -> bool {
+        path.to_str().is_some_and(|path| {
+            path.starts_with("postgres://") || path.starts_with("postgresql://")
+        })
+    }
+
+    pub fn parse(url: &str) -> anyhow::Result<Self> {
+        Ok(Self {
+            config: Config::parse(url)?,
+            table: "users".to_string(),
+        })
+    }
+
+    /// Upserts into `table`, which may be qualified with a schema, instead
+    /// of `users`.
+    pub fn with_table(mut self, table: &str) -> Self {
+        self.table = table.to_string();
+        self
+    }
+
+    /// The table and database, for messages; without the password.
+    pub fn describe(&self) -> String {
+        format!(
+            "{} in {}:{}/{}",
+            self.table, self.config.host, self.config.port, self.config.database
+        )
+    }
+
+    /// The table name quoted, keeping the schema separate.
+    fn quoted_table(&self) -> String {
+        self.table
+            .split('.')
+            .map(identifier)
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+
+    /// The unqualified name, for the names of the indexes.
+    fn bare_table(&self) -> &str {
+        self.table.rsplit('.').next().unwrap_or(&self.table)
+    }
+}
+
+/// Upserts users into a PostgreSQL table, one row per user keyed by `id`
+/// with the `manager_id` of their manager, so that repeated runs keep the
+/// table in sync with the directory. Users removed in delta mode are
+/// deleted; every row written also gets the run's `synced_at`, so rows a
+/// full run did not touch can be pruned.
+///
+/// The table and any missing columns are created. Rows are written in a
+/// transaction committed by [`OutputSink::flush`] and [`OutputSink::finish`].
+///
+/// The table has the CSV columns except `manager_display_name`, which is a
+/// join away.
+pub struct PostgresSink {
+    connection: Connection,
+    target: PostgresTarget,
+    columns: Vec<Column>,
+    /// The `INSERT` column list and `ON CONFLICT` clause.
+    insert: (String, String),
+    synced_at: String,
+    /// Rows to upsert and ids to delete (`None`) since the last statement,
+    /// in the order of the dump.
+    pending: Vec<(String, Option<Vec<Option<String>>>)>,
+    upserted: usize,
+    deleted: usize,
+}
+
+impl PostgresSink {
+    pub fn connect(target: PostgresTarget, columns: Vec<Column>) -> anyhow::Result<Self> {
+        let columns: Vec<Column> = columns
+            .into_iter()
+            .filter(|column| *column != Column::ManagerDisplayName)
+            .collect();
+        if !columns.contains(&Column::Property("id".to_string())) {
+            anyhow::bail!("postgres output requires the id column")
+        }
+        let names: Vec<String> = columns.iter().map(Column::name).collect();
+
+        let mut connection = Connection::connect(&target.config)
+            .map_err(|e| anyhow::anyhow!("connecting to {}; {}", target.describe(), e))?;
+        create_table(&mut connection, &target, &names)
+            .map_err(|e| anyhow::anyhow!("creating {}; {}", target.describe(), e))?;
+        connection.execute("BEGIN")?;
+
+        let mut quoted: Vec<String> = names.iter().map(|name| identifier(name)).collect();
+        quoted.push(identifier("synced_at"));
+        let updates: Vec<String> = quoted
+            .iter()
+            .filter(|name| *name != "\"id\"")
+            .map(|name| format!("{0} = EXCLUDED.{0}", name))
+            .collect();
+        let insert = (
+            format!(
+                "INSERT INTO {} ({})",
+                target.quoted_table(),
+                quoted.join(", ")
+            ),
+            format!("ON CONFLICT (\"id\") DO UPDATE SET {}", updates.join(", ")),
+        );
+        Ok(Self {
+            connection,
+            columns,
+            insert,
+            synced_at: literal(&Utc::now().to_rfc3339()),
+            target,
+            pending: Vec::new(),
+            upserted: 0,
+            deleted: 0,
+        })
+    }
+
+    /// Sends the pending rows. Only the last change of each user counts, as
+    /// one `INSERT` cannot update a row twice.
+    fn write_pending(&mut self) -> anyhow::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let mut seen = HashSet::new();
+        let mut rows = Vec::new();
+        let mut deletes = Vec::new();
+        for (id, row) in self.pending.drain(..).rev() {
+            if !seen.insert(id.clone()) {
+                continue;
+            }
+            match row {
+                Some(values) => {
+                    let values: Vec<String> = values
+                        .iter()
+                        .map(|value| value.as_deref().map_or("NULL".to_string(), literal))
+                        .chain([format!("{}::timestamptz", self.synced_at)])
+                        .collect();
+                    rows.push(format!("({})", values.join(", ")));
+                }
+                None => deletes.push(literal(&id)),
+            }
+        }
+
+        let mut sql = String::new();
+        if !deletes.is_empty() {
+            sql.push_str(&format!(
+                "DELETE FROM {} WHERE \"id\" IN ({});\n",
+                self.target.quoted_table(),
+                deletes.join(", ")
+            ));
+        }
+        if !rows.is_empty() {
+            rows.reverse();
+            sql.push_str(&format!(
+                "{} VALUES\n{}\n{};\n",
+                self.insert.0,
+                rows.join(",\n"),
+                self.insert.1
+            ));
+        }
+        self.connection
+            .execute(&sql)
+            .map_err(|e| anyhow::anyhow!("writing to {}; {}", self.target.describe(), e))?;
+        self.upserted += rows.len();
+        self.deleted += deletes.len();
+        Ok(())
+    }
+}
+
+impl OutputSink for PostgresSink {
+    fn write_user(&mut self, user: &User, manager: Option<&User>) -> anyhow::Result<()> {
+        let row = match user.change {
+            Some(Change::Delete) => None,
+            _ => Some(
+                self.columns
+                    .iter()
+                    .map(|column| column.text(user, manager))
+                    .collect(),
+            ),
+        };
+        self.pending.push((user.id.clone(), row));
+        if self.pending.len() >= BATCH_SIZE {
+            self.write_pending()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.write_pending()?;
+        self.connection.execute("COMMIT; BEGIN")
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.write_pending()?;
+        self.connection.execute("COMMIT")?;
+        tracing::info!(
+            "Upserted {} users into {} and deleted {}",
+            self.upserted,
+            self.target.describe(),
+            self.deleted
+        );
+        Ok(())
+    }
+}
+
+/// Creates the table, or adds the columns an earlier run did not write, and
+/// indexes `manager_id` and the common lookup columns. An up-to-date table
+/// is left alone, so the user only needs to be allowed to write to it.
+///
+/// There is no foreign key on `manager_id`: managers can be written after
+/// their reports, and subtree dumps leave out the root's manager.
+fn create_table(
+    connection: &mut Connection,
+    target: &PostgresTarget,
+    names: &[String],
+) -> anyhow::Result<()> {
+    let table = target.quoted_table();
+    let existing: HashSet<String> = connection
+        .query(&format!(
+            "SELECT attname FROM pg_attribute \
+             WHERE attrelid = to_regclass({}) AND attnum > 0 AND NOT attisdropped",
+            literal(&table)
+        ))?
+        .into_iter()
+        .filter_map(|row| row.into_iter().next().flatten())
+        .collect();
+
+    let mut schema = String::new();
+    if existing.is_empty() {
+        schema.push_str(&format!(
+            "CREATE TABLE IF NOT EXISTS {} (\"id\" TEXT PRIMARY KEY);\n",
+            table
+        ));
+    }
+    let missing = names
+        .iter()
+        .map(String::as_str)
+        .chain(["synced_at"])
+        .filter(|name| *name != "id" && !existing.contains(*name));
+    for name in missing {
+        let kind = if name == "synced_at" {
+            "TIMESTAMPTZ"
+        } else {
+            "TEXT"
+        };
+        schema.push_str(&format!(
+            "ALTER TABLE {} ADD COLUMN IF NOT EXISTS {} {};\n",
+            table,
+            identifier(name),
+            kind
+        ));
+        if name == "manager_id" || INDEXED.contains(&name) {
+            schema.push_str(&format!(
+                "CREATE INDEX IF NOT EXISTS {} ON {} ({});\n",
+                identifier(&format!("{}_{}", target.bare_table(), name)),
+                table,
+                identifier(name)
+            ));
+        }
+    }
+    if !schema.is_empty() {
+        connection.execute(&schema)?;
+    }
+    Ok(())
+}
+
+fn identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// A string constant; PostgreSQL text cannot hold NUL characters, which are
+/// dropped.
+fn literal(value: &str) -> String {
+    let escaped = value
+        .replace('\0', "")
+        .replace('\\', "\\\\")
+        .replace('\'', "''");
+    format!("E'{}'", escaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identifiers_are_quoted() {
+        assert_eq!(identifier("users"), "\"users\"");
+        assert_eq!(identifier("Display Name"), "\"Display Name\"");
+        assert_eq!(
+            identifier("a\"; DROP TABLE x; --"),
+            "\"a\"\"; DROP TABLE x; --\""
+        );
+    }
+
+    #[test]
+    fn literals_are_escaped() {
+        assert_eq!(literal("Ann"), "E'Ann'");
+        assert_eq!(literal("O'Brien"), "E'O''Brien'");
+        assert_eq!(literal("C:\\Users"), "E'C:\\\\Users'");
+        assert_eq!(
+            literal("\\'; DROP TABLE x; --"),
+            "E'\\\\''; DROP TABLE x; --'"
+        );
+        assert_eq!(literal("a\0b"), "E'ab'");
+    }
+}
@@ -0,0 +1,318 @@
+use std::env;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Duration, Utc};
+use reqwest::Client;
+use serde::Deserialize;
+use tokio::sync::Mutex;
+
+/// Credentials are refreshed this long before they expire.
+const EXPIRY_SKEW: Duration = Duration::minutes(5);
+
+/// ECS task role credentials are served here, below the relative URI.
+const ECS_ENDPOINT: &str = "http://169.254.170.2";
+
+/// EC2 instance metadata service (IMDSv2).
+const IMDS_ENDPOINT: &str = "http://169.254.169.254";
+
+/// AWS STS, for web identity (EKS service account) credentials.
+const STS_ENDPOINT: &str = "https://sts.amazonaws.com/";
+
+/// AWS access keys, temporary ones with a session token and expiry.
+#[derive(Clone)]
+pub struct Credentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl Credentials {
+    fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| Utc::now() + EXPIRY_SKEW >= expires_at)
+    }
+}
+
+/// Credentials as the container and instance metadata endpoints return
+/// them.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct MetadataCredentials {
+    access_key_id: String,
+    secret_access_key: String,
+    token: Option<String>,
+    expiration: Option<DateTime<Utc>>,
+}
+
+impl From<MetadataCredentials> for Credentials {
+    fn from(credentials: MetadataCredentials) -> Self {
+        Self {
+            access_key_id: credentials.access_key_id,
+            secret_access_key: credentials.secret_access_key,
+            session_token: credentials.token,
+            expires_at: credentials.expiration,
+        }
+    }
+}
+
+/// Resolves credentials the way the AWS SDKs and CLI do, in order: the
+/// `AWS_ACCESS_KEY_ID` / `AWS_SECRET_ACCESS_KEY` environment variables, the
+/// `AWS_PROFILE` (or `default`) profile of the shared credentials file, a
+/// web identity token (`AWS_WEB_IDENTITY_TOKEN_FILE` and `AWS_ROLE_ARN`, as
+/// on EKS), the ECS container credentials and the EC2 instance profile.
+/// Temporary credentials are refreshed before they expire.
+pub struct CredentialChain {
+    client: Client,
+    credentials: Mutex<Option<Credentials>>,
+}
+
+impl CredentialChain {
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            credentials: Mutex::default(),
+        }
+    }
+
+    /// Valid credentials, resolving them again once expired.
+    pub async fn credentials(&self) -> anyhow::Result<Credentials> {
+        let mut credentials = self.credentials.lock().await;
+        match credentials.as_ref() {
+            Some(current) if !current.is_expired() => Ok(current.clone()),
+            _ => {
+                let resolved = self
+                    .resolve()
+                    .await
+                    .map_err(|e| anyhow::anyhow!("resolving AWS credentials; {}", e))?;
+                *credentials = Some(resolved.clone());
+                Ok(resolved)
+            }
+        }
+    }
+
+    async fn resolve(&self) -> anyhow::Result<Credentials> {
+        if let (Ok(access_key_id), Ok(secret_access_key)) = (
+            env::var("AWS_ACCESS_KEY_ID"),
+            env::var("AWS_SECRET_ACCESS_KEY"),
+        ) {
+            return Ok(Credentials {
+                access_key_id,
+                secret_access_key,
+                session_token: env::var("AWS_SESSION_TOKEN").ok(),
+                expires_at: None,
+            });
+        }
+        if let Some(credentials) = profile_credentials()? {
+            return Ok(credentials);
+        }
+        if let (Ok(token_file), Ok(role_arn)) = (
+            env::var("AWS_WEB_IDENTITY_TOKEN_FILE"),
+            env::var("AWS_ROLE_ARN"),
+        ) {
+            return self.web_identity_credentials(&token_file, &role_arn).await;
+        }
+        if let Some(credentials) = self.container_credentials().await? {
+            return Ok(credentials);
+        }
+        self.instance_credentials().await.map_err(|e| {
+            anyhow::anyhow!(
+                "no credentials in the environment or shared credentials file, \
+                 and the instance profile is unavailable; {}",
+                e
+            )
+        })
+    }
+
+    async fn web_identity_credentials(
+        &self,
+        token_file: &str,
+        role_arn: &str,
+    ) -> anyhow::Result<Credentials> {
+        let token = std::fs::read_to_string(token_file)
+            .map_err(|e| anyhow::anyhow!("reading {}; {}", token_file, e))?;
+        let session_name = env::var("AWS_ROLE_SESSION_NAME")
+            .unwrap_or_else(|_| env!("CARGO_PKG_NAME").to_string());
+        let response = self
+            .client
+            .get(STS_ENDPOINT)
+            .query(&[
+                ("Action", "AssumeRoleWithWebIdentity"),
+                ("Version", "2011-06-15"),
+                ("RoleArn", role_arn),
+                ("RoleSessionName", &session_name),
+                ("WebIdentityToken", token.trim()),
+            ])
+            .send()
+            .await?;
+        let status = response.status();
+        let body = response.text().await?;
+        if !status.is_success() {
+            anyhow::bail!(
+                "assuming {} with a web identity; {}: {}",
+                role_arn,
+                status,
+                body
+            )
+        }
+        let element = |name: &str| {
+            xml_element(&body, name).ok_or_else(|| anyhow::anyhow!("STS response has no {}", name))
+        };
+        Ok(Credentials {
+            access_key_id: element("AccessKeyId")?.to_string(),
+            secret_access_key: element("SecretAccessKey")?.to_string(),
+            session_token: Some(element("SessionToken")?.to_string()),
+            expires_at: element("Expiration")?.parse().ok(),
+        })
+    }
+
+    /// The task role of an ECS task (or any container given the endpoint).
+    async fn container_credentials(&self) -> anyhow::Result<Option<Credentials>> {
+        let url = match (
+            env::var("AWS_CONTAINER_CREDENTIALS_RELATIVE_URI"),
+            env::var("AWS_CONTAINER_CREDENTIALS_FULL_URI"),
+        ) {
+            (Ok(relative), _) => format!("{}{}", ECS_ENDPOINT, relative),
+            (Err(_), Ok(full)) => full,
+            _ => return Ok(None),
+        };
+        let mut request = self.client.get(&url);
+        if let Ok(token) = env::var("AWS_CONTAINER_AUTHORIZATION_TOKEN") {
+            request = request.header("Authorization", token);
+        }
+        let response = request.send().await?.error_for_status()?;
+        let credentials: MetadataCredentials = response.json().await?;
+        Ok(Some(credentials.into()))
+    }
+
+    /// The role of the EC2 instance profile, through IMDSv2.
+    async fn instance_credentials(&self) -> anyhow::Result<Credentials> {
+        let token = self
+            .client
+            .put(format!("{}/latest/api/token", IMDS_ENDPOINT))
+            .header("X-aws-ec2-metadata-token-ttl-seconds", "21600")
+            .timeout(std::time::Duration::from_secs(2))
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        let get = |path: String| {
+            self.client
+                .get(format!("{}{}", IMDS_ENDPOINT, path))
+                .header("X-aws-ec2-metadata-token", &token)
+                .send()
+        };
+        let roles = get("/latest/meta-data/iam/security-credentials/".to_string())
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        let role = roles
+            .lines()
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("the instance has no IAM role"))?;
+        let credentials: MetadataCredentials = get(format!(
+            "/latest/meta-data/iam/security-credentials/{}",
+            role
+        ))
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+        Ok(credentials.into())
+    }
+}
+
+/// The text of the first `<name>` element of an XML document.
+pub(super) fn xml_element<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let start = xml.find(&format!("<{}>", name))? + name.len() + 2;
+    let end = start + xml[start..].find(&format!("</{}>", name))?;
+    Some(&xml[start..end])
+}
+
+/// The active profile, `AWS_PROFILE` or `default`.
+fn profile() -> String {
+    env::var("AWS_PROFILE").unwrap_or_else(|_| "default".to_string())
+}
+
+/// `~/.aws/<name>`, or the file set in `variable`.
+fn shared_file(variable: &str, name: &str) -> Option<PathBuf> {
+    if let Ok(path) = env::var(variable) {
+        return Some(PathBuf::from(path));
+    }
+    let home = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE"))?;
+    Some(PathBuf::from(home).join(".aws").join(name))
+}
+
+/// The keys of `section` in an INI file such as `~/.aws/credentials`.
+fn ini_section(text: &str, section: &str) -> Option<Vec<(String, String)>> {
+    let mut keys = None;
+    for line in text.lines().map(str::trim) {
+        if line.starts_with('[') && line.ends_with(']') {
+            if keys.is_some() {
+                break;
+            }
+            if line[1..line.len() - 1].trim() == section {
+                keys = Some(Vec::new());
+            }
+        } else if let (Some(keys), Some((key, value))) = (keys.as_mut(), line.split_once('=')) {
+            keys.push((key.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    keys
+}
+
+/// The keys of the active profile in the shared credentials file.
+fn profile_credentials() -> anyhow::Result<Option<Credentials>> {
+    let Some(path) = shared_file("AWS_SHARED_CREDENTIALS_FILE", "credentials") else {
+        return Ok(None);
+    };
+    let Ok(text) = std::fs::read_to_string(&path) else {
+        return Ok(None);
+    };
+    let Some(keys) = ini_section(&text, &profile()) else {
+        return Ok(None);
+    };
+    let key = |name: &str| {
+        keys.iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.clone())
+    };
+    let (Some(access_key_id), Some(secret_access_key)) =
+        (key("aws_access_key_id"), key("aws_secret_access_key"))
+    else {
+        // e.g. a `role_arn` or `sso_*` profile; the next provider may have keys
+        tracing::debug!(
+            "profile {} in {} has no access keys",
+            profile(),
+            path.display()
+        );
+        return Ok(None);
+    };
+    Ok(Some(Credentials {
+        access_key_id,
+        secret_access_key,
+        session_token: key("aws_session_token"),
+        expires_at: None,
+    }))
+}
+
+/// The region from `AWS_REGION`, `AWS_DEFAULT_REGION` or the active profile
+/// of the shared config file.
+pub fn region() -> Option<String> {
+    if let Ok(region) = env::var("AWS_REGION").or_else(|_| env::var("AWS_DEFAULT_REGION")) {
+        return Some(region);
+    }
+    let path = shared_file("AWS_CONFIG_FILE", "config")?;
+    let text = std::fs::read_to_string(path).ok()?;
+    let profile = profile();
+    let section = match profile.as_str() {
+        "default" => profile,
+        name => format!("profile {}", name),
+    };
+    ini_section(&text, &section)?
+        .into_iter()
+        .find(|(key, _)| key == "region")
+        .map(|(_, region)| region)
+}
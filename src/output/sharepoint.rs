@@ -0,0 +1,197 @@
+use std::collections::{HashMap, HashSet};
+
+use serde_json::{Map, Value};
+
+use super::upload::block_on;
+use super::{Column, OutputSink};
+use crate::graph::lists::{ListItemWrite, ListUrl, SharePointList};
+use crate::graph::GraphClient;
+use crate::model::{Change, User};
+
+/// Writes per `$batch` call.
+const BATCH_SIZE: usize = crate::graph::lists::MAX_BATCH_SIZE;
+
+/// Most characters of a single line of text column.
+const MAX_TEXT_LENGTH: usize = 255;
+
+/// The list column of the user id; `ID` is the item's own.
+const USER_ID_COLUMN: &str = "user_id";
+
+/// A SharePoint list the users are written to, from `--sharepoint-list`.
+#[derive(Clone)]
+pub struct SharePointTarget {
+    graph: GraphClient,
+    url: ListUrl,
+    prune: bool,
+}
+
+impl std::fmt::Debug for SharePointTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SharePointTarget")
+            .field("url", &self.url.to_string())
+            .field("prune", &self.prune)
+            .finish()
+    }
+}
+
+impl SharePointTarget {
+    pub fn new(graph: GraphClient, url: ListUrl) -> Self {
+        Self {
+            graph,
+            url,
+            prune: true,
+        }
+    }
+
+    /// Whether the items of users the dump did not write are deleted once
+    /// it is complete; not for runs that only write some of the users, such
+    /// as `--delta` rounds.
+    pub fn with_prune(mut self, prune: bool) -> Self {
+        self.prune = prune;
+        self
+    }
+}
+
+/// Keeps a SharePoint list in sync with the dump: one item per user, titled
+/// with their display name, with a single line of text column per CSV
+/// column. Items of users already in the list are updated in place, so
+/// views, alerts and links keep working.
+///
+/// The list and missing columns are created. Users removed in delta mode
+/// are deleted, and so are the users a full dump did not write.
+pub struct SharePointSink {
+    target: SharePointTarget,
+    list: SharePointList,
+    columns: Vec<Column>,
+    /// Item ids by user id.
+    items: HashMap<String, String>,
+    /// Users written by this run.
+    seen: HashSet<String>,
+    /// Writes not sent yet, with the user each is for.
+    pending: Vec<(String, ListItemWrite)>,
+}
+
+impl SharePointSink {
+    pub fn open(target: SharePointTarget, columns: Vec<Column>) -> anyhow::Result<Self> {
+        let names: Vec<String> = columns.iter().map(column_name).collect();
+        let (list, items) = block_on(async {
+            let list = target.graph.open_list(&target.url, &names).await?;
+            let items = target
+                .graph
+                .fetch_list_items(&list, USER_ID_COLUMN)
+                .await
+                .map_err(|e| anyhow::anyhow!("fetching the items of {}; {}", target.url, e))?;
+            anyhow::Ok((list, items))
+        })?;
+        Ok(Self {
+            target,
+            list,
+            columns,
+            items,
+            seen: HashSet::new(),
+            pending: Vec::new(),
+        })
+    }
+
+    fn write_pending(&mut self) -> anyhow::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let (users, writes): (Vec<String>, Vec<ListItemWrite>) =
+            std::mem::take(&mut self.pending).into_iter().unzip();
+        let created = block_on(self.target.graph.write_list_items(&self.list, &writes))
+            .map_err(|e| anyhow::anyhow!("writing to {}; {}", self.target.url, e))?;
+        for (user, item) in users.into_iter().zip(created) {
+            if let Some(item) = item {
+                self.items.insert(user, item);
+            }
+        }
+        Ok(())
+    }
+
+    fn fields(&self, user: &User, manager: Option<&User>) -> Map<String, Value> {
+        let mut fields = Map::new();
+        fields.insert(
+            "Title".to_string(),
+            Value::String(truncate(user.display_name.clone())),
+        );
+        for column in &self.columns {
+            let value = column.text(user, manager).map(truncate);
+            fields.insert(
+                column_name(column),
+                value.map_or(Value::Null, Value::String),
+            );
+        }
+        fields
+    }
+}
+
+impl OutputSink for SharePointSink {
+    fn write_user(&mut self, user: &User, manager: Option<&User>) -> anyhow::Result<()> {
+        // an item created in the pending batch has no id to update yet
+        if self.pending.iter().any(|(id, _)| *id == user.id) {
+            self.write_pending()?;
+        }
+        self.seen.insert(user.id.clone());
+        let write = match (user.change, self.items.get(&user.id)) {
+            (Some(Change::Delete), Some(item)) => {
+                let item = item.clone();
+                self.items.remove(&user.id);
+                ListItemWrite::Delete(item)
+            }
+            (Some(Change::Delete), None) => return Ok(()),
+            (_, Some(item)) => ListItemWrite::Update(item.clone(), self.fields(user, manager)),
+            (_, None) => ListItemWrite::Create(self.fields(user, manager)),
+        };
+        self.pending.push((user.id.clone(), write));
+        if self.pending.len() >= BATCH_SIZE {
+            self.write_pending()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.write_pending()
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.write_pending()?;
+        let mut removed = 0;
+        if self.target.prune {
+            let stale: Vec<(String, String)> = self
+                .items
+                .iter()
+                .filter(|(user, _)| !self.seen.contains(*user))
+                .map(|(user, item)| (user.clone(), item.clone()))
+                .collect();
+            removed = stale.len();
+            for (user, item) in stale {
+                self.items.remove(&user);
+                self.pending.push((user, ListItemWrite::Delete(item)));
+            }
+            self.write_pending()?;
+        }
+        tracing::info!(
+            "Wrote {} users to {} and removed {}",
+            self.seen.len(),
+            self.list.web_url,
+            removed
+        );
+        Ok(())
+    }
+}
+
+/// The list column of `column`.
+fn column_name(column: &Column) -> String {
+    match column.name().as_str() {
+        "id" => USER_ID_COLUMN.to_string(),
+        name => name.to_string(),
+    }
+}
+
+fn truncate(value: String) -> String {
+    match value.char_indices().nth(MAX_TEXT_LENGTH) {
+        Some((end, _)) => value[..end].to_string(),
+        None => value,
+    }
+}
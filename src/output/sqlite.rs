@@ -0,0 +1,110 @@
+use std::path::Path;
+
+use rusqlite::{params_from_iter, Connection};
+
+use super::{Column, OutputSink};
+use crate::model::User;
+
+/// Columns indexed when present, besides `manager_id`.
+const INDEXED: [&str; 4] = ["department", "display_name", "mail", "employee_id"];
+
+/// Writes users into a SQLite database, one row per user with a
+/// self-referencing `manager_id`.
+///
+/// An existing database at the path is replaced. Rows are written in a
+/// transaction committed by [`OutputSink::flush`] and [`OutputSink::finish`].
+///
+/// The table has the CSV columns except `manager_display_name`, which is a
+/// join away.
+pub struct SqliteSink {
+    connection: Connection,
+    columns: Vec<Column>,
+    insert: String,
+}
+
+impl SqliteSink {
+    pub fn create(path: &Path, columns: Vec<Column>) -> anyhow::Result<Self> {
+        let columns: Vec<Column> = columns
+            .into_iter()
+            .filter(|column| *column != Column::ManagerDisplayName)
+            .collect();
+        let names: Vec<String> = columns.iter().map(Column::name).collect();
+
+        if path.exists() {
+            std::fs::remove_file(path)
+                .map_err(|e| anyhow::anyhow!("replacing {}; {}", path.display(), e))?;
+        }
+
+        let connection = Connection::open(path)?;
+        connection.execute_batch(&schema(&columns, &names))?;
+        connection.execute_batch("BEGIN")?;
+
+        let placeholders: Vec<String> = (1..=names.len()).map(|i| format!("?{}", i)).collect();
+        let insert = format!(
+            "INSERT OR REPLACE INTO users ({}) VALUES ({})",
+            quoted(&names).join(", "),
+            placeholders.join(", ")
+        );
+        Ok(Self {
+            connection,
+            columns,
+            insert,
+        })
+    }
+}
+
+impl OutputSink for SqliteSink {
+    fn write_user(&mut self, user: &User, manager: Option<&User>) -> anyhow::Result<()> {
+        let values = self.columns.iter().map(|column| column.text(user, manager));
+        self.connection
+            .prepare_cached(&self.insert)?
+            .execute(params_from_iter(values))?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        if !self.connection.is_autocommit() {
+            self.connection.execute_batch("COMMIT; BEGIN")?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        if !self.connection.is_autocommit() {
+            self.connection.execute_batch("COMMIT")?;
+        }
+        Ok(())
+    }
+}
+
+/// Builds the `users` table with a self-referencing `manager_id`.
+fn schema(columns: &[Column], names: &[String]) -> String {
+    let definitions: Vec<String> = columns
+        .iter()
+        .zip(names)
+        .map(|(column, name)| {
+            let constraint = match column {
+                Column::Property(property) if property == "id" => " PRIMARY KEY",
+                Column::ManagerId => " REFERENCES users(id)",
+                column if !column.nullable() => " NOT NULL",
+                _ => "",
+            };
+            format!("    \"{}\" TEXT{}", name, constraint)
+        })
+        .collect();
+
+    let mut schema = format!("CREATE TABLE users (\n{}\n);\n", definitions.join(",\n"));
+    for name in ["manager_id"].into_iter().chain(INDEXED) {
+        if names.iter().any(|n| n == name) {
+            schema.push_str(&format!(
+                "CREATE INDEX users_{0} ON users(\"{0}\");\n",
+                name
+            ));
+        }
+    }
+    schema
+}
+
+fn quoted(names: &[String]) -> Vec<String> {
+    names.iter().map(|name| format!("\"{}\"", name)).collect()
+}
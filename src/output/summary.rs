@@ -0,0 +1,238 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display};
+use std::io::Write;
+
+use super::OutputSink;
+use crate::model::User;
+
+/// Headcounts and shape of the org, gathered while users are written.
+#[derive(Default)]
+pub struct OrgStats {
+    users: usize,
+    departments: HashMap<String, usize>,
+    office_locations: HashMap<String, usize>,
+    employment_types: HashMap<String, usize>,
+    /// Users by presence availability, when fetched.
+    availabilities: HashMap<String, usize>,
+    /// Users present (not away or offline) by office location.
+    present_by_office: HashMap<String, usize>,
+    /// Users whose Microsoft 365 usage was joined, and those of them active
+    /// in its period, by department.
+    usage_by_department: HashMap<String, (usize, usize)>,
+    /// Users without MFA registered by manager display name, when MFA
+    /// registrations were fetched.
+    without_mfa_by_manager: HashMap<String, usize>,
+    /// Users whose MFA registration was looked up.
+    mfa_checked: usize,
+    depths: Vec<usize>,
+    ids: HashSet<String>,
+    /// Direct reports written per manager id.
+    reports: HashMap<String, usize>,
+}
+
+impl OrgStats {
+    pub fn add(&mut self, user: &User, manager: Option<&User>) {
+        self.users += 1;
+        *self
+            .departments
+            .entry(user.get_department().to_string())
+            .or_default() += 1;
+        *self
+            .office_locations
+            .entry(user.get_office_location().to_string())
+            .or_default() += 1;
+        *self
+            .employment_types
+            .entry(user.get_category().0.to_string())
+            .or_default() += 1;
+        if let Some(availability) = user
+            .presence
+            .as_ref()
+            .and_then(|p| p.availability.as_deref())
+        {
+            *self
+                .availabilities
+                .entry(availability.to_string())
+                .or_default() += 1;
+            if !matches!(
+                availability,
+                "Away" | "BeRightBack" | "Offline" | "PresenceUnknown"
+            ) {
+                *self
+                    .present_by_office
+                    .entry(user.get_office_location().to_string())
+                    .or_default() += 1;
+            }
+        }
+        if let Some(usage) = &user.usage {
+            let (users, active) = self
+                .usage_by_department
+                .entry(user.get_department().to_string())
+                .or_default();
+            *users += 1;
+            if usage.last_activity().is_some() {
+                *active += 1;
+            }
+        }
+        if let Some(mfa) = &user.mfa {
+            self.mfa_checked += 1;
+            if !mfa.is_mfa_registered {
+                let manager = manager.map_or("none", |m| m.display_name.as_str());
+                *self
+                    .without_mfa_by_manager
+                    .entry(manager.to_string())
+                    .or_default() += 1;
+            }
+        }
+        self.depths.extend(user.depth);
+        self.ids.insert(user.id.clone());
+        if let Some(manager) = manager {
+            *self.reports.entry(manager.id.clone()).or_default() += 1;
+        }
+    }
+
+    pub fn users(&self) -> usize {
+        self.users
+    }
+
+    pub fn departments(&self) -> usize {
+        self.departments.len()
+    }
+
+    /// Average number of direct reports of the managers in the dump, and the
+    /// number of those managers. Managers outside the dump (e.g. the root's)
+    /// are left out.
+    pub fn span_of_control(&self) -> Option<(f64, usize)> {
+        let spans: Vec<usize> = self
+            .reports
+            .iter()
+            .filter(|(id, _)| self.ids.contains(*id))
+            .map(|(_, &reports)| reports)
+            .collect();
+        if spans.is_empty() {
+            return None;
+        }
+        let average = spans.iter().sum::<usize>() as f64 / spans.len() as f64;
+        Some((average, spans.len()))
+    }
+}
+
+impl Display for OrgStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Users: {}", self.users)?;
+        if let Some(max) = self.depths.iter().max() {
+            let average = self.depths.iter().sum::<usize>() as f64 / self.depths.len() as f64;
+            writeln!(f, "Max depth: {}", max)?;
+            writeln!(f, "Average depth: {:.2}", average)?;
+        }
+        if let Some((average, managers)) = self.span_of_control() {
+            writeln!(
+                f,
+                "Average span of control: {:.2} ({} managers)",
+                average, managers
+            )?;
+        }
+        for (title, counts) in [
+            ("department", &self.departments),
+            ("office location", &self.office_locations),
+            ("employment type", &self.employment_types),
+        ] {
+            writeln!(f, "\nUsers by {}:", title)?;
+            write_counts(f, counts)?;
+        }
+        if !self.availabilities.is_empty() {
+            writeln!(f, "\nUsers by availability:")?;
+            write_counts(f, &self.availabilities)?;
+            writeln!(f, "\nPresent users by office location:")?;
+            write_counts(f, &self.present_by_office)?;
+        }
+        if !self.usage_by_department.is_empty() {
+            writeln!(f, "\nMicrosoft 365 adoption by department:")?;
+            write_adoption(f, &self.usage_by_department)?;
+        }
+        if self.mfa_checked > 0 {
+            let without: usize = self.without_mfa_by_manager.values().sum();
+            writeln!(
+                f,
+                "\nUsers without MFA registered: {} of {}",
+                without, self.mfa_checked
+            )?;
+            if without > 0 {
+                writeln!(f, "\nUsers without MFA registered by manager:")?;
+                write_counts(f, &self.without_mfa_by_manager)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes `counts` largest first, ties by name.
+fn write_counts(f: &mut fmt::Formatter<'_>, counts: &HashMap<String, usize>) -> fmt::Result {
+    let mut counts: Vec<(&String, &usize)> = counts.iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+    let width = counts.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    for (name, count) in counts {
+        writeln!(f, "  {:width$}  {}", name, count, width = width)?;
+    }
+    Ok(())
+}
+
+/// Writes the share of active users per department in `usage`, lowest
+/// first, ties by name.
+fn write_adoption(
+    f: &mut fmt::Formatter<'_>,
+    usage: &HashMap<String, (usize, usize)>,
+) -> fmt::Result {
+    let share = |&(users, active): &(usize, usize)| active as f64 / users as f64;
+    let mut usage: Vec<(&String, &(usize, usize))> = usage.iter().collect();
+    usage.sort_by(|a, b| share(a.1).total_cmp(&share(b.1)).then(a.0.cmp(b.0)));
+    let width = usage.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    for (name, counts @ (users, active)) in usage {
+        writeln!(
+            f,
+            "  {:width$}  {}/{} active ({:.0}%)",
+            name,
+            active,
+            users,
+            share(counts) * 100.0,
+            width = width
+        )?;
+    }
+    Ok(())
+}
+
+/// Passes users through to the inner sink and writes the [`OrgStats`] of the
+/// dump to `report` once it is complete.
+pub struct SummarySink<W: Write> {
+    inner: Box<dyn OutputSink + Send>,
+    report: W,
+    stats: OrgStats,
+}
+
+impl<W: Write> SummarySink<W> {
+    pub fn new(inner: Box<dyn OutputSink + Send>, report: W) -> Self {
+        Self {
+            inner,
+            report,
+            stats: OrgStats::default(),
+        }
+    }
+}
+
+impl<W: Write> OutputSink for SummarySink<W> {
+    fn write_user(&mut self, user: &User, manager: Option<&User>) -> anyhow::Result<()> {
+        self.stats.add(user, manager);
+        self.inner.write_user(user, manager)
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.inner.flush()
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.inner.finish()?;
+        write!(self.report, "{}", self.stats)?;
+        self.report.flush()?;
+        Ok(())
+    }
+}
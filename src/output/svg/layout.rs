@@ -0,0 +1,150 @@
+use super::super::tree::OrgNode;
+
+/// Direction in which the tree grows.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Orientation {
+    /// Root at the top, reports below.
+    #[default]
+    TopDown,
+    /// Root on the left, reports to the right.
+    LeftRight,
+}
+
+/// Size of a user box and the gaps between boxes, in pixels.
+pub const BOX_WIDTH: f64 = 200.0;
+pub const BOX_HEIGHT: f64 = 54.0;
+const SIBLING_GAP: f64 = 20.0;
+const LEVEL_GAP: f64 = 50.0;
+
+/// A user box placed on the canvas.
+pub struct Placed<'a> {
+    pub node: &'a OrgNode,
+    /// Top-left corner of the box.
+    pub x: f64,
+    pub y: f64,
+    /// Index of the manager's box, if it is on the canvas.
+    pub parent: Option<usize>,
+    /// Index of the top-level subtree this box belongs to, for coloring.
+    pub subtree: usize,
+}
+
+/// Result of laying out a forest.
+pub struct Layout<'a> {
+    pub boxes: Vec<Placed<'a>>,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// Lays out the forest so that leaves take consecutive slots along the
+/// breadth axis and every manager is centered over their reports.
+pub fn layout(roots: &[OrgNode], orientation: Orientation) -> Layout<'_> {
+    let mut slots = Vec::new();
+    let mut next_slot = 0.0;
+    let mut max_depth = 0;
+    for root in roots {
+        place(
+            root,
+            0,
+            None,
+            None,
+            &mut next_slot,
+            &mut max_depth,
+            &mut slots,
+        );
+    }
+
+    let (breadth_step, depth_step) = match orientation {
+        Orientation::TopDown => (BOX_WIDTH + SIBLING_GAP, BOX_HEIGHT + LEVEL_GAP),
+        Orientation::LeftRight => (BOX_HEIGHT + SIBLING_GAP, BOX_WIDTH + LEVEL_GAP),
+    };
+
+    let boxes = slots
+        .into_iter()
+        .map(|slot| {
+            let breadth = SIBLING_GAP + slot.breadth * breadth_step;
+            let depth = SIBLING_GAP + slot.depth as f64 * depth_step;
+            let (x, y) = match orientation {
+                Orientation::TopDown => (breadth, depth),
+                Orientation::LeftRight => (depth, breadth),
+            };
+            Placed {
+                node: slot.node,
+                x,
+                y,
+                parent: slot.parent,
+                subtree: slot.subtree,
+            }
+        })
+        .collect();
+
+    let breadth = 2.0 * SIBLING_GAP + next_slot.max(1.0) * breadth_step - SIBLING_GAP;
+    let depth = 2.0 * SIBLING_GAP + (max_depth + 1) as f64 * depth_step - LEVEL_GAP;
+    let (width, height) = match orientation {
+        Orientation::TopDown => (breadth, depth),
+        Orientation::LeftRight => (depth, breadth),
+    };
+
+    Layout {
+        boxes,
+        width,
+        height,
+    }
+}
+
+struct Slot<'a> {
+    node: &'a OrgNode,
+    breadth: f64,
+    depth: usize,
+    parent: Option<usize>,
+    subtree: usize,
+}
+
+/// Places `node` and its reports post-order; returns the index of its slot.
+fn place<'a>(
+    node: &'a OrgNode,
+    depth: usize,
+    parent: Option<usize>,
+    subtree: Option<usize>,
+    next_slot: &mut f64,
+    max_depth: &mut usize,
+    slots: &mut Vec<Slot<'a>>,
+) -> usize {
+    *max_depth = (*max_depth).max(depth);
+
+    let index = slots.len();
+    slots.push(Slot {
+        node,
+        breadth: 0.0,
+        depth,
+        parent,
+        subtree: subtree.unwrap_or(0),
+    });
+
+    let mut first = None;
+    let mut last = None;
+    for (i, report) in node.reports.iter().enumerate() {
+        // each report of a root starts its own colored subtree
+        let subtree = subtree.unwrap_or(i);
+        let child = place(
+            report,
+            depth + 1,
+            Some(index),
+            Some(subtree),
+            next_slot,
+            max_depth,
+            slots,
+        );
+        first.get_or_insert(child);
+        last = Some(child);
+    }
+
+    slots[index].breadth = match (first, last) {
+        (Some(first), Some(last)) => (slots[first].breadth + slots[last].breadth) / 2.0,
+        _ => {
+            let breadth = *next_slot;
+            *next_slot += 1.0;
+            breadth
+        }
+    };
+    index
+}
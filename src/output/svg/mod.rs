@@ -0,0 +1,159 @@
+use std::io::Write;
+
+use super::tree::TreeBuilder;
+use super::{escape_xml, OutputSink};
+use crate::model::User;
+
+mod layout;
+
+pub use layout::Orientation;
+use layout::{layout, Placed, BOX_HEIGHT, BOX_WIDTH};
+
+/// Colors given to the subtrees below the root when none are configured.
+pub const DEFAULT_COLORS: [&str; 8] = [
+    "#4e79a7", "#f28e2b", "#e15759", "#76b7b2", "#59a14f", "#edc948", "#b07aa1", "#ff9da7",
+];
+
+/// Color of the root boxes.
+const ROOT_COLOR: &str = "#555555";
+
+/// Longest name or title drawn in a box before it is truncated.
+const MAX_LABEL_CHARS: usize = 28;
+
+/// Renders the reporting tree as a standalone SVG org chart.
+pub struct SvgSink<W: Write> {
+    writer: W,
+    orientation: Orientation,
+    colors: Vec<String>,
+    tree: TreeBuilder,
+}
+
+impl<W: Write> SvgSink<W> {
+    /// Uses [`DEFAULT_COLORS`] when `colors` is empty.
+    pub fn new(writer: W, orientation: Orientation, colors: Vec<String>) -> Self {
+        let colors = if colors.is_empty() {
+            DEFAULT_COLORS.iter().map(|c| c.to_string()).collect()
+        } else {
+            colors
+        };
+        Self {
+            writer,
+            orientation,
+            colors,
+            tree: TreeBuilder::default(),
+        }
+    }
+
+    fn color(&self, placed: &Placed) -> &str {
+        if placed.parent.is_none() {
+            ROOT_COLOR
+        } else {
+            &self.colors[placed.subtree % self.colors.len()]
+        }
+    }
+}
+
+impl<W: Write> OutputSink for SvgSink<W> {
+    fn write_user(&mut self, user: &User, manager: Option<&User>) -> anyhow::Result<()> {
+        self.tree.push(user, manager);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        let roots = std::mem::take(&mut self.tree).build();
+        let layout = layout(&roots, self.orientation);
+
+        writeln!(
+            self.writer,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}" font-family="Helvetica, Arial, sans-serif">"#,
+            layout.width, layout.height
+        )?;
+        writeln!(
+            self.writer,
+            r##"<rect width="100%" height="100%" fill="#ffffff"/>"##
+        )?;
+
+        writeln!(
+            self.writer,
+            r##"<g fill="none" stroke="#999999" stroke-width="1.5">"##
+        )?;
+        for placed in &layout.boxes {
+            if let Some(parent) = placed.parent {
+                let path = self.edge(&layout.boxes[parent], placed);
+                writeln!(self.writer, r#"<path d="{}"/>"#, path)?;
+            }
+        }
+        writeln!(self.writer, "</g>")?;
+
+        for placed in &layout.boxes {
+            let user = &placed.node.user;
+            let color = escape_xml(self.color(placed));
+            writeln!(self.writer, "<g>")?;
+            writeln!(
+                self.writer,
+                "<title>{}</title>",
+                escape_xml(&format!("{} ({})", user.display_name, user.get_email()))
+            )?;
+            writeln!(
+                self.writer,
+                r##"<rect x="{}" y="{}" width="{}" height="{}" rx="6" fill="#ffffff" stroke="{}" stroke-width="2"/>"##,
+                placed.x, placed.y, BOX_WIDTH, BOX_HEIGHT, color
+            )?;
+            writeln!(
+                self.writer,
+                r#"<rect x="{}" y="{}" width="6" height="{}" fill="{}"/>"#,
+                placed.x, placed.y, BOX_HEIGHT, color
+            )?;
+            writeln!(
+                self.writer,
+                r##"<text x="{}" y="{}" font-size="13" font-weight="bold" fill="#222222">{}</text>"##,
+                placed.x + 14.0,
+                placed.y + 22.0,
+                escape_xml(&truncate(&user.display_name))
+            )?;
+            if let Some(job_title) = &user.job_title {
+                writeln!(
+                    self.writer,
+                    r##"<text x="{}" y="{}" font-size="11" fill="#555555">{}</text>"##,
+                    placed.x + 14.0,
+                    placed.y + 40.0,
+                    escape_xml(&truncate(job_title))
+                )?;
+            }
+            writeln!(self.writer, "</g>")?;
+        }
+
+        writeln!(self.writer, "</svg>")?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl<W: Write> SvgSink<W> {
+    /// Elbow connector from the manager's box to the report's box.
+    fn edge(&self, from: &Placed, to: &Placed) -> String {
+        match self.orientation {
+            Orientation::TopDown => {
+                let (x1, y1) = (from.x + BOX_WIDTH / 2.0, from.y + BOX_HEIGHT);
+                let (x2, y2) = (to.x + BOX_WIDTH / 2.0, to.y);
+                let mid = (y1 + y2) / 2.0;
+                format!("M{} {} V{} H{} V{}", x1, y1, mid, x2, y2)
+            }
+            Orientation::LeftRight => {
+                let (x1, y1) = (from.x + BOX_WIDTH, from.y + BOX_HEIGHT / 2.0);
+                let (x2, y2) = (to.x, to.y + BOX_HEIGHT / 2.0);
+                let mid = (x1 + x2) / 2.0;
+                format!("M{} {} H{} V{} H{}", x1, y1, mid, y2, x2)
+            }
+        }
+    }
+}
+
+fn truncate(s: &str) -> String {
+    if s.chars().count() <= MAX_LABEL_CHARS {
+        s.to_string()
+    } else {
+        let truncated: String = s.chars().take(MAX_LABEL_CHARS - 1).collect();
+        format!("{}…", truncated)
+    }
+}
@@ -0,0 +1,266 @@
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use reqwest::{Client, Url};
+use serde_json::{json, Value};
+
+use super::summary::OrgStats;
+use super::upload::block_on;
+use super::OutputSink;
+use crate::diff::{Dump, OrgDiff};
+use crate::graph::GraphClient;
+use crate::model::{Change, User};
+use crate::snapshot::SnapshotStore;
+
+/// Where the summary card of a run is posted.
+#[derive(Clone)]
+enum Destination {
+    /// A Teams incoming webhook or Workflows URL, from `--teams-webhook`.
+    Webhook { client: Client, url: Url },
+    /// A channel, through Graph as the signed-in user, from
+    /// `--teams-channel`.
+    Channel {
+        graph: GraphClient,
+        team_id: String,
+        channel_id: String,
+    },
+}
+
+/// A Teams channel told about each completed run.
+#[derive(Clone)]
+pub struct TeamsNotice {
+    destination: Destination,
+    link: Option<String>,
+}
+
+impl std::fmt::Debug for TeamsNotice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // leaves out the webhook URL, which authorizes posting
+        let destination = match &self.destination {
+            Destination::Webhook { .. } => "webhook".to_string(),
+            Destination::Channel {
+                team_id,
+                channel_id,
+                ..
+            } => format!("{}/{}", team_id, channel_id),
+        };
+        f.debug_struct("TeamsNotice")
+            .field("destination", &destination)
+            .field("link", &self.link)
+            .finish()
+    }
+}
+
+impl TeamsNotice {
+    pub fn webhook(client: Client, url: Url) -> Self {
+        Self {
+            destination: Destination::Webhook { client, url },
+            link: None,
+        }
+    }
+
+    /// Posts to `channel`, given as `<team id>/<channel id>`.
+    pub fn channel(graph: GraphClient, channel: &str) -> anyhow::Result<Self> {
+        let (team_id, channel_id) = channel
+            .split_once('/')
+            .filter(|(team, channel)| !team.is_empty() && !channel.is_empty())
+            .ok_or_else(|| {
+                anyhow::anyhow!("{:?} is not a channel like <team id>/<channel id>", channel)
+            })?;
+        Ok(Self {
+            destination: Destination::Channel {
+                graph,
+                team_id: team_id.to_string(),
+                channel_id: channel_id.to_string(),
+            },
+            link: None,
+        })
+    }
+
+    /// Shows where the output went; URLs get a button.
+    pub fn with_link(mut self, link: Option<String>) -> Self {
+        self.link = link;
+        self
+    }
+
+    async fn post(&self, card: Value) -> anyhow::Result<()> {
+        match &self.destination {
+            Destination::Webhook { client, url } => {
+                let message = json!({
+                    "type": "message",
+                    "attachments": [{
+                        "contentType": "application/vnd.microsoft.card.adaptive",
+                        "content": card,
+                    }],
+                });
+                let response = client.post(url.clone()).json(&message).send().await?;
+                let status = response.status();
+                if !status.is_success() {
+                    let response_txt = response.text().await?;
+                    anyhow::bail!("posting to the Teams webhook; {}: {}", status, response_txt)
+                }
+                Ok(())
+            }
+            Destination::Channel {
+                graph,
+                team_id,
+                channel_id,
+            } => graph.post_channel_card(team_id, channel_id, &card).await,
+        }
+    }
+}
+
+/// Posts a summary card to Teams once the dump is complete: the users,
+/// departments and managers written, the changes since the previous run and
+/// where the output went.
+///
+/// The changes are the delta's in delta mode, else those between the two
+/// latest snapshots when the run records one.
+pub struct TeamsSink {
+    inner: Box<dyn OutputSink + Send>,
+    notice: TeamsNotice,
+    snapshot_dir: Option<PathBuf>,
+    stats: OrgStats,
+    /// Users added, updated and deleted, in delta mode.
+    changes: [usize; 3],
+    started: Instant,
+}
+
+impl TeamsSink {
+    pub fn new(
+        inner: Box<dyn OutputSink + Send>,
+        notice: TeamsNotice,
+        snapshot_dir: Option<&Path>,
+    ) -> Self {
+        Self {
+            inner,
+            notice,
+            snapshot_dir: snapshot_dir.map(Path::to_path_buf),
+            stats: OrgStats::default(),
+            changes: [0; 3],
+            started: Instant::now(),
+        }
+    }
+
+    /// The changes since the previous snapshot, which the inner sink has
+    /// just recorded.
+    fn snapshot_changes(&self) -> Option<Vec<(String, String)>> {
+        let (old, new) = SnapshotStore::new(self.snapshot_dir.as_ref()?)
+            .latest_pair()
+            .ok()?;
+        let load = |path: &Path| {
+            Dump::load(path)
+                .map_err(|e| tracing::warn!("Cannot compare with the previous snapshot; {}", e))
+                .ok()
+        };
+        let diff = OrgDiff::between(&load(&old.users_path())?, &load(&new.users_path())?);
+        Some(vec![
+            ("Since".to_string(), old.name()),
+            ("Joiners".to_string(), diff.joiners.len().to_string()),
+            ("Leavers".to_string(), diff.leavers.len().to_string()),
+            (
+                "Manager changes".to_string(),
+                diff.manager_changes.len().to_string(),
+            ),
+            (
+                "Title changes".to_string(),
+                diff.title_changes.len().to_string(),
+            ),
+            (
+                "Department moves".to_string(),
+                diff.department_moves.len().to_string(),
+            ),
+        ])
+    }
+
+    fn card(&self) -> Value {
+        let mut facts = vec![
+            ("Users".to_string(), self.stats.users().to_string()),
+            (
+                "Departments".to_string(),
+                self.stats.departments().to_string(),
+            ),
+        ];
+        if let Some((_, managers)) = self.stats.span_of_control() {
+            facts.push(("Managers".to_string(), managers.to_string()));
+        }
+        if self.changes.iter().any(|&count| count > 0) {
+            for (title, count) in ["Added", "Updated", "Removed"].iter().zip(self.changes) {
+                facts.push((title.to_string(), count.to_string()));
+            }
+        } else if let Some(changes) = self.snapshot_changes() {
+            facts.extend(changes);
+        }
+        facts.push((
+            "Duration".to_string(),
+            format!("{}s", self.started.elapsed().as_secs()),
+        ));
+        if let Some(link) = &self.notice.link {
+            facts.push(("Output".to_string(), link.clone()));
+        }
+
+        let mut card = json!({
+            "type": "AdaptiveCard",
+            "$schema": "http://adaptivecards.io/schemas/adaptive-card.json",
+            "version": "1.4",
+            "body": [
+                {
+                    "type": "TextBlock",
+                    "text": "Org dump complete",
+                    "size": "Medium",
+                    "weight": "Bolder",
+                },
+                {
+                    "type": "TextBlock",
+                    "text": chrono::Utc::now().format("%Y-%m-%d %H:%M UTC").to_string(),
+                    "isSubtle": true,
+                    "spacing": "None",
+                },
+                {
+                    "type": "FactSet",
+                    "facts": facts
+                        .into_iter()
+                        .map(|(title, value)| json!({ "title": title, "value": value }))
+                        .collect::<Vec<_>>(),
+                },
+            ],
+        });
+        if let Some(link) = self
+            .notice
+            .link
+            .as_ref()
+            .filter(|link| link.starts_with("https://") || link.starts_with("http://"))
+        {
+            card["actions"] = json!([{
+                "type": "Action.OpenUrl",
+                "title": "Open output",
+                "url": link,
+            }]);
+        }
+        card
+    }
+}
+
+impl OutputSink for TeamsSink {
+    fn write_user(&mut self, user: &User, manager: Option<&User>) -> anyhow::Result<()> {
+        match user.change {
+            Some(Change::Add) => self.changes[0] += 1,
+            Some(Change::Update) => self.changes[1] += 1,
+            Some(Change::Delete) => self.changes[2] += 1,
+            None => {}
+        }
+        self.stats.add(user, manager);
+        self.inner.write_user(user, manager)
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.inner.flush()
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.inner.finish()?;
+        block_on(self.notice.post(self.card()))?;
+        tracing::info!("Posted the run summary to Teams");
+        Ok(())
+    }
+}
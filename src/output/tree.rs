@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+use std::io::Write;
+
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+
+use super::json::JsonRecord;
+use super::{Column, OutputSink, UserFilter};
+use crate::model::User;
+
+/// A user and the subtree of everyone reporting to them.
+pub struct OrgNode {
+    pub user: User,
+    pub manager: Option<User>,
+    pub reports: Vec<OrgNode>,
+}
+
+/// Collects users in traversal order and assembles them into trees.
+#[derive(Default)]
+pub struct TreeBuilder {
+    users: Vec<(User, Option<User>)>,
+}
+
+impl TreeBuilder {
+    pub fn push(&mut self, user: &User, manager: Option<&User>) {
+        self.users.push((user.clone(), manager.cloned()));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.users.is_empty()
+    }
+
+    /// Builds the forest of collected users; users whose manager was not
+    /// collected become roots, and depths are counted from them. Siblings keep their traversal order, and
+    /// users caught in a management cycle are attached where the cycle is
+    /// first entered.
+    pub fn build(self) -> Vec<OrgNode> {
+        let index: HashMap<String, usize> = self
+            .users
+            .iter()
+            .enumerate()
+            .map(|(i, (user, _))| (user.id.clone(), i))
+            .collect();
+
+        let mut children: Vec<Vec<usize>> = vec![Vec::new(); self.users.len()];
+        let mut roots = Vec::new();
+        for (i, (_, manager)) in self.users.iter().enumerate() {
+            match manager.as_ref().and_then(|m| index.get(&m.id)) {
+                Some(&parent) if parent != i => children[parent].push(i),
+                _ => roots.push(i),
+            }
+        }
+
+        let mut slots: Vec<Option<(User, Option<User>)>> =
+            self.users.into_iter().map(Some).collect();
+        let mut forest: Vec<OrgNode> = roots
+            .into_iter()
+            .filter_map(|i| assemble(i, None, &children, &mut slots))
+            .collect();
+
+        // whatever is left is only reachable through a cycle
+        for i in 0..slots.len() {
+            if let Some(node) = assemble(i, None, &children, &mut slots) {
+                forest.push(node);
+            }
+        }
+        forest
+    }
+}
+
+fn assemble(
+    i: usize,
+    parent: Option<&User>,
+    children: &[Vec<usize>],
+    slots: &mut [Option<(User, Option<User>)>],
+) -> Option<OrgNode> {
+    let (mut user, manager) = slots[i].take()?;
+    match parent {
+        Some(parent) => user.set_walk_manager(parent),
+        None => {
+            user.depth = Some(0);
+            user.management_chain.clear();
+            user.management_chain_ids.clear();
+        }
+    }
+    let reports = children[i]
+        .iter()
+        .filter_map(|&child| assemble(child, Some(&user), children, slots))
+        .collect();
+    Some(OrgNode {
+        user,
+        manager,
+        reports,
+    })
+}
+
+impl OrgNode {
+    /// Sets the headcount of every user in the subtree to their number of
+    /// direct and indirect reports included by `filter`, and returns the one
+    /// of this node.
+    pub fn roll_up_headcount(&mut self, filter: &UserFilter) -> usize {
+        let headcount = self
+            .reports
+            .iter_mut()
+            .map(|report| {
+                let included = usize::from(filter.includes(&report.user));
+                report.roll_up_headcount(filter) + included
+            })
+            .sum();
+        self.user.headcount = Some(headcount);
+        headcount
+    }
+}
+
+/// Writes `node` and its subtree to `sink`, managers before their reports.
+pub fn write_tree(sink: &mut dyn OutputSink, node: &OrgNode) -> anyhow::Result<()> {
+    sink.write_user(&node.user, node.manager.as_ref())?;
+    for report in &node.reports {
+        write_tree(sink, report)?;
+    }
+    Ok(())
+}
+
+/// Serializes `node` and its subtree with the user fields in `columns`.
+struct JsonNode<'a> {
+    node: &'a OrgNode,
+    columns: &'a [Column],
+}
+
+impl<'a> JsonNode<'a> {
+    fn new(node: &'a OrgNode, columns: &'a [Column]) -> Self {
+        Self { node, columns }
+    }
+}
+
+impl Serialize for JsonNode<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut node = serializer.serialize_struct("OrgNode", 2)?;
+        node.serialize_field(
+            "user",
+            &JsonRecord::new(&self.node.user, self.node.manager.as_ref(), self.columns),
+        )?;
+        let reports: Vec<JsonNode> = self
+            .node
+            .reports
+            .iter()
+            .map(|node| JsonNode::new(node, self.columns))
+            .collect();
+        node.serialize_field("reports", &reports)?;
+        node.end()
+    }
+}
+
+/// Writes the org as nested JSON, `{"user": ..., "reports": [...]}`.
+///
+/// A single root is written as an object, several roots as an array.
+pub struct JsonTreeSink<W: Write> {
+    writer: W,
+    columns: Vec<Column>,
+    tree: TreeBuilder,
+}
+
+impl<W: Write> JsonTreeSink<W> {
+    pub fn new(writer: W, columns: Vec<Column>) -> Self {
+        Self {
+            writer,
+            columns,
+            tree: TreeBuilder::default(),
+        }
+    }
+}
+
+impl<W: Write> OutputSink for JsonTreeSink<W> {
+    fn write_user(&mut self, user: &User, manager: Option<&User>) -> anyhow::Result<()> {
+        self.tree.push(user, manager);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        let roots = std::mem::take(&mut self.tree).build();
+        let roots: Vec<JsonNode> = roots
+            .iter()
+            .map(|root| JsonNode::new(root, &self.columns))
+            .collect();
+        match roots.as_slice() {
+            [root] => serde_json::to_writer_pretty(&mut self.writer, root)?,
+            roots => serde_json::to_writer_pretty(&mut self.writer, roots)?,
+        }
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Holds back the users of a dump until it is complete, then writes them to
+/// the inner sink with their headcounts rolled up.
+pub struct HeadcountSink {
+    inner: Box<dyn OutputSink + Send>,
+    filter: UserFilter,
+    tree: TreeBuilder,
+}
+
+impl HeadcountSink {
+    /// Only users included by `filter` are counted.
+    pub fn new(inner: Box<dyn OutputSink + Send>, filter: UserFilter) -> Self {
+        Self {
+            inner,
+            filter,
+            tree: TreeBuilder::default(),
+        }
+    }
+}
+
+impl OutputSink for HeadcountSink {
+    fn write_user(&mut self, user: &User, manager: Option<&User>) -> anyhow::Result<()> {
+        self.tree.push(user, manager);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        for mut root in std::mem::take(&mut self.tree).build() {
+            root.roll_up_headcount(&self.filter);
+            write_tree(self.inner.as_mut(), &root)?;
+        }
+        self.inner.finish()
+    }
+}
@@ -0,0 +1,242 @@
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use super::OutputSink;
+use crate::model::User;
+
+/// A remote object the output is streamed to in parts, such as a blob or
+/// an S3 object. Nothing is visible until the upload is committed.
+pub trait Upload: Send {
+    /// Output buffered before it is handed to [`put_part`](Self::put_part).
+    fn part_size(&self) -> usize;
+
+    /// Uploads the next part of the output; `last` for the remainder at the
+    /// end of the dump, which may be empty.
+    fn put_part(&mut self, data: Vec<u8>, last: bool) -> anyhow::Result<()>;
+
+    /// Makes the uploaded parts the content of the object.
+    fn commit(&mut self) -> anyhow::Result<()>;
+
+    /// Discards the parts of a dump that failed.
+    fn abort(&mut self) {}
+
+    /// The object, for messages; without credentials.
+    fn describe(&self) -> String;
+}
+
+/// Runs `future` to completion from the synchronous sinks.
+pub(super) fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    tokio::task::block_in_place(|| tokio::runtime::Handle::current().block_on(future))
+}
+
+/// An upload and how far the output got.
+struct State {
+    upload: Box<dyn Upload>,
+    /// The first failure of the writer, which cannot return it when dropped.
+    error: Option<anyhow::Error>,
+    /// Set once the dump is complete; the output of failed dumps is not
+    /// uploaded further.
+    finishing: bool,
+}
+
+/// Starts `upload`: the inner sink writes to the returned writer and is
+/// wrapped in the returned [`UploadSink`] with `wrap`.
+pub fn start(
+    upload: Box<dyn Upload>,
+    wrap: impl FnOnce(UploadWriter) -> anyhow::Result<Box<dyn OutputSink + Send>>,
+) -> anyhow::Result<UploadSink> {
+    let part_size = upload.part_size();
+    let state = Arc::new(Mutex::new(State {
+        upload,
+        error: None,
+        finishing: false,
+    }));
+    let writer = UploadWriter {
+        state: state.clone(),
+        buffer: Vec::with_capacity(part_size),
+        part_size,
+    };
+    Ok(UploadSink {
+        inner: Some(wrap(writer)?),
+        state,
+        committed: false,
+    })
+}
+
+/// Uploads everything written to it in parts; the rest is uploaded when the
+/// writer is dropped at the end of the dump.
+pub struct UploadWriter {
+    state: Arc<Mutex<State>>,
+    buffer: Vec<u8>,
+    part_size: usize,
+}
+
+impl UploadWriter {
+    fn put_part(&mut self, last: bool) -> anyhow::Result<()> {
+        let data = std::mem::replace(&mut self.buffer, Vec::with_capacity(self.part_size));
+        self.state.lock().unwrap().upload.put_part(data, last)
+    }
+}
+
+impl Write for UploadWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        if self.buffer.len() >= self.part_size {
+            self.put_part(false).map_err(std::io::Error::other)?;
+        }
+        Ok(buf.len())
+    }
+
+    /// Parts are only uploaded once full; the object is not readable before
+    /// it is committed anyway.
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for UploadWriter {
+    fn drop(&mut self) {
+        if !self.state.lock().unwrap().finishing {
+            return;
+        }
+        if let Err(e) = self.put_part(true) {
+            self.state.lock().unwrap().error.get_or_insert(e);
+        }
+    }
+}
+
+/// Commits the upload of the inner sink's output once the dump is
+/// complete, so the object only ever holds complete dumps, and aborts it
+/// otherwise.
+pub struct UploadSink {
+    inner: Option<Box<dyn OutputSink + Send>>,
+    state: Arc<Mutex<State>>,
+    committed: bool,
+}
+
+impl UploadSink {
+    fn inner(&mut self) -> anyhow::Result<&mut Box<dyn OutputSink + Send>> {
+        self.inner
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("the upload is already complete"))
+    }
+}
+
+impl OutputSink for UploadSink {
+    fn write_user(&mut self, user: &User, manager: Option<&User>) -> anyhow::Result<()> {
+        self.inner()?.write_user(user, manager)
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.inner()?.flush()
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        // completes the output, e.g. writes the end of a compressed stream,
+        // so failing to upload it fails the dump
+        self.inner()?.finish()?;
+        // uploads the last part
+        self.state.lock().unwrap().finishing = true;
+        self.inner = None;
+        let mut state = self.state.lock().unwrap();
+        if let Some(e) = state.error.take() {
+            return Err(e);
+        }
+        state.upload.commit()?;
+        self.committed = true;
+        tracing::info!("Uploaded the output to {}", state.upload.describe());
+        Ok(())
+    }
+}
+
+impl Drop for UploadSink {
+    fn drop(&mut self) {
+        // the writer must not upload the rest of a failed dump
+        self.inner = None;
+        if !self.committed {
+            self.state.lock().unwrap().upload.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use serde_json::Map;
+
+    use super::super::{writer_sink, Column, Compression, OutputFormat, OutputOptions};
+    use super::*;
+
+    /// An upload kept in memory.
+    #[derive(Clone, Default)]
+    struct Memory {
+        data: Arc<Mutex<Vec<u8>>>,
+        committed: Arc<Mutex<bool>>,
+        part_size: usize,
+        fail: bool,
+    }
+
+    impl Upload for Memory {
+        fn part_size(&self) -> usize {
+            self.part_size
+        }
+
+        fn put_part(&mut self, data: Vec<u8>, _last: bool) -> anyhow::Result<()> {
+            if self.fail {
+                anyhow::bail!("uploading a part; connection reset")
+            }
+            self.data.lock().unwrap().extend(data);
+            Ok(())
+        }
+
+        fn commit(&mut self) -> anyhow::Result<()> {
+            *self.committed.lock().unwrap() = true;
+            Ok(())
+        }
+
+        fn describe(&self) -> String {
+            "memory".to_string()
+        }
+    }
+
+    fn dump(upload: &Memory) -> anyhow::Result<()> {
+        let options = OutputOptions {
+            compression: Some(Compression::Gzip),
+            columns: vec![Column::Property("displayName".to_string())],
+            ..OutputOptions::default()
+        };
+        let mut sink = start(Box::new(upload.clone()), |writer| {
+            writer_sink(OutputFormat::Csv, Box::new(writer), &options)
+        })?;
+        sink.write_user(&User::record("u1", "Ann", Map::new()), None)?;
+        sink.finish()
+    }
+
+    #[test]
+    fn compressed_uploads_are_complete() {
+        let upload = Memory {
+            part_size: 16,
+            ..Memory::default()
+        };
+        dump(&upload).unwrap();
+        assert!(*upload.committed.lock().unwrap());
+        let data = upload.data.lock().unwrap().clone();
+        let mut csv = String::new();
+        flate2::read::GzDecoder::new(&data[..])
+            .read_to_string(&mut csv)
+            .unwrap();
+        assert_eq!(csv, "display_name\nAnn\n");
+    }
+
+    #[test]
+    fn failing_to_upload_the_end_of_the_stream_fails_the_dump() {
+        let upload = Memory {
+            part_size: 1,
+            fail: true,
+            ..Memory::default()
+        };
+        assert!(dump(&upload).is_err());
+        assert!(!*upload.committed.lock().unwrap());
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::{BTreeMap, HashSet};
+use std::io::Write;
+
+use rust_xlsxwriter::{Format, Workbook, Worksheet};
+
+use super::{Column, OutputSink};
+use crate::model::User;
+
+/// Excel limits sheet names to 31 characters.
+const MAX_SHEET_NAME_LEN: usize = 31;
+
+/// Columns wider than this are capped when autofitting.
+const MAX_COLUMN_WIDTH: u32 = 60;
+
+type Row = Vec<String>;
+
+/// Writes users to an Excel workbook with a frozen, filterable header row,
+/// either as a single sheet or one sheet per department.
+pub struct XlsxSink<W: Write> {
+    writer: W,
+    columns: Vec<Column>,
+    sheet_per_department: bool,
+    null_as: Option<String>,
+    rows: Vec<Row>,
+}
+
+impl<W: Write> XlsxSink<W> {
+    pub fn new(writer: W, columns: Vec<Column>, sheet_per_department: bool) -> Self {
+        Self {
+            writer,
+            columns,
+            sheet_per_department,
+            null_as: None,
+            rows: Vec::new(),
+        }
+    }
+
+    /// Writes `null_as` for missing values instead of the placeholders.
+    pub fn with_null_as(mut self, null_as: Option<String>) -> Self {
+        self.null_as = null_as;
+        self
+    }
+}
+
+impl<W: Write> OutputSink for XlsxSink<W> {
+    fn write_user(&mut self, user: &User, manager: Option<&User>) -> anyhow::Result<()> {
+        self.rows.push(
+            self.columns
+                .iter()
+                .map(|column| column.text_or(user, manager, self.null_as.as_deref()))
+                .collect(),
+        );
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        let rows = std::mem::take(&mut self.rows);
+        let header: Vec<String> = self.columns.iter().map(Column::name).collect();
+        let mut workbook = Workbook::new();
+
+        if self.sheet_per_department {
+            let department = self
+                .columns
+                .iter()
+                .position(|column| *column == Column::Property("department".to_string()));
+            let mut departments: BTreeMap<String, Vec<Row>> = BTreeMap::new();
+            for row in rows {
+                let name = department.map_or("unknown", |i| row[i].as_str());
+                departments.entry(name.to_string()).or_default().push(row);
+            }
+
+            let mut used_names = HashSet::new();
+            for (department, rows) in &departments {
+                let name = sheet_name(department, &mut used_names);
+                write_sheet(workbook.add_worksheet(), &name, &header, rows)?;
+            }
+        } else {
+            write_sheet(workbook.add_worksheet(), "Users", &header, &rows)?;
+        }
+
+        self.writer.write_all(&workbook.save_to_buffer()?)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+fn write_sheet(
+    sheet: &mut Worksheet,
+    name: &str,
+    header: &[String],
+    rows: &[Row],
+) -> anyhow::Result<()> {
+    let header_format = Format::new().set_bold();
+
+    sheet.set_name(name)?;
+    sheet.write_row_with_format(0, 0, header, &header_format)?;
+    for (i, row) in rows.iter().enumerate() {
+        sheet.write_row(i as u32 + 1, 0, row)?;
+    }
+
+    sheet.set_freeze_panes(1, 0)?;
+    sheet.autofilter(0, 0, rows.len() as u32, header.len() as u16 - 1)?;
+    sheet.set_autofit_max_width(MAX_COLUMN_WIDTH);
+    sheet.autofit();
+    Ok(())
+}
+
+/// Makes a valid, unique sheet name from a department name.
+fn sheet_name(department: &str, used_names: &mut HashSet<String>) -> String {
+    let base: String = department
+        .chars()
+        .map(|c| match c {
+            '[' | ']' | ':' | '*' | '?' | '/' | '\\' => '_',
+            c => c,
+        })
+        .collect();
+    let base = base.trim_matches('\'').trim();
+    let base = if base.is_empty() { "unknown" } else { base };
+
+    let mut suffix = 1;
+    loop {
+        let candidate = if suffix == 1 {
+            base.chars().take(MAX_SHEET_NAME_LEN).collect::<String>()
+        } else {
+            let suffix = format!(" ({})", suffix);
+            let len = MAX_SHEET_NAME_LEN - suffix.len();
+            format!("{}{}", base.chars().take(len).collect::<String>(), suffix)
+        };
+        // sheet names are case-insensitive in Excel
+        if used_names.insert(candidate.to_lowercase()) {
+            return candidate;
+        }
+        suffix += 1;
+    }
+}
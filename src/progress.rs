@@ -0,0 +1,162 @@
+use std::io::{IsTerminal, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::task::JoinHandle;
+
+/// How often the progress line is redrawn.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(200);
+
+/// The progress line currently shown on stderr, if any. Log lines clear it
+/// and have it redrawn below them through [`suspend`].
+static LINE: Mutex<Option<String>> = Mutex::new(None);
+
+/// Counters of a running dump, shown as a single line on stderr.
+pub struct Progress {
+    users: AtomicU64,
+    requests: AtomicU64,
+    /// Users the dump is expected to fetch, `0` while unknown.
+    expected: AtomicU64,
+    subtree: Mutex<Option<String>>,
+    started: Instant,
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Self {
+            users: AtomicU64::new(0),
+            requests: AtomicU64::new(0),
+            expected: AtomicU64::new(0),
+            subtree: Mutex::new(None),
+            started: Instant::now(),
+        }
+    }
+}
+
+impl Progress {
+    pub fn add_users(&self, users: usize) {
+        self.users.fetch_add(users as u64, Ordering::Relaxed);
+    }
+
+    pub fn add_request(&self) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Seeds the ETA with the number of users the dump will fetch.
+    pub fn set_expected(&self, users: u64) {
+        self.expected.store(users, Ordering::Relaxed);
+    }
+
+    /// Names the subtree below the root that is currently walked.
+    pub fn set_subtree(&self, name: &str) {
+        *self.subtree.lock().unwrap() = Some(name.to_string());
+    }
+
+    /// Estimated time left, from the rate so far.
+    pub fn eta(&self) -> Option<Duration> {
+        let users = self.users.load(Ordering::Relaxed);
+        let expected = self.expected.load(Ordering::Relaxed);
+        if users == 0 || expected <= users {
+            return None;
+        }
+        let per_user = self.started.elapsed().as_secs_f64() / users as f64;
+        Some(Duration::from_secs_f64(
+            per_user * (expected - users) as f64,
+        ))
+    }
+
+    fn line(&self) -> String {
+        let users = self.users.load(Ordering::Relaxed);
+        let mut line = match self.expected.load(Ordering::Relaxed) {
+            0 => format!("Fetched {} users", users),
+            expected => format!(
+                "Fetched {}/{} users ({}%)",
+                users,
+                expected,
+                (users * 100 / expected).min(100)
+            ),
+        };
+        line.push_str(&format!(
+            ", {} requests",
+            self.requests.load(Ordering::Relaxed)
+        ));
+        if let Some(subtree) = self.subtree.lock().unwrap().as_deref() {
+            line.push_str(&format!(", in {}", subtree));
+        }
+        if let Some(eta) = self.eta() {
+            line.push_str(&format!(", ETA {}", format_duration(eta)));
+        }
+        line
+    }
+
+    /// Redraws the progress line until the returned display is finished.
+    /// Nothing is drawn unless stderr is a terminal.
+    pub fn show(self: &Arc<Self>) -> ProgressDisplay {
+        if !std::io::stderr().is_terminal() {
+            return ProgressDisplay(None);
+        }
+        let progress = self.clone();
+        ProgressDisplay(Some(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(REDRAW_INTERVAL);
+            loop {
+                interval.tick().await;
+                let line = progress.line();
+                let mut shown = LINE.lock().unwrap();
+                let _ = write!(std::io::stderr(), "\r\x1b[2K{}", line);
+                *shown = Some(line);
+            }
+        })))
+    }
+}
+
+/// The task drawing the progress line; the line is cleared once finished
+/// or dropped.
+#[derive(Default)]
+pub struct ProgressDisplay(Option<JoinHandle<()>>);
+
+impl ProgressDisplay {
+    /// Stops drawing and clears the line.
+    pub fn finish(mut self) {
+        self.stop();
+    }
+
+    fn stop(&mut self) {
+        if let Some(task) = self.0.take() {
+            task.abort();
+            if LINE.lock().unwrap().take().is_some() {
+                let _ = write!(std::io::stderr(), "\r\x1b[2K");
+            }
+        }
+    }
+}
+
+impl Drop for ProgressDisplay {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Runs `write`, which writes to stderr, with the progress line cleared
+/// and redraws it below.
+pub(crate) fn suspend<R>(write: impl FnOnce() -> R) -> R {
+    let shown = LINE.lock().unwrap();
+    if shown.is_some() {
+        let _ = write!(std::io::stderr(), "\r\x1b[2K");
+    }
+    let result = write();
+    if let Some(line) = shown.as_deref() {
+        let _ = write!(std::io::stderr(), "{}", line);
+    }
+    result
+}
+
+/// Formats `duration` as e.g. `1h 05m`, `4m 10s` or `12s`.
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m {:02}s", m, s),
+        (h, m, _) => format!("{}h {:02}m", h, m),
+    }
+}
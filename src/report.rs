@@ -0,0 +1,316 @@
+//! Reports on directory objects besides the org, from `--report`. Each row
+//! is a [`User::record`], so reports are written in the flat formats and to
+//! the same destinations as a dump.
+
+use std::fmt;
+
+use chrono::{DateTime, Duration, Utc};
+use clap::ValueEnum;
+use serde_json::{Map, Value};
+
+use crate::graph::GraphClient;
+use crate::output::Column;
+use crate::User;
+
+/// A directory report.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Report {
+    /// Administrative units, with a row per member and scoped role
+    /// assignment.
+    AdminUnits,
+    /// Directory roles, with a row per member and per principal eligible
+    /// for the role in Privileged Identity Management.
+    DirectoryRoles,
+    /// App registrations and service principals, with their owners and a row
+    /// per credential.
+    Apps,
+}
+
+impl Report {
+    /// The columns of the report, by their camelCase names; `id` identifies
+    /// a row.
+    fn column_names(self) -> &'static [&'static str] {
+        match self {
+            Report::AdminUnits => &[
+                "id",
+                "administrativeUnitId",
+                "administrativeUnit",
+                "description",
+                "membershipType",
+                "visibility",
+                "relationship",
+                "role",
+                "objectId",
+                "objectType",
+                "displayName",
+                "userPrincipalName",
+            ],
+            Report::DirectoryRoles => &[
+                "id",
+                "role",
+                "roleTemplateId",
+                "assignment",
+                "principalId",
+                "principalType",
+                "displayName",
+                "userPrincipalName",
+                "directoryScope",
+                "startDateTime",
+                "endDateTime",
+            ],
+            Report::Apps => &[
+                "id",
+                "objectType",
+                "appId",
+                "displayName",
+                "signInAudience",
+                "servicePrincipalType",
+                "accountEnabled",
+                "createdDateTime",
+                "owners",
+                "credentialType",
+                "credentialId",
+                "credentialName",
+                "startDateTime",
+                "endDateTime",
+                "credentialStatus",
+            ],
+        }
+    }
+
+    pub fn columns(self) -> Vec<Column> {
+        self.column_names()
+            .iter()
+            .map(|name| Column::Property(name.to_string()))
+            .collect()
+    }
+
+    /// Fetches the rows of the report.
+    pub async fn fetch(self, graph: &GraphClient) -> anyhow::Result<Vec<User>> {
+        match self {
+            Report::AdminUnits => admin_unit_rows(graph).await,
+            Report::DirectoryRoles => directory_role_rows(graph).await,
+            Report::Apps => app_rows(graph).await,
+        }
+    }
+}
+
+/// A `unit` row per administrative unit, then a `member` row per member and
+/// a `scopedRole` row per role assignment scoped to it.
+async fn admin_unit_rows(graph: &GraphClient) -> anyhow::Result<Vec<User>> {
+    let mut rows = Vec::new();
+    for unit in graph.fetch_administrative_units().await? {
+        let unit_values = [
+            ("administrativeUnitId", Some(unit.id.as_str())),
+            ("administrativeUnit", Some(unit.display_name.as_str())),
+            ("description", unit.description.as_deref()),
+            ("membershipType", unit.membership_type.as_deref()),
+            ("visibility", unit.visibility.as_deref()),
+        ];
+        // units without members or roles are listed too
+        rows.push(row(
+            &unit.id,
+            &unit.display_name,
+            unit_values
+                .into_iter()
+                .chain([("relationship", Some("unit"))]),
+        ));
+        for member in &unit.members {
+            let name = member.name();
+            rows.push(row(
+                &format!("{}/{}", unit.id, member.id),
+                name,
+                unit_values.into_iter().chain([
+                    ("relationship", Some("member")),
+                    ("objectId", Some(member.id.as_str())),
+                    ("objectType", member.kind()),
+                    ("userPrincipalName", member.user_principal_name.as_deref()),
+                ]),
+            ));
+        }
+        for scoped in &unit.scoped_roles {
+            let member = &scoped.member;
+            let name = member.name();
+            rows.push(row(
+                &scoped.id,
+                name,
+                unit_values.into_iter().chain([
+                    ("relationship", Some("scopedRole")),
+                    ("role", Some(scoped.role.as_str())),
+                    ("objectId", Some(member.id.as_str())),
+                ]),
+            ));
+        }
+    }
+    Ok(rows)
+}
+
+/// A row per member of each directory role, then one per role eligibility.
+async fn directory_role_rows(graph: &GraphClient) -> anyhow::Result<Vec<User>> {
+    let mut rows = Vec::new();
+    for role in graph.fetch_directory_roles().await? {
+        for member in &role.members {
+            rows.push(row(
+                &format!("{}/{}", role.id, member.id),
+                member.name(),
+                [
+                    ("role", Some(role.display_name.as_str())),
+                    ("roleTemplateId", role.role_template_id.as_deref()),
+                    ("assignment", Some("active")),
+                    ("principalId", Some(member.id.as_str())),
+                    ("principalType", member.kind()),
+                    ("userPrincipalName", member.user_principal_name.as_deref()),
+                    ("directoryScope", Some("/")),
+                ],
+            ));
+        }
+    }
+    let active = rows.len();
+
+    // without a P2 license there are none, and the request fails
+    match graph.fetch_role_eligibilities().await {
+        Ok(eligibilities) => {
+            for eligibility in &eligibilities {
+                let principal = &eligibility.principal;
+                let schedule = eligibility.schedule_info.as_ref();
+                rows.push(row(
+                    &eligibility.id,
+                    principal.name(),
+                    [
+                        (
+                            "role",
+                            Some(eligibility.role_definition.display_name.as_str()),
+                        ),
+                        (
+                            "roleTemplateId",
+                            eligibility.role_definition.template_id.as_deref(),
+                        ),
+                        ("assignment", Some("eligible")),
+                        ("principalId", Some(principal.id.as_str())),
+                        ("principalType", principal.kind()),
+                        (
+                            "userPrincipalName",
+                            principal.user_principal_name.as_deref(),
+                        ),
+                        ("directoryScope", eligibility.directory_scope_id.as_deref()),
+                        (
+                            "startDateTime",
+                            schedule.and_then(|s| s.start_date_time.as_deref()),
+                        ),
+                        (
+                            "endDateTime",
+                            schedule
+                                .and_then(|s| s.expiration.as_ref())
+                                .and_then(|e| e.end_date_time.as_deref()),
+                        ),
+                    ],
+                ));
+            }
+        }
+        Err(e) => tracing::warn!("Leaving out eligible role assignments; {}", e),
+    }
+    tracing::info!(
+        "{} active and {} eligible role assignments",
+        active,
+        rows.len() - active
+    );
+    Ok(rows)
+}
+
+/// A row per credential of each app, or one for an app without any.
+async fn app_rows(graph: &GraphClient) -> anyhow::Result<Vec<User>> {
+    let now = Utc::now();
+    let mut rows = Vec::new();
+    let mut expiring = 0;
+    for app in graph.fetch_apps().await? {
+        let owners = app
+            .owners
+            .iter()
+            .map(|owner| owner.user_principal_name.as_deref().unwrap_or(owner.name()))
+            .collect::<Vec<_>>()
+            .join("; ");
+        let account_enabled = app.account_enabled.map(|enabled| enabled.to_string());
+        let app_values = [
+            ("objectType", Some(app.kind.as_str())),
+            ("appId", app.app_id.as_deref()),
+            ("signInAudience", app.sign_in_audience.as_deref()),
+            (
+                "servicePrincipalType",
+                app.service_principal_type.as_deref(),
+            ),
+            ("accountEnabled", account_enabled.as_deref()),
+            ("createdDateTime", app.created_date_time.as_deref()),
+            ("owners", Some(owners.as_str())),
+        ];
+        if app.credentials.is_empty() {
+            rows.push(row(&app.id, &app.display_name, app_values));
+        }
+        for credential in &app.credentials {
+            let status = credential_status(credential.end_date_time.as_deref(), now);
+            if status != "valid" {
+                expiring += 1;
+            }
+            let key_id = credential.key_id.as_deref().unwrap_or_default();
+            rows.push(row(
+                &format!("{}/{}", app.id, key_id),
+                &app.display_name,
+                app_values.into_iter().chain([
+                    ("credentialType", Some(credential.credential_type.as_str())),
+                    ("credentialId", credential.key_id.as_deref()),
+                    ("credentialName", credential.display_name.as_deref()),
+                    ("startDateTime", credential.start_date_time.as_deref()),
+                    ("endDateTime", credential.end_date_time.as_deref()),
+                    ("credentialStatus", Some(status)),
+                ]),
+            ));
+        }
+    }
+    if expiring > 0 {
+        tracing::warn!(
+            "{} app credentials expired or expire within {} days",
+            expiring,
+            EXPIRY_WARNING_DAYS
+        );
+    }
+    Ok(rows)
+}
+
+/// Days before its end a credential is reported as `expiring`.
+const EXPIRY_WARNING_DAYS: i64 = 30;
+
+/// `expired`, `expiring` or `valid`, by when a credential ends.
+fn credential_status(end: Option<&str>, now: DateTime<Utc>) -> &'static str {
+    let Some(end) = end.and_then(|end| DateTime::parse_from_rfc3339(end).ok()) else {
+        return "valid";
+    };
+    if end < now {
+        "expired"
+    } else if end < now + Duration::days(EXPIRY_WARNING_DAYS) {
+        "expiring"
+    } else {
+        "valid"
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Report::AdminUnits => "administrative units",
+            Report::DirectoryRoles => "directory roles",
+            Report::Apps => "apps",
+        })
+    }
+}
+
+/// A report row with `values` by column name; missing columns are null.
+fn row<'a>(
+    id: &str,
+    display_name: &str,
+    values: impl IntoIterator<Item = (&'a str, Option<&'a str>)>,
+) -> User {
+    let properties: Map<String, Value> = values
+        .into_iter()
+        .filter_map(|(name, value)| Some((name.to_string(), Value::from(value?))))
+        .collect();
+    User::record(id, display_name, properties)
+}
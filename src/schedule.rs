@@ -0,0 +1,359 @@
+//! Cron-like schedules for `--daemon`.
+
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Datelike, Duration, LocalResult, NaiveDate, TimeZone, Timelike};
+
+const MONTHS: [&str; 12] = [
+    "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+];
+
+const WEEKDAYS: [&str; 7] = ["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+
+/// Days searched for the next run before giving up, e.g. on `0 0 30 2 *`.
+const SEARCH_DAYS: i64 = 366 * 5;
+
+/// A five field cron expression: minute, hour, day of month, month and day
+/// of week, e.g. `0 6 * * MON`. Fields take `*`, values, ranges (`1-5`),
+/// steps (`*/15`, `8-18/2`) and lists of those (`MON,WED,FRI`); months and
+/// days of the week also take their three letter names, and Sunday is both
+/// 0 and 7. As in cron, a day matches either field when both the day of
+/// month and day of week are restricted. `@hourly`, `@daily`, `@weekly`
+/// and `@monthly` stand for the usual expressions.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Schedule {
+    expression: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether the day of month and day of week fields don't start with
+    /// `*`, as in cron; `*/2` is not a restriction.
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+impl FromStr for Schedule {
+    type Err = String;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        let expanded = match expression.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            expression => expression,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(format!(
+                "{:?} is not a cron expression like \"0 6 * * MON\" (minute hour day month weekday)",
+                expression
+            ));
+        };
+        let weekdays_bits = field(weekdays, 0, 7, &WEEKDAYS, "day of week")?;
+        Ok(Self {
+            expression: expression.trim().to_string(),
+            minutes: field(minutes, 0, 59, &[], "minute")?,
+            hours: field(hours, 0, 23, &[], "hour")?,
+            days: field(days, 1, 31, &[], "day of month")?,
+            months: field(months, 1, 12, &MONTHS, "month")?,
+            // 7 is Sunday as well
+            weekdays: (weekdays_bits | (weekdays_bits >> 7)) & 0x7f,
+            days_restricted: !days.starts_with('*'),
+            weekdays_restricted: !weekdays.starts_with('*'),
+        })
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.expression)
+    }
+}
+
+/// The values of one field as bits, e.g. bit 6 for hour 6.
+fn field(spec: &str, min: u32, max: u32, names: &[&str], what: &str) -> Result<u64, String> {
+    let invalid = || format!("invalid {} {:?}", what, spec);
+    let value = |s: &str| -> Result<u32, String> {
+        let named = names
+            .iter()
+            .position(|name| name.eq_ignore_ascii_case(s))
+            // month names count from 1
+            .map(|i| i as u32 + min);
+        let value = named.map_or_else(|| s.parse().map_err(|_| invalid()), Ok)?;
+        if value < min || value > max {
+            return Err(invalid());
+        }
+        Ok(value)
+    };
+
+    let mut bits = 0;
+    for part in spec.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().map_err(|_| invalid())?),
+            None => (part, 1),
+        };
+        if step == 0 {
+            return Err(invalid());
+        }
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (value(start)?, value(end)?),
+            // `5/15` runs from 5 to the end
+            None if part.contains('/') => (value(range)?, max),
+            None => {
+                let value = value(range)?;
+                (value, value)
+            }
+        };
+        if start > end {
+            return Err(invalid());
+        }
+        for value in (start..=end).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+impl Schedule {
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        let day = self.days & (1 << date.day()) != 0;
+        let weekday = self.weekdays & (1 << date.weekday().num_days_from_sunday()) != 0;
+        if self.months & (1 << date.month()) == 0 {
+            return false;
+        }
+        match (self.days_restricted, self.weekdays_restricted) {
+            (true, true) => day || weekday,
+            _ => day && weekday,
+        }
+    }
+
+    /// The first time after `after` the schedule runs at, in the time zone
+    /// of `after`. Times skipped by a daylight saving change are left out,
+    /// and times repeated by one run once, at their first pass unless
+    /// `after` is past it.
+    pub fn next_after<Tz: TimeZone>(&self, after: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+        let zone = after.timezone();
+        let start = after.naive_local() + Duration::minutes(1);
+        let start = start.with_second(0)?.with_nanosecond(0)?;
+        for offset in 0..SEARCH_DAYS {
+            let date = start.date() + Duration::days(offset);
+            if !self.matches_day(date) {
+                continue;
+            }
+            for hour in (0..24).filter(|hour| self.hours & (1 << hour) != 0) {
+                for minute in (0..60).filter(|minute| self.minutes & (1 << minute) != 0) {
+                    let time = date.and_hms_opt(hour, minute, 0)?;
+                    if time < start {
+                        continue;
+                    }
+                    match zone.from_local_datetime(&time) {
+                        LocalResult::Single(time) => return Some(time),
+                        LocalResult::Ambiguous(earliest, latest) => {
+                            if earliest > *after {
+                                return Some(earliest);
+                            }
+                            if latest > *after {
+                                return Some(latest);
+                            }
+                        }
+                        LocalResult::None => {}
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{FixedOffset, NaiveDateTime, Utc};
+
+    use super::*;
+
+    fn schedule(expression: &str) -> Schedule {
+        expression.parse().unwrap()
+    }
+
+    fn utc(at: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(at)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn next(expression: &str, after: &str) -> Option<String> {
+        schedule(expression)
+            .next_after(&utc(after))
+            .map(|at| at.to_rfc3339())
+    }
+
+    /// Central European Time of 2024: CEST from 31 March 01:00 UTC to
+    /// 27 October 01:00 UTC.
+    #[derive(Clone, Copy, Debug)]
+    struct Cet;
+
+    impl Cet {
+        fn winter() -> FixedOffset {
+            FixedOffset::east_opt(3600).unwrap()
+        }
+
+        fn summer() -> FixedOffset {
+            FixedOffset::east_opt(7200).unwrap()
+        }
+    }
+
+    impl TimeZone for Cet {
+        type Offset = FixedOffset;
+
+        fn from_offset(_: &FixedOffset) -> Self {
+            Cet
+        }
+
+        fn offset_from_local_date(&self, local: &NaiveDate) -> LocalResult<FixedOffset> {
+            self.offset_from_local_datetime(&local.and_hms_opt(0, 0, 0).unwrap())
+        }
+
+        fn offset_from_local_datetime(&self, local: &NaiveDateTime) -> LocalResult<FixedOffset> {
+            // the offsets that map back to themselves, earliest instant first
+            let offsets: Vec<FixedOffset> = [Self::summer(), Self::winter()]
+                .into_iter()
+                .filter(|offset| {
+                    let utc = *local - Duration::seconds(offset.local_minus_utc().into());
+                    self.offset_from_utc_datetime(&utc) == *offset
+                })
+                .collect();
+            match offsets[..] {
+                [offset] => LocalResult::Single(offset),
+                [earliest, latest] => LocalResult::Ambiguous(earliest, latest),
+                _ => LocalResult::None,
+            }
+        }
+
+        fn offset_from_utc_date(&self, utc: &NaiveDate) -> FixedOffset {
+            self.offset_from_utc_datetime(&utc.and_hms_opt(0, 0, 0).unwrap())
+        }
+
+        fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> FixedOffset {
+            let at = |s: &str| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap();
+            if (at("2024-03-31 01:00")..at("2024-10-27 01:00")).contains(utc) {
+                Self::summer()
+            } else {
+                Self::winter()
+            }
+        }
+    }
+
+    fn next_cet(expression: &str, after: &str) -> Option<String> {
+        let after = utc(after).with_timezone(&Cet);
+        schedule(expression)
+            .next_after(&after)
+            .map(|at| at.to_rfc3339())
+    }
+
+    #[test]
+    fn restricted_day_of_month_or_day_of_week() {
+        // the 13th or any Friday
+        let expression = "0 0 13 * FRI";
+        assert_eq!(
+            next(expression, "2024-01-01T00:00:00Z").as_deref(),
+            Some("2024-01-05T00:00:00+00:00")
+        );
+        assert_eq!(
+            next(expression, "2024-01-12T00:00:00Z").as_deref(),
+            Some("2024-01-13T00:00:00+00:00")
+        );
+        // `*/2` doesn't restrict the days, so both fields must match: odd
+        // days that are Mondays
+        assert_eq!(
+            next("0 0 */2 * MON", "2024-01-02T00:00:00Z").as_deref(),
+            Some("2024-01-15T00:00:00+00:00")
+        );
+        // a lone restricted field
+        assert_eq!(
+            next("0 0 * * MON", "2024-01-02T00:00:00Z").as_deref(),
+            Some("2024-01-08T00:00:00+00:00")
+        );
+    }
+
+    #[test]
+    fn seven_is_sunday() {
+        assert_eq!(
+            schedule("0 0 * * 7").weekdays,
+            schedule("0 0 * * SUN").weekdays
+        );
+        assert_eq!(
+            schedule("0 0 * * 0").weekdays,
+            schedule("0 0 * * SUN").weekdays
+        );
+        assert_eq!(
+            schedule("0 0 * * 5-7").weekdays,
+            schedule("0 0 * * FRI,SAT,SUN").weekdays
+        );
+        assert_eq!(
+            next("0 0 * * 7", "2024-01-01T00:00:00Z").as_deref(),
+            Some("2024-01-07T00:00:00+00:00")
+        );
+    }
+
+    #[test]
+    fn impossible_dates_never_run() {
+        assert_eq!(next("0 0 30 2 *", "2024-01-01T00:00:00Z"), None);
+        assert_eq!(next("0 0 31 4,6,9,11 *", "2024-01-01T00:00:00Z"), None);
+        // leap days come around
+        assert_eq!(
+            next("0 0 29 2 *", "2024-03-01T00:00:00Z").as_deref(),
+            Some("2028-02-29T00:00:00+00:00")
+        );
+    }
+
+    #[test]
+    fn invalid_expressions() {
+        for expression in [
+            "",
+            "0 6 * *",
+            "60 * * * *",
+            "0 0 0 * *",
+            "0 0 * * 8",
+            "*/0 * * * *",
+            "5-1 * * * *",
+        ] {
+            assert!(expression.parse::<Schedule>().is_err(), "{:?}", expression);
+        }
+    }
+
+    #[test]
+    fn times_skipped_by_daylight_saving_are_left_out() {
+        // 02:00 to 03:00 doesn't exist on 31 March
+        assert_eq!(
+            next_cet("30 2 * * *", "2024-03-30T12:00:00Z").as_deref(),
+            Some("2024-04-01T02:30:00+02:00")
+        );
+        assert_eq!(
+            next_cet("30 3 * * *", "2024-03-30T12:00:00Z").as_deref(),
+            Some("2024-03-31T03:30:00+02:00")
+        );
+    }
+
+    #[test]
+    fn times_repeated_by_daylight_saving_run_once() {
+        // 02:00 to 03:00 happens twice on 27 October
+        assert_eq!(
+            next_cet("30 2 * * *", "2024-10-26T12:00:00Z").as_deref(),
+            Some("2024-10-27T02:30:00+02:00")
+        );
+        assert_eq!(
+            next_cet("30 2 * * *", "2024-10-27T00:30:00Z").as_deref(),
+            Some("2024-10-28T02:30:00+01:00")
+        );
+        // past the first pass of 02:50, during the second pass of 02:45
+        assert_eq!(
+            next_cet("50 2 * * *", "2024-10-27T01:45:00Z").as_deref(),
+            Some("2024-10-27T02:50:00+01:00")
+        );
+    }
+}
@@ -0,0 +1,323 @@
+//! A read-only REST mirror of the latest dump, for `serve`.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, Utc};
+use reqwest::Url;
+use serde_json::{json, Map, Value};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::diff::read_records;
+use crate::graph::lists::percent_decode;
+use crate::snapshot::SnapshotStore;
+use crate::webhook::{read_request, respond_with};
+
+/// Most users `/search` answers with.
+const MAX_SEARCH_RESULTS: usize = 50;
+
+/// Columns `/search` looks in.
+const SEARCH_COLUMNS: [&str; 5] = [
+    "display_name",
+    "mail",
+    "user_principal_name",
+    "employee_id",
+    "job_title",
+];
+
+/// Where the dump served comes from.
+pub enum DumpSource {
+    /// A dump file, read again whenever it changes.
+    File(PathBuf),
+    /// The latest snapshot of a `--snapshot-dir`.
+    Snapshots(SnapshotStore),
+}
+
+impl DumpSource {
+    /// The file holding the latest dump and when it was last written.
+    fn latest(&self) -> anyhow::Result<(PathBuf, SystemTime)> {
+        let path = match self {
+            DumpSource::File(path) => path.clone(),
+            DumpSource::Snapshots(store) => store.latest()?.users_path(),
+        };
+        let modified = std::fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .map_err(|e| anyhow::anyhow!("reading {}; {}", path.display(), e))?;
+        Ok((path, modified))
+    }
+}
+
+/// The users of one dump, indexed for the API.
+struct Mirror {
+    path: PathBuf,
+    modified: SystemTime,
+    loaded: DateTime<Utc>,
+    /// The users as JSON objects, by display name.
+    users: Vec<Map<String, Value>>,
+    /// Indexes into `users` by id and by lowercase mail and UPN.
+    by_key: HashMap<String, usize>,
+    /// Indexes of the direct reports of each manager id.
+    reports: HashMap<String, Vec<usize>>,
+}
+
+impl Mirror {
+    fn load(path: PathBuf, modified: SystemTime) -> anyhow::Result<Self> {
+        // a user written twice is as last written, as in `Dump::load`
+        let mut by_id = HashMap::new();
+        for record in read_records(&path)? {
+            let Some(Some(id)) = record.get("id").cloned() else {
+                continue;
+            };
+            // users deleted in a `--delta` dump
+            if record.get("change").cloned().flatten().as_deref() == Some("delete") {
+                by_id.remove(&id);
+                continue;
+            }
+            let user: Map<String, Value> = record
+                .into_iter()
+                .map(|(name, value)| (name, value.map_or(Value::Null, Value::String)))
+                .collect();
+            by_id.insert(id, user);
+        }
+        let mut users: Vec<Map<String, Value>> = by_id.into_values().collect();
+        let text = |user: &Map<String, Value>, name: &str| -> String {
+            user.get(name)
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string()
+        };
+        users.sort_by_cached_key(|user| (text(user, "display_name"), text(user, "id")));
+
+        let mut by_key = HashMap::new();
+        let mut reports: HashMap<String, Vec<usize>> = HashMap::new();
+        for (i, user) in users.iter().enumerate() {
+            for name in ["mail", "user_principal_name"] {
+                let key = text(user, name).to_lowercase();
+                if !key.is_empty() {
+                    by_key.entry(key).or_insert(i);
+                }
+            }
+            let manager_id = text(user, "manager_id");
+            if !manager_id.is_empty() {
+                reports.entry(manager_id).or_default().push(i);
+            }
+        }
+        // ids win over a mail or UPN that happens to look the same
+        for (i, user) in users.iter().enumerate() {
+            by_key.insert(text(user, "id"), i);
+        }
+        Ok(Self {
+            path,
+            modified,
+            loaded: Utc::now(),
+            users,
+            by_key,
+            reports,
+        })
+    }
+
+    fn find(&self, key: &str) -> Option<usize> {
+        self.by_key
+            .get(key)
+            .or_else(|| self.by_key.get(&key.to_lowercase()))
+            .copied()
+    }
+
+    /// The reports of `manager`, and with `all` theirs too, breadth first.
+    fn reports(&self, manager: usize, all: bool) -> Vec<&Map<String, Value>> {
+        let mut found = Vec::new();
+        let mut seen = vec![false; self.users.len()];
+        seen[manager] = true;
+        let mut pending = vec![manager];
+        while !pending.is_empty() {
+            let mut next = Vec::new();
+            for manager in pending {
+                let id = self.users[manager]
+                    .get("id")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default();
+                for &report in self.reports.get(id).into_iter().flatten() {
+                    // management cycles end here
+                    if !std::mem::replace(&mut seen[report], true) {
+                        found.push(&self.users[report]);
+                        next.push(report);
+                    }
+                }
+            }
+            if !all {
+                break;
+            }
+            pending = next;
+        }
+        found
+    }
+
+    fn search(&self, query: &str) -> Vec<&Map<String, Value>> {
+        let query = query.to_lowercase();
+        self.users
+            .iter()
+            .filter(|user| {
+                SEARCH_COLUMNS.iter().any(|name| {
+                    user.get(*name)
+                        .and_then(Value::as_str)
+                        .is_some_and(|value| value.to_lowercase().contains(&query))
+                })
+            })
+            .take(MAX_SEARCH_RESULTS)
+            .collect()
+    }
+
+    /// The status and JSON body answering `GET url`.
+    fn answer(&self, url: &Url) -> (&'static str, Value) {
+        let query = |name: &str| {
+            url.query_pairs()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.into_owned())
+        };
+        let segments: Vec<String> = url
+            .path_segments()
+            .into_iter()
+            .flatten()
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| String::from_utf8_lossy(&percent_decode(segment)).into_owned())
+            .collect();
+        let not_found = |what: &str| ("404 Not Found", json!({ "error": what }));
+        match segments.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+            ["users"] => ("200 OK", json!({ "value": self.users })),
+            ["users", key] => match self.find(key) {
+                Some(i) => ("200 OK", Value::Object(self.users[i].clone())),
+                None => not_found("no such user"),
+            },
+            ["users", key, "reports"] => match self.find(key) {
+                Some(i) => {
+                    let all = matches!(query("all").as_deref(), Some("true" | "1"));
+                    ("200 OK", json!({ "value": self.reports(i, all) }))
+                }
+                None => not_found("no such user"),
+            },
+            ["search"] => match query("q").filter(|q| !q.trim().is_empty()) {
+                Some(q) => ("200 OK", json!({ "value": self.search(q.trim()) })),
+                None => (
+                    "400 Bad Request",
+                    json!({ "error": "search for something with ?q=" }),
+                ),
+            },
+            ["healthz"] => (
+                "200 OK",
+                json!({
+                    "dump": self.path.display().to_string(),
+                    "loaded": self.loaded,
+                    "users": self.users.len(),
+                }),
+            ),
+            _ => not_found("no such endpoint"),
+        }
+    }
+}
+
+/// Serves the dump of `source` on `listen`, reading it again every
+/// `refresh` once it changed, until the returned future is dropped:
+///
+/// - `GET /users`: every user
+/// - `GET /users/{id, mail or UPN}`: one user
+/// - `GET /users/{id, mail or UPN}/reports`: their direct reports, or with
+///   `?all=true` everyone below them
+/// - `GET /search?q=`: users whose name, mail, UPN, employee id or job title
+///   contains `q`
+/// - `GET /healthz`: the dump served and when it was read
+pub async fn serve(
+    source: DumpSource,
+    listen: SocketAddr,
+    refresh: Duration,
+) -> anyhow::Result<()> {
+    let (path, modified) = source.latest()?;
+    let mirror = tokio::task::spawn_blocking(move || Mirror::load(path, modified)).await??;
+    tracing::info!(
+        "Serving {} users of {} on http://{}",
+        mirror.users.len(),
+        mirror.path.display(),
+        listen
+    );
+    let mirror = Arc::new(RwLock::new(Arc::new(mirror)));
+    let listener = TcpListener::bind(listen)
+        .await
+        .map_err(|e| anyhow::anyhow!("listening on {}; {}", listen, e))?;
+
+    let accept = async {
+        loop {
+            let Ok((stream, _)) = listener.accept().await else {
+                continue;
+            };
+            let mirror = current(&mirror);
+            tokio::spawn(async move {
+                if let Err(e) = handle(stream, &mirror).await {
+                    tracing::debug!("Handling a request failed; {}", e);
+                }
+            });
+        }
+    };
+    let reload = async {
+        let mut interval = tokio::time::interval(refresh);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            let loaded = current(&mirror);
+            let (path, modified) = match source.latest() {
+                Ok(latest) => latest,
+                Err(e) => {
+                    tracing::warn!("Keeping the dump served; {}", e);
+                    continue;
+                }
+            };
+            if path == loaded.path && modified == loaded.modified {
+                continue;
+            }
+            match tokio::task::spawn_blocking(move || Mirror::load(path, modified)).await? {
+                Ok(reloaded) => {
+                    tracing::info!(
+                        "Serving {} users of {}",
+                        reloaded.users.len(),
+                        reloaded.path.display()
+                    );
+                    *mirror.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(reloaded);
+                }
+                // e.g. a dump still being written without an atomic rename
+                Err(e) => tracing::warn!("Keeping the dump served; {}", e),
+            }
+        }
+    };
+    tokio::select! {
+        _ = accept => Ok(()),
+        reloaded = reload => reloaded,
+    }
+}
+
+fn current(mirror: &RwLock<Arc<Mirror>>) -> Arc<Mirror> {
+    mirror.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+async fn handle(mut stream: TcpStream, mirror: &Mirror) -> anyhow::Result<()> {
+    let (head, _) = read_request(&mut stream).await?;
+    // e.g. "GET /users/42/reports?all=true HTTP/1.1"
+    let mut request_line = head.lines().next().unwrap_or_default().split(' ');
+    let method = request_line.next().unwrap_or_default();
+    let path = request_line.next().unwrap_or("/");
+    let (status, body) = match Url::parse(&format!("http://localhost{}", path)) {
+        Ok(_) if method != "GET" => (
+            "405 Method Not Allowed",
+            json!({ "error": "only GET is supported" }),
+        ),
+        Ok(url) => mirror.answer(&url),
+        Err(_) => ("400 Bad Request", json!({ "error": "invalid path" })),
+    };
+    respond_with(
+        &mut stream,
+        status,
+        "application/json",
+        &serde_json::to_string(&body)?,
+    )
+    .await
+}
@@ -0,0 +1,151 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter};
+use std::path::PathBuf;
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+
+use crate::model::User;
+use crate::output::json::JsonLinesSink;
+use crate::output::{Column, OutputSink};
+
+/// Name of a snapshot directory: the UTC time the run started.
+const NAME_FORMAT: &str = "%Y-%m-%dT%H-%M-%SZ";
+
+/// File holding the users of a snapshot, one JSON object per line.
+const USERS_FILE: &str = "users.jsonl";
+
+/// One recorded run in a [`SnapshotStore`].
+pub struct Snapshot {
+    pub taken: DateTime<Utc>,
+    pub path: PathBuf,
+}
+
+impl Snapshot {
+    pub fn name(&self) -> String {
+        self.taken.format(NAME_FORMAT).to_string()
+    }
+
+    /// The dump of the run, readable by [`Dump::load`](crate::diff::Dump::load).
+    pub fn users_path(&self) -> PathBuf {
+        self.path.join(USERS_FILE)
+    }
+
+    pub fn count_users(&self) -> anyhow::Result<usize> {
+        let file = File::open(self.users_path())
+            .map_err(|e| anyhow::anyhow!("opening snapshot {}; {}", self.name(), e))?;
+        Ok(BufReader::new(file).lines().count())
+    }
+}
+
+/// A directory with one timestamped subdirectory per run.
+pub struct SnapshotStore {
+    dir: PathBuf,
+}
+
+impl SnapshotStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// The completed snapshots, oldest first.
+    pub fn list(&self) -> anyhow::Result<Vec<Snapshot>> {
+        let entries = std::fs::read_dir(&self.dir)
+            .map_err(|e| anyhow::anyhow!("reading snapshots {}; {}", self.dir.display(), e))?;
+        let mut snapshots = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            // runs still in progress have a hidden name and are skipped here
+            let taken = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| NaiveDateTime::parse_from_str(name, NAME_FORMAT).ok());
+            if let Some(taken) = taken {
+                snapshots.push(Snapshot {
+                    taken: taken.and_utc(),
+                    path,
+                });
+            }
+        }
+        snapshots.sort_by_key(|snapshot| snapshot.taken);
+        Ok(snapshots)
+    }
+
+    /// The latest snapshot whose name starts with `date`, e.g. `2024-05-06`
+    /// or `2024-05-06T09`.
+    pub fn find(&self, date: &str) -> anyhow::Result<Snapshot> {
+        self.list()?
+            .into_iter()
+            .rev()
+            .find(|snapshot| snapshot.name().starts_with(date))
+            .ok_or_else(|| anyhow::anyhow!("no snapshot taken at {}", date))
+    }
+
+    /// The latest snapshot.
+    pub fn latest(&self) -> anyhow::Result<Snapshot> {
+        self.list()?
+            .pop()
+            .ok_or_else(|| anyhow::anyhow!("{} holds no snapshots", self.dir.display()))
+    }
+
+    /// The two latest snapshots, older first.
+    pub fn latest_pair(&self) -> anyhow::Result<(Snapshot, Snapshot)> {
+        let mut snapshots = self.list()?;
+        match (snapshots.pop(), snapshots.pop()) {
+            (Some(new), Some(old)) => Ok((old, new)),
+            _ => anyhow::bail!("{} holds fewer than two snapshots", self.dir.display()),
+        }
+    }
+
+    /// Starts recording a snapshot of the current run with `columns`,
+    /// alongside `output`. It is only listed once the sink is finished.
+    pub fn create(
+        &self,
+        output: Box<dyn OutputSink + Send>,
+        columns: Vec<Column>,
+    ) -> anyhow::Result<SnapshotSink> {
+        let name = Utc::now().format(NAME_FORMAT).to_string();
+        let path = self.dir.join(&name);
+        if path.exists() {
+            anyhow::bail!("snapshot {} already exists", path.display())
+        }
+        let partial = self.dir.join(format!(".{}.partial", name));
+        std::fs::create_dir_all(&partial)
+            .map_err(|e| anyhow::anyhow!("creating snapshot {}; {}", partial.display(), e))?;
+        let file = File::create(partial.join(USERS_FILE))
+            .map_err(|e| anyhow::anyhow!("creating snapshot {}; {}", partial.display(), e))?;
+        Ok(SnapshotSink {
+            output,
+            users: JsonLinesSink::new(BufWriter::new(file), columns),
+            partial,
+            path,
+        })
+    }
+}
+
+/// Writes every user to the output sink and records them as a snapshot.
+pub struct SnapshotSink {
+    output: Box<dyn OutputSink + Send>,
+    users: JsonLinesSink<BufWriter<File>>,
+    partial: PathBuf,
+    path: PathBuf,
+}
+
+impl OutputSink for SnapshotSink {
+    fn write_user(&mut self, user: &User, manager: Option<&User>) -> anyhow::Result<()> {
+        self.output.write_user(user, manager)?;
+        self.users.write_user(user, manager)
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.output.flush()
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.output.finish()?;
+        self.users.finish()?;
+        std::fs::rename(&self.partial, &self.path)
+            .map_err(|e| anyhow::anyhow!("saving snapshot {}; {}", self.path.display(), e))?;
+        tracing::info!("Saved snapshot {}", self.path.display());
+        Ok(())
+    }
+}
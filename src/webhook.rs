@@ -0,0 +1,224 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use chrono::Utc;
+use rand::distributions::{Alphanumeric, DistString};
+use reqwest::Url;
+use serde::Deserialize;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+
+use crate::graph::subscription::Subscription;
+use crate::GraphClient;
+
+/// How long a subscription is requested for; Graph allows just under 30
+/// days for users.
+const SUBSCRIPTION_LIFETIME: Duration = Duration::from_secs(2 * 24 * 60 * 60);
+
+/// Subscriptions are renewed once less than this is left.
+const RENEW_BEFORE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Notifications arriving this soon after the first one are handled
+/// together, since Graph sends bursts of them.
+const DEBOUNCE: Duration = Duration::from_secs(10);
+
+/// Largest notification request accepted.
+const MAX_REQUEST_SIZE: usize = 1024 * 1024;
+
+#[derive(Deserialize)]
+struct Notifications {
+    value: Vec<Notification>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Notification {
+    client_state: Option<String>,
+}
+
+/// Change notifications for users, received by a small webhook listener.
+///
+/// Graph must be able to reach the listener at the public notification URL,
+/// e.g. through a reverse proxy or tunnel.
+pub struct ChangeNotifications {
+    graph: GraphClient,
+    subscription: Subscription,
+    received: mpsc::UnboundedReceiver<()>,
+}
+
+impl ChangeNotifications {
+    /// Starts listening on `listen` and subscribes `notification_url`,
+    /// which must forward to it, to changes of any user.
+    pub async fn start(
+        graph: &GraphClient,
+        listen: SocketAddr,
+        notification_url: &str,
+    ) -> anyhow::Result<Self> {
+        let listener = TcpListener::bind(listen)
+            .await
+            .map_err(|e| anyhow::anyhow!("listening on {}; {}", listen, e))?;
+        let client_state = Alphanumeric.sample_string(&mut rand::thread_rng(), 32);
+        let (tx, received) = mpsc::unbounded_channel();
+        tokio::spawn(serve(listener, client_state.clone(), tx));
+
+        let subscription = graph
+            .create_users_subscription(notification_url, &client_state, expiration())
+            .await?;
+        tracing::info!(
+            "Subscribed to user changes until {}",
+            subscription.expiration_date_time
+        );
+        Ok(Self {
+            graph: graph.clone(),
+            subscription,
+            received,
+        })
+    }
+
+    /// Waits until users changed, renewing the subscription as needed.
+    pub async fn changed(&mut self) -> anyhow::Result<()> {
+        loop {
+            let left = (self.subscription.expiration_date_time - Utc::now())
+                .to_std()
+                .unwrap_or_default();
+            tokio::select! {
+                received = self.received.recv() => {
+                    if received.is_none() {
+                        anyhow::bail!("the notification listener stopped")
+                    }
+                    tokio::time::sleep(DEBOUNCE).await;
+                    while self.received.try_recv().is_ok() {}
+                    return Ok(());
+                }
+                _ = tokio::time::sleep(left.saturating_sub(RENEW_BEFORE)) => {
+                    self.subscription = self
+                        .graph
+                        .renew_subscription(&self.subscription.id, expiration())
+                        .await?;
+                }
+            }
+        }
+    }
+
+    /// Deletes the subscription.
+    pub async fn stop(self) -> anyhow::Result<()> {
+        self.graph.delete_subscription(&self.subscription.id).await
+    }
+}
+
+fn expiration() -> chrono::DateTime<Utc> {
+    Utc::now() + chrono::Duration::from_std(SUBSCRIPTION_LIFETIME).unwrap_or_default()
+}
+
+/// Answers Graph's validation requests and reports every notification with
+/// the right client state on `tx`.
+async fn serve(listener: TcpListener, client_state: String, tx: mpsc::UnboundedSender<()>) {
+    while !tx.is_closed() {
+        let Ok((stream, _)) = listener.accept().await else {
+            continue;
+        };
+        let (client_state, tx) = (client_state.clone(), tx.clone());
+        tokio::spawn(async move {
+            if let Err(e) = handle(stream, &client_state, &tx).await {
+                tracing::warn!("Handling a notification request failed; {}", e);
+            }
+        });
+    }
+}
+
+async fn handle(
+    mut stream: TcpStream,
+    client_state: &str,
+    tx: &mpsc::UnboundedSender<()>,
+) -> anyhow::Result<()> {
+    let (head, body) = read_request(&mut stream).await?;
+
+    // e.g. "POST /notify?validationToken=... HTTP/1.1"
+    let path = head
+        .lines()
+        .next()
+        .and_then(|l| l.split(' ').nth(1))
+        .unwrap_or("/");
+    let url = Url::parse(&format!("http://localhost{}", path))?;
+    if let Some((_, token)) = url.query_pairs().find(|(k, _)| k == "validationToken") {
+        return respond(&mut stream, "200 OK", &token).await;
+    }
+
+    let notifications: Notifications = match serde_json::from_slice(&body) {
+        Ok(notifications) => notifications,
+        Err(_) => return respond(&mut stream, "400 Bad Request", "").await,
+    };
+    // Graph only waits a few seconds, so acknowledge before anything else
+    respond(&mut stream, "202 Accepted", "").await?;
+    if notifications
+        .value
+        .iter()
+        .any(|n| n.client_state.as_deref() == Some(client_state))
+    {
+        let _ = tx.send(());
+    }
+    Ok(())
+}
+
+/// Reads the request head and a body of `Content-Length` bytes.
+pub(crate) async fn read_request(stream: &mut TcpStream) -> anyhow::Result<(String, Vec<u8>)> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 8192];
+    let head_end = loop {
+        if let Some(i) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break i + 4;
+        }
+        if buf.len() > MAX_REQUEST_SIZE {
+            anyhow::bail!("request head too large")
+        }
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            anyhow::bail!("connection closed mid-request")
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    };
+
+    let head = String::from_utf8_lossy(&buf[..head_end]).into_owned();
+    let content_length = head
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+    if content_length > MAX_REQUEST_SIZE {
+        anyhow::bail!("request body too large")
+    }
+
+    let mut body = buf.split_off(head_end);
+    while body.len() < content_length {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            anyhow::bail!("connection closed mid-request")
+        }
+        body.extend_from_slice(&chunk[..n]);
+    }
+    body.truncate(content_length);
+    Ok((head, body))
+}
+
+async fn respond(stream: &mut TcpStream, status: &str, body: &str) -> anyhow::Result<()> {
+    respond_with(stream, status, "text/plain", body).await
+}
+
+pub(crate) async fn respond_with(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &str,
+) -> anyhow::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    Ok(())
+}